@@ -0,0 +1,84 @@
+//! When an enemy faction is usually online: how many of their members are
+//! active in each hour of the week, in Torn City Time (UTC), averaged over
+//! every week their last actions were polled.
+#![warn(missing_docs)]
+
+use std::fmt::Write;
+use chrono::{Datelike, Timelike};
+use serde::Serialize;
+use crate::history::{ActivityHour, History, HistoryError};
+
+/// Day names, Monday first as the rows are
+pub const DAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Average members active in each hour of the week
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Heatmap {
+    /// Faction id
+    pub faction_id: u32,
+
+    /// One row per day, Monday first, of one cell per hour in TCT. `None`
+    /// for hours that have never been polled.
+    pub cells: [[Option<f64>; 24]; 7],
+
+    /// How many recorded hours went into each cell's average, laid out as
+    /// `cells`. An hour polled several times still counts once.
+    pub samples: [[u32; 24]; 7],
+}
+
+impl Heatmap {
+    /// Average the hours recorded for a faction into their hour of the week
+    pub fn build(faction_id: u32, hours: &[ActivityHour]) -> Heatmap {
+        let mut totals = [[0u32; 24]; 7];
+        let mut samples = [[0u32; 24]; 7];
+
+        for x in hours {
+            let day = x.hour.weekday().num_days_from_monday() as usize;
+            let hour = x.hour.hour() as usize;
+
+            totals[day][hour] += x.active;
+            samples[day][hour] += 1;
+        }
+
+        let mut cells = [[None; 24]; 7];
+        for day in 0..7 {
+            for hour in 0..24 {
+                if samples[day][hour] > 0 {
+                    cells[day][hour] = Some(totals[day][hour] as f64 / samples[day][hour] as f64);
+                }
+            }
+        }
+
+        Heatmap { faction_id, cells, samples }
+    }
+
+    /// Load what's been recorded for a faction
+    pub fn load(history: &History, faction_id: u32) -> Result<Heatmap, HistoryError> {
+        Ok(Heatmap::build(faction_id, &history.activity(faction_id)?))
+    }
+
+    /// The busiest hour's average, or 0 if nothing's been recorded
+    pub fn peak(&self) -> f64 {
+        self.cells.iter().flatten().flatten().copied().fold(0.0, f64::max)
+    }
+
+    /// Whether any hour has been polled
+    pub fn is_empty(&self) -> bool {
+        self.cells.iter().flatten().all(Option::is_none)
+    }
+
+    /// The heatmap as CSV, one row per hour that's been polled
+    pub fn to_csv(&self) -> String {
+        let mut csv = "faction,day,hour,active,samples\n".to_string();
+
+        for (day, row) in self.cells.iter().enumerate() {
+            for (hour, cell) in row.iter().enumerate() {
+                if let Some(active) = cell {
+                    writeln!(csv, "{},{},{},{},{}", self.faction_id, DAYS[day], hour, active, self.samples[day][hour]).unwrap();
+                }
+            }
+        }
+
+        csv
+    }
+}
\ No newline at end of file
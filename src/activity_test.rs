@@ -0,0 +1,58 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use crate::activity::Heatmap;
+use crate::store::LastAction;
+use crate::test_history::memory;
+
+/// Monday 1 January 2024, midnight TCT
+fn monday() -> DateTime<Utc> {
+    DateTime::from_timestamp(1_704_067_200, 0).unwrap()
+}
+
+fn at(days: i64, hours: i64, minutes: i64) -> DateTime<Utc> {
+    monday() + TimeDelta::days(days) + TimeDelta::hours(hours) + TimeDelta::minutes(minutes)
+}
+
+fn action(user_id: u32, at: DateTime<Utc>) -> LastAction {
+    LastAction { user_id, at }
+}
+
+#[test]
+fn polls_are_recorded() {
+    let history = memory();
+
+    assert!(history.activity_due(200, at(0, 9, 5)).unwrap());
+    assert_eq!(history.record_activity(200, &[action(1, at(0, 8, 50)), action(2, at(-3, 0, 0))], at(0, 9, 5)).unwrap(), 2);
+
+    // Polled again too soon, and nothing's changed for user 2
+    assert!(!history.activity_due(200, at(0, 9, 10)).unwrap());
+    assert!(history.activity_due(200, at(0, 9, 20)).unwrap());
+    assert_eq!(history.record_activity(200, &[action(1, at(0, 9, 15)), action(2, at(-3, 0, 0))], at(0, 9, 20)).unwrap(), 1);
+
+    history.record_activity(300, &[], at(0, 9, 30)).unwrap();
+    assert_eq!(history.activity_factions().unwrap(), [300, 200]);
+}
+
+#[test]
+fn hours_of_the_week_are_averaged() {
+    let history = memory();
+
+    // First Monday: user 2 on in the 9 o'clock hour, user 1 in the 10
+    history.record_activity(200, &[action(1, at(0, 8, 50)), action(2, at(0, 9, 2))], at(0, 9, 5)).unwrap();
+    history.record_activity(200, &[action(1, at(0, 10, 1)), action(2, at(0, 9, 30))], at(0, 10, 5)).unwrap();
+    history.record_activity(200, &[action(1, at(0, 10, 19)), action(2, at(0, 9, 30))], at(0, 10, 20)).unwrap();
+
+    // The next Monday: both on at 10
+    history.record_activity(200, &[action(1, at(7, 10, 9)), action(2, at(7, 10, 40))], at(7, 10, 50)).unwrap();
+
+    let heatmap = Heatmap::load(&history, 200).unwrap();
+
+    // User 1's 8:50 is before anything's known about the 8 o'clock hour
+    assert_eq!(heatmap.cells[0][8], None);
+    assert_eq!(heatmap.cells[0][9], Some(1.0));
+    assert_eq!(heatmap.cells[0][10], Some(1.5));
+    assert_eq!(heatmap.samples[0][10], 2);
+    assert_eq!(heatmap.cells[1][10], None);
+    assert_eq!(heatmap.peak(), 1.5);
+
+    assert_eq!(heatmap.to_csv(), "faction,day,hour,active,samples\n200,Mon,9,1,1\n200,Mon,10,1.5,2\n");
+}
\ No newline at end of file
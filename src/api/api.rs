@@ -1,42 +1,65 @@
-use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
-use chrono::DateTime;
+use std::sync::{Arc, LazyLock};
+use chrono::{DateTime, TimeDelta, Utc};
 use serde::{Deserialize, Serialize};
 use serde::de::DeserializeOwned;
-use torn_api::request::{ApiRequest, ApiResponse, IntoRequest};
-use torn_api::request::models::UserRequest;
-use crate::ExampleApp;
+use torn_api::request::{ApiRequest, IntoRequest};
+use crate::api::client::client;
+use crate::clock::ClockOffset;
 
-/// Player info response
-#[derive(Deserialize, Serialize, Debug,  PartialEq, Clone)]
-pub struct PlayerInfo {
-    pub name: String,
+/// Offset between the local clock and Torn's, as measured from API responses
+static SERVER_OFFSET: LazyLock<Arc<ClockOffset>> = LazyLock::new(Default::default);
 
-    /// Hospital timestamp, jail timestamp
-    pub states: HashMap<String, i64>,
+/// Get the shared server clock offset, which is updated on every API response
+pub fn server_offset() -> Arc<ClockOffset> {
+    SERVER_OFFSET.clone()
 }
 
-/// Type that can be either a number or a string
-#[derive(Deserialize, Serialize, Debug, Clone, PartialOrd, PartialEq)]
-#[serde(untagged)]
-pub enum NumOrString{
-    Num(i64),
-    String(String)
+/// Parse the HTTP `Date` header sent back by the API. It only has second
+/// resolution, so half a second is added to land in the middle of the second
+/// it was truncated from.
+pub fn parse_server_date(header: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(header)
+        .ok()
+        .map(|x| x.to_utc() + TimeDelta::milliseconds(500))
+}
+
+/// Details of an error returned by the API
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ApiErrorDetails{
+    /// Torn's error code
+    pub code: u16,
+
+    /// Description of the error
+    pub error: String,
 }
 
 /// Response for an access error
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct AccessErrorStructure{
-    pub error: HashMap<String, NumOrString>
+    pub error: ApiErrorDetails
 }
 
 /// Error returned when attempting to send a tornapi request
-#[derive(Deserialize, Serialize, Debug, Copy, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub enum GetInfoError{
     InvalidId,
     WrongKey,
-    Other(u8)
+    Other(u8),
+
+    /// Couldn't reach the API, or it timed out
+    Network,
+
+    /// The API sent back something that couldn't be decoded, with where and
+    /// why decoding failed
+    Decode(String),
+
+    /// Requests are paused after too many failures
+    Paused,
+
+    /// The request couldn't be turned into a URL
+    BadRequest,
 }
 
 // Make it usable as an error
@@ -46,101 +69,54 @@ impl Display for GetInfoError {
             GetInfoError::InvalidId => write!(f, "Invalid Id"),
             GetInfoError::WrongKey => write!(f, "Wrong Key"),
             GetInfoError::Other(x) => write!(f, "Other API error: {}", x),
+            GetInfoError::Network => write!(f, "Network error"),
+            GetInfoError::Decode(x) => write!(f, "Unexpected API response: {}", x),
+            GetInfoError::Paused => write!(f, "API degraded"),
+            GetInfoError::BadRequest => write!(f, "Invalid request"),
         }
     }
 }
 impl Error for GetInfoError {}
 
-/// Get the information for a player. Generic, and errors should be checked in
-/// calling code.
-async fn get_player_info<RJT: DeserializeOwned>(req: &ApiRequest, section: &str) -> Result<RJT, ()> {
-    let mut start = "https://api.torn.com/v2/".to_string();
-
-    start.push_str(section);
-    for i in req.parameters.iter().enumerate(){
-        start.push_str(format!("{}{}={}",
-                               if i.0 == 0 {'?'} else {'&'},
-                               i.1.0,
-                               i.1.1).as_str()
-        );
-    }
-
-    let ret = reqwest::get(&start).await.unwrap();
-
-    if let Ok(x) = ret.json::<RJT>().await {
-        Ok(x)
-    }
-    else{
-        Err(())
+impl GetInfoError {
+    /// Whether the key itself is the problem, rather than the request or
+    /// the API
+    pub fn is_key_error(&self) -> bool {
+        matches!(self, GetInfoError::WrongKey | GetInfoError::Other(1 | 10 | 13 | 16 | 18))
     }
-}
 
-impl ExampleApp {
-    /// Update the hospitalisation timestamp map
-    pub async fn update_hosp_time(&mut self, id: u32) -> Result<PlayerInfo, GetInfoError> {
-        // Make the request string
-        let req = UserRequest::builder()
-            .api_key_public(self.apikey.clone())
-            .id(id.to_string())
-            .build()
-            .into_request();
-
-        // Get the normal info
-        let resp = get_player_info::<PlayerInfo>(&req.1, "user").await;
-
-        // If that's what was sent, parse and collect
-        if let Ok(x) = resp {
-            let hosp_datetime = DateTime::from_timestamp(
-                x.states["hospital_timestamp"],
-                0
-            ).unwrap();
-
-            self.hosp_map.insert(x.name.clone(), hosp_datetime);
-            Ok(x.clone())
-        }
-        else{
-            // If it's actually an error, figure out what it is and propagate
-            if let Ok(x) = get_player_info::<AccessErrorStructure>(&req.1, "user").await{
-                match x.error["code"]{
-                    NumOrString::Num(code) => {match code{
-                        6 => Err(GetInfoError::InvalidId),
-                        2 => Err(GetInfoError::WrongKey),
-                        x => Err(GetInfoError::Other(x as u8))
-                    }}
-                    _ => panic!("Should not get here!")
-                }
-            }
-            // If it reaches here, there's something wrong
-            else{
-                panic!("Should not get here!");
-            }
+    /// Longer explanation of the error, for showing to the user
+    pub fn help_text(&self) -> &'static str {
+        match self{
+            GetInfoError::InvalidId => "One of the IDs being monitored doesn't exist.",
+            GetInfoError::WrongKey => "The API key is incorrect. Check that it was copied in full \
+                from the API keys section of your Torn settings.",
+            GetInfoError::Other(1) => "No API key has been entered.",
+            GetInfoError::Other(5) => "Too many requests have been made with this key. Wait a \
+                minute before trying again.",
+            GetInfoError::Other(8) => "Your IP address has been temporarily blocked by Torn.",
+            GetInfoError::Other(9) => "The Torn API is currently disabled.",
+            GetInfoError::Other(10) => "The key owner is in federal jail, so the key can't be used.",
+            GetInfoError::Other(13) => "The key owner hasn't been online for over 7 days.",
+            GetInfoError::Other(16) => "The key's access level is too low for this request.",
+            GetInfoError::Other(18) => "The key has been paused by its owner.",
+            GetInfoError::Other(_) => "The Torn API returned an unexpected error.",
+            GetInfoError::Network => "Couldn't reach the Torn API. Check your internet connection.",
+            GetInfoError::Decode(_) => "The Torn API sent back something torndkt doesn't understand. \
+                The API may have changed, in which case torndkt needs updating.",
+            GetInfoError::Paused => "The Torn API has failed several times in a row, so requests \
+                are paused for a short while.",
+            GetInfoError::BadRequest => "torndkt built a request the API can't accept.",
         }
     }
 }
 
-pub async fn run_request<R: DeserializeOwned + Clone>(request: &ApiRequest) -> Result<R, GetInfoError>{
-    // Get the normal info
-    let resp = get_player_info::<R>(request, "user").await;
+/// Run a request built by `torn_api`, against whatever path it was built for
+pub async fn run_request<R: DeserializeOwned>(request: &ApiRequest) -> Result<R, GetInfoError>{
+    client().run(request).await
+}
 
-    // If that's what was sent, parse and collect
-    if let Ok(x) = resp {
-        Ok(x.clone())
-    }
-    else{
-        // If it's actually an error, figure out what it is and propagate
-        if let Ok(x) = get_player_info::<AccessErrorStructure>(request, "user").await{
-            match x.error["code"]{
-                NumOrString::Num(code) => {match code{
-                    6 => Err(GetInfoError::InvalidId),
-                    2 => Err(GetInfoError::WrongKey),
-                    x => Err(GetInfoError::Other(x as u8))
-                }}
-                _ => unreachable!()
-            }
-        }
-        // If it reaches here, there's something wrong
-        else{
-            unreachable!()
-        }
-    }
+/// Run a `torn_api` request, decoding into the typed model that comes with it
+pub async fn run_model<T: IntoRequest>(request: T) -> Result<T::Response, GetInfoError>{
+    run_request(&request.into_request().1).await
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
-use std::collections::HashMap;
-use crate::api::api::{AccessErrorStructure, NumOrString};
+use chrono::{DateTime, TimeDelta};
+use crate::api::api::{parse_server_date, AccessErrorStructure, ApiErrorDetails, GetInfoError};
+use crate::api::key::{AccessLevel, KeyCapabilities, KeyInfo, Selection};
 
 #[test]
 fn error_parse() {
@@ -7,9 +8,60 @@ fn error_parse() {
 
     let returned = serde_json::from_str::<AccessErrorStructure>(test_1).unwrap();
     assert_eq!(returned, AccessErrorStructure{
-        error: HashMap::from([
-            ("error".to_string(), NumOrString::String("Incorrect ID".to_string())),
-            ("code".to_string(), NumOrString::Num(6)),
-        ])
+        error: ApiErrorDetails { code: 6, error: "Incorrect ID".to_string() }
     })
+}
+
+#[test]
+fn server_date_parse() {
+    let parsed = parse_server_date("Tue, 14 Nov 2023 22:13:20 GMT").unwrap();
+    let expected = DateTime::from_timestamp(1_700_000_000, 0).unwrap() + TimeDelta::milliseconds(500);
+
+    assert_eq!(parsed, expected);
+    assert_eq!(parse_server_date("not a date"), None);
+}
+
+#[test]
+fn key_info_parse() {
+    let test_1 = "{\"info\": {
+        \"selections\": {\"user\": [\"basic\"]},
+        \"user\": {\"id\": 1, \"faction_id\": null, \"company_id\": null},
+        \"access\": {\"level\": 3, \"type\": \"Limited Access\", \"faction\": false}
+    } }";
+
+    let returned = serde_json::from_str::<KeyInfo>(test_1).unwrap();
+    assert_eq!(returned.info.user.id, 1);
+    assert_eq!(returned.info.access.access_type, "Limited Access");
+    assert_eq!(AccessLevel::from_level(returned.info.access.level), Some(AccessLevel::Limited));
+    assert!(AccessLevel::Limited > AccessLevel::Public);
+}
+
+#[test]
+fn key_capabilities() {
+    let test_1 = "{\"info\": {
+        \"selections\": {\"user\": [\"basic\", \"profile\"], \"faction\": [\"basic\"]},
+        \"user\": {\"id\": 1, \"faction_id\": 2},
+        \"access\": {\"level\": 1, \"type\": \"Public Only\"}
+    } }";
+
+    let info = serde_json::from_str::<KeyInfo>(test_1).unwrap();
+    let caps = KeyCapabilities::from_info(&info.info).unwrap();
+
+    assert_eq!(caps.access, AccessLevel::Public);
+    assert_eq!(caps.faction_id, Some(2));
+    assert!(caps.allows(&Selection::new("user", "profile")));
+    assert!(!caps.allows(&Selection::new("faction", "chain")));
+    assert!(!caps.allows(&Selection::new("market", "basic")));
+    assert_eq!(
+        caps.missing(&[Selection::new("user", "basic"), Selection::new("faction", "chain")]),
+        vec![Selection::new("faction", "chain")]
+    );
+}
+
+#[test]
+fn unknown_access_level() {
+    let json = "{\"info\": {\"user\": {\"id\": 1}, \"access\": {\"level\": 9, \"type\": \"New\"}}}";
+    let info = serde_json::from_str::<KeyInfo>(json).unwrap();
+
+    assert!(matches!(KeyCapabilities::from_info(&info.info), Err(GetInfoError::Decode(x)) if x.contains("access level 9")));
 }
\ No newline at end of file
@@ -0,0 +1,220 @@
+//! In-memory cache of API responses, shared by everything that fetches users,
+//! factions or wars. Responses are kept for a time that depends on the
+//! selection, and requests for something that's already being fetched wait
+//! for that fetch rather than making their own.
+#![warn(missing_docs)]
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use chrono::{DateTime, TimeDelta, Utc};
+use futures::future::{BoxFuture, Shared};
+use futures::FutureExt;
+use crate::api::api::{server_offset, GetInfoError};
+use crate::api::key::Selection;
+use crate::clock::{Clock, ServerSyncedClock};
+
+/// User profiles, including status
+pub const USER_PROFILE: Selection = Selection::new("user", "profile");
+
+/// Faction members list, including their statuses
+pub const FACTION_MEMBERS: Selection = Selection::new("faction", "members");
+
+/// Faction's current chain
+pub const FACTION_CHAIN: Selection = Selection::new("faction", "chain");
+
+/// Wars a faction is currently in
+pub const FACTION_WARS: Selection = Selection::new("faction", "wars");
+
+/// Attacks made by and on the key owner's faction
+pub const FACTION_ATTACKS: Selection = Selection::new("faction", "attacks");
+
+/// How long anything without its own TTL is kept
+const DEFAULT_TTL: TimeDelta = TimeDelta::seconds(30);
+
+/// Cached response, type-erased so one cache can hold every kind of entity
+type Value = Arc<dyn Any + Send + Sync>;
+
+/// Fetch in progress, which any number of callers can wait on
+type Pending = Shared<BoxFuture<'static, Result<Value, GetInfoError>>>;
+
+/// What's cached: a selection for one id, fetched with one API key
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    /// What was fetched
+    pub selection: Selection,
+
+    /// Who it was fetched for (user id, faction id, etc)
+    pub id: u32,
+
+    /// Hash of the API key it was fetched with, so a result is never handed
+    /// out for a different (or revoked) key
+    pub apikey: u64,
+}
+
+impl CacheKey {
+    /// Shorthand constructor
+    pub fn new(selection: Selection, id: u32, apikey: &str) -> CacheKey {
+        let mut hasher = DefaultHasher::new();
+        apikey.hash(&mut hasher);
+
+        CacheKey { selection, id, apikey: hasher.finish() }
+    }
+}
+
+/// A cache slot
+enum Entry {
+    /// Fetched at the given time
+    Ready(Value, DateTime<Utc>),
+
+    /// Being fetched
+    Fetching(Pending),
+}
+
+/// Counts of how requests were served
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Served from the cache
+    pub hits: u64,
+
+    /// Had to be fetched
+    pub misses: u64,
+
+    /// Waited on a fetch that was already running
+    pub coalesced: u64,
+}
+
+impl CacheStats {
+    /// Fraction of requests that didn't need their own fetch
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses + self.coalesced;
+
+        if total == 0 {
+            0.0
+        }
+        else {
+            (self.hits + self.coalesced) as f64 / total as f64
+        }
+    }
+}
+
+/// The cache itself
+pub struct ApiCache {
+    /// Cached and in-flight requests
+    entries: Mutex<HashMap<CacheKey, Entry>>,
+
+    /// How long each selection is kept for
+    ttls: HashMap<Selection, TimeDelta>,
+
+    /// Time source for expiry
+    clock: Arc<dyn Clock>,
+
+    /// Counters for [`CacheStats`]
+    hits: AtomicU64,
+    misses: AtomicU64,
+    coalesced: AtomicU64,
+}
+
+impl ApiCache {
+    /// An empty cache with the default TTLs
+    pub fn new(clock: Arc<dyn Clock>) -> ApiCache {
+        let ttls = HashMap::from([
+            (USER_PROFILE, TimeDelta::seconds(15)),
+            (FACTION_MEMBERS, TimeDelta::seconds(15)),
+            (FACTION_CHAIN, TimeDelta::seconds(10)),
+            (FACTION_WARS, TimeDelta::seconds(30)),
+        ]);
+
+        ApiCache {
+            entries: Mutex::default(),
+            ttls,
+            clock,
+            hits: AtomicU64::default(),
+            misses: AtomicU64::default(),
+            coalesced: AtomicU64::default(),
+        }
+    }
+
+    /// How long a selection is kept for
+    pub fn ttl(&self, selection: Selection) -> TimeDelta {
+        self.ttls.get(&selection).copied().unwrap_or(DEFAULT_TTL)
+    }
+
+    /// Counts of how requests have been served so far
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Get `key` from the cache if it's fresh, wait for it if it's already
+    /// being fetched, or fetch it with `fetch` otherwise. Errors aren't cached.
+    pub async fn get_or_fetch<T, F, Fut>(&self, key: CacheKey, fetch: F) -> Result<T, GetInfoError>
+        where T: Clone + Send + Sync + 'static,
+              F: FnOnce() -> Fut,
+              Fut: Future<Output = Result<T, GetInfoError>> + Send + 'static
+    {
+        let pending = {
+            let mut entries = self.entries.lock().unwrap();
+
+            match entries.get(&key) {
+                Some(Entry::Ready(value, fetched)) if self.clock.now() - *fetched < self.ttl(key.selection) => {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(downcast(value));
+                }
+                Some(Entry::Fetching(pending)) => {
+                    self.coalesced.fetch_add(1, Ordering::Relaxed);
+                    pending.clone()
+                }
+                _ => {
+                    self.misses.fetch_add(1, Ordering::Relaxed);
+
+                    let pending = fetch()
+                        .map(|x| x.map(|x| Arc::new(x) as Value))
+                        .boxed()
+                        .shared();
+
+                    entries.insert(key, Entry::Fetching(pending.clone()));
+                    pending
+                }
+            }
+        };
+
+        let result = pending.clone().await;
+
+        // Whoever gets here first files the result
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(Entry::Fetching(x)) = entries.get(&key) && Shared::ptr_eq(x, &pending) {
+            match &result {
+                Ok(x) => entries.insert(key, Entry::Ready(x.clone(), self.clock.now())),
+                Err(_) => entries.remove(&key),
+            };
+        }
+        drop(entries);
+
+        result.map(|x| downcast(&x))
+    }
+}
+
+/// Get a cached value back out as the type it was stored as. Keys are only
+/// ever used with one type, so a mismatch is a bug.
+fn downcast<T: Clone + 'static>(value: &Value) -> T {
+    value.downcast_ref::<T>()
+        .expect("cache key used with more than one type")
+        .clone()
+}
+
+/// Cache shared by the whole app, expiring by Torn's clock
+static ENTITY_CACHE: LazyLock<ApiCache> = LazyLock::new(|| {
+    ApiCache::new(Arc::new(ServerSyncedClock::new(server_offset())))
+});
+
+/// Get the shared cache
+pub fn entity_cache() -> &'static ApiCache {
+    &ENTITY_CACHE
+}
\ No newline at end of file
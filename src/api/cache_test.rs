@@ -0,0 +1,101 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use chrono::{DateTime, TimeDelta, Utc};
+use futures::channel::oneshot;
+use futures::executor::block_on;
+use futures::{join, FutureExt};
+use crate::api::api::GetInfoError;
+use crate::api::cache::{ApiCache, CacheKey, CacheStats, FACTION_WARS, USER_PROFILE};
+use crate::clock::Clock;
+
+/// Clock that only moves when told to
+struct ManualClock(Mutex<DateTime<Utc>>);
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// A cache with a manual clock
+fn cache() -> (ApiCache, Arc<ManualClock>) {
+    let clock = Arc::new(ManualClock(Mutex::new(DateTime::from_timestamp(1_700_000_000, 0).unwrap())));
+    (ApiCache::new(clock.clone()), clock)
+}
+
+#[test]
+fn hits_until_expiry() {
+    let (cache, clock) = cache();
+    let key = CacheKey::new(USER_PROFILE, 1, "key");
+    let fetches = AtomicUsize::new(0);
+
+    let get = |value: u32| block_on(cache.get_or_fetch(key, || {
+        fetches.fetch_add(1, Ordering::Relaxed);
+        async move { Ok(value) }
+    }));
+
+    assert_eq!(get(1), Ok(1));
+    assert_eq!(get(2), Ok(1));
+
+    *clock.0.lock().unwrap() += cache.ttl(USER_PROFILE);
+    assert_eq!(get(3), Ok(3));
+
+    assert_eq!(fetches.load(Ordering::Relaxed), 2);
+    assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 2, coalesced: 0 });
+    assert!(cache.ttl(FACTION_WARS) > TimeDelta::zero());
+}
+
+#[test]
+fn errors_not_cached() {
+    let (cache, _) = cache();
+    let key = CacheKey::new(USER_PROFILE, 1, "key");
+
+    let failed: Result<u32, _> = block_on(cache.get_or_fetch(key, || async { Err(GetInfoError::Other(5)) }));
+    assert_eq!(failed, Err(GetInfoError::Other(5)));
+
+    assert_eq!(block_on(cache.get_or_fetch(key, || async { Ok(7u32) })), Ok(7));
+}
+
+#[test]
+fn concurrent_requests_coalesce() {
+    let (cache, _) = cache();
+    let key = CacheKey::new(USER_PROFILE, 1, "key");
+    let fetches = Arc::new(AtomicUsize::new(0));
+
+    // The fetch can't finish until both requests have been made
+    let (tx, rx) = oneshot::channel::<()>();
+    let rx = rx.shared();
+
+    let fetch = || {
+        let fetches = fetches.clone();
+        let rx = rx.clone();
+
+        move || async move {
+            fetches.fetch_add(1, Ordering::Relaxed);
+            let _ = rx.await;
+            Ok(42u32)
+        }
+    };
+
+    let (a, b, _) = block_on(async {
+        join!(
+            cache.get_or_fetch(key, fetch()),
+            cache.get_or_fetch(key, fetch()),
+            async { tx.send(()).unwrap() },
+        )
+    });
+
+    assert_eq!((a, b), (Ok(42), Ok(42)));
+    assert_eq!(fetches.load(Ordering::Relaxed), 1);
+    assert_eq!(cache.stats(), CacheStats { hits: 0, misses: 1, coalesced: 1 });
+}
+
+#[test]
+fn keys_dont_share_results() {
+    let (cache, _) = cache();
+    let get = |apikey: &str, value: u32| block_on(cache.get_or_fetch(CacheKey::new(USER_PROFILE, 1, apikey), || async move { Ok(value) }));
+
+    assert_eq!(get("first", 1), Ok(1));
+    assert_eq!(get("second", 2), Ok(2));
+    assert_eq!(get("first", 3), Ok(1));
+}
\ No newline at end of file
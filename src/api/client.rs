@@ -0,0 +1,397 @@
+//! HTTP side of the API layer: timeouts, retries with backoff for transient
+//! failures, and a circuit breaker that stops hammering the API when it's down
+#![warn(missing_docs)]
+
+use std::sync::{Arc, LazyLock, Mutex, RwLock};
+use std::time::Duration;
+use chrono::{DateTime, TimeDelta, Utc};
+use reqwest::Url;
+use reqwest::header::AUTHORIZATION;
+use serde::de::DeserializeOwned;
+use torn_api::request::ApiRequest;
+use crate::api::api::{parse_server_date, server_offset, AccessErrorStructure, GetInfoError};
+use crate::api::recording::{Exchange, Recorder, Replay};
+
+/// Where requests go by default
+pub const DEFAULT_BASE_URL: &str = "https://api.torn.com/v2/";
+
+/// Name of the query parameter `torn_api` puts the key in
+pub const KEY_PARAMETER: &str = "key";
+
+/// Torn error codes worth retrying: unknown error and backend error
+const TRANSIENT_CODES: &[u8] = &[0, 17];
+
+/// Settings for talking to the API
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiConfig {
+    /// Base URL that request paths are relative to. Changed to point at a fake
+    /// server in tests.
+    pub base_url: Url,
+
+    /// Send the API key in the `Authorization` header rather than the query
+    /// string, so it doesn't end up in proxy or server logs
+    pub key_in_header: bool,
+
+    /// How long a single request can take
+    pub timeout: Duration,
+
+    /// How many times a transient failure is retried
+    pub retries: u32,
+
+    /// Delay before the first retry. Doubles with each retry after that.
+    pub backoff: Duration,
+
+    /// Longest delay between retries
+    pub max_backoff: Duration,
+
+    /// Failed requests in a row before requests are paused
+    pub breaker_threshold: u32,
+
+    /// How long requests are paused for
+    pub breaker_cooldown: Duration,
+}
+
+impl Default for ApiConfig {
+    fn default() -> ApiConfig {
+        ApiConfig {
+            base_url: Url::parse(DEFAULT_BASE_URL).unwrap(),
+            key_in_header: false,
+            timeout: Duration::from_secs(10),
+            retries: 3,
+            backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+            breaker_threshold: 5,
+            breaker_cooldown: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Delay before retry number `attempt` (starting from 0). `jitter` is a random
+/// number from 0 to 1, which spreads the delay over its upper half so clients
+/// that failed together don't retry together.
+pub fn backoff(config: &ApiConfig, attempt: u32, jitter: f64) -> Duration {
+    let delay = config.backoff
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(config.max_backoff);
+
+    delay.mul_f64(0.5 + jitter.clamp(0.0, 1.0) / 2.0)
+}
+
+/// How the API has been behaving lately
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ApiHealth {
+    /// Last request worked
+    Ok,
+
+    /// Some requests in a row have failed
+    Degraded(u32),
+
+    /// Too many requests failed, so they're paused until the given time
+    Paused(DateTime<Utc>),
+}
+
+/// Circuit breaker state
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum BreakerState {
+    /// Letting requests through, with this many failures in a row so far
+    Closed(u32),
+
+    /// Not letting requests through until the given time. After that one
+    /// request is let through to test the water.
+    Open(DateTime<Utc>),
+
+    /// Testing the water with the request let through at the given time.
+    /// Nothing else is let through until it's done, and a failure reopens
+    /// the breaker. If it never reports back, another is let through after
+    /// the cooldown.
+    HalfOpen(DateTime<Utc>),
+}
+
+/// Stops requests for a while after too many failures in a row
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: TimeDelta,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    /// Open after `threshold` failures in a row, for `cooldown`
+    pub fn new(threshold: u32, cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            threshold: threshold.max(1),
+            cooldown: TimeDelta::from_std(cooldown).unwrap_or(TimeDelta::MAX),
+            state: Mutex::new(BreakerState::Closed(0)),
+        }
+    }
+
+    /// Whether a request can be made at `now`. Once the breaker has been open
+    /// for the cooldown, only the first caller is let through.
+    pub fn allow(&self, now: DateTime<Utc>) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        match *state {
+            BreakerState::Closed(_) => true,
+            BreakerState::Open(until) if now < until => false,
+            BreakerState::HalfOpen(since) if now < since + self.cooldown => false,
+            BreakerState::Open(_) | BreakerState::HalfOpen(_) => {
+                *state = BreakerState::HalfOpen(now);
+                true
+            }
+        }
+    }
+
+    /// Note a request that reached the API
+    pub fn success(&self) {
+        *self.state.lock().unwrap() = BreakerState::Closed(0);
+    }
+
+    /// Note a request that didn't, at `now`
+    pub fn failure(&self, now: DateTime<Utc>) {
+        let mut state = self.state.lock().unwrap();
+
+        *state = match *state {
+            BreakerState::Closed(x) if x + 1 < self.threshold => BreakerState::Closed(x + 1),
+            _ => BreakerState::Open(now + self.cooldown),
+        };
+    }
+
+    /// Current health, as seen at `now`
+    pub fn health(&self, now: DateTime<Utc>) -> ApiHealth {
+        match *self.state.lock().unwrap() {
+            BreakerState::Closed(0) => ApiHealth::Ok,
+            BreakerState::Closed(x) => ApiHealth::Degraded(x),
+            BreakerState::Open(until) if now < until => ApiHealth::Paused(until),
+            BreakerState::Open(_) | BreakerState::HalfOpen(_) => ApiHealth::Degraded(self.threshold),
+        }
+    }
+}
+
+/// Makes requests to the API
+pub struct ApiClient {
+    config: ApiConfig,
+    http: reqwest::Client,
+    breaker: CircuitBreaker,
+
+    /// Where responses are recorded to, if they are
+    recorder: Option<Recorder>,
+
+    /// Recording that's answering requests instead of the API
+    replay: Option<Replay>,
+}
+
+impl ApiClient {
+    /// Client using the given settings
+    pub fn new(mut config: ApiConfig) -> ApiClient {
+        // Without a trailing slash, joining paths would replace the last part
+        if !config.base_url.path().ends_with('/') {
+            let path = format!("{}/", config.base_url.path());
+            config.base_url.set_path(&path);
+        }
+
+        let http = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .expect("HTTP client couldn't be set up");
+
+        ApiClient {
+            breaker: CircuitBreaker::new(config.breaker_threshold, config.breaker_cooldown),
+            http,
+            config,
+            recorder: None,
+            replay: None,
+        }
+    }
+
+    /// Record every response to `recorder`
+    pub fn with_recorder(mut self, recorder: Recorder) -> ApiClient {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Answer requests from a recording rather than the API
+    pub fn with_replay(mut self, replay: Replay) -> ApiClient {
+        self.replay = Some(replay);
+        self
+    }
+
+    /// The recording being played back, if there is one
+    pub fn replay(&self) -> Option<&Replay> {
+        self.replay.as_ref()
+    }
+
+    /// How the API has been behaving lately
+    pub fn health(&self) -> ApiHealth {
+        self.breaker.health(Utc::now())
+    }
+
+    /// Run a request, against the path it was built for. Transient failures
+    /// are retried, and requests aren't made at all while the circuit breaker
+    /// is open.
+    pub async fn run<R: DeserializeOwned>(&self, request: &ApiRequest) -> Result<R, GetInfoError> {
+        // Nothing to retry when replaying, the recording has what it has
+        if let Some(replay) = &self.replay {
+            return match replay.find(request) {
+                Some(x) if x.status < 500 => parse_response(&x.body_bytes()),
+                _ => Err(GetInfoError::Network),
+            };
+        }
+
+        if !self.breaker.allow(Utc::now()) {
+            return Err(GetInfoError::Paused);
+        }
+
+        let mut attempt = 0;
+        loop {
+            let result = self.fetch(request).await;
+
+            match &result {
+                Err(x) if x.is_transient() && attempt < self.config.retries => {
+                    tokio::time::sleep(backoff(&self.config, attempt, fastrand::f64())).await;
+                    attempt += 1;
+                }
+                Err(x) if x.is_transient() => {
+                    self.breaker.failure(Utc::now());
+                    return result;
+                }
+                _ => {
+                    self.breaker.success();
+                    return result;
+                }
+            }
+        }
+    }
+
+    /// Work out the URL for a request, and the `Authorization` header if the
+    /// key is sent that way
+    pub fn prepare(&self, request: &ApiRequest) -> Result<(Url, Option<String>), GetInfoError> {
+        let mut url = self.config.base_url
+            .join(request.path.trim_start_matches('/'))
+            .map_err(|_| GetInfoError::BadRequest)?;
+
+        let mut auth = None;
+        let mut query = url.query_pairs_mut();
+
+        for (name, value) in &request.parameters {
+            if self.config.key_in_header && *name == KEY_PARAMETER {
+                auth = Some(format!("ApiKey {}", value));
+            }
+            else {
+                query.append_pair(name, value);
+            }
+        }
+
+        drop(query);
+
+        // Don't leave a dangling `?` when there were no parameters
+        if url.query() == Some("") {
+            url.set_query(None);
+        }
+
+        Ok((url, auth))
+    }
+
+    /// Make a single request
+    async fn fetch<R: DeserializeOwned>(&self, request: &ApiRequest) -> Result<R, GetInfoError> {
+        let (url, auth) = self.prepare(request)?;
+
+        let mut builder = self.http.get(url);
+        if let Some(auth) = auth {
+            builder = builder.header(AUTHORIZATION, auth);
+        }
+
+        let sent = Utc::now();
+        let resp = builder.send().await.map_err(|_| GetInfoError::Network)?;
+        let received = Utc::now();
+
+        // Use the server's timestamp to keep track of clock drift. The midpoint
+        // of the request is the best guess for when the server generated it.
+        let server_date = resp.headers()
+            .get(reqwest::header::DATE)
+            .and_then(|x| x.to_str().ok())
+            .and_then(parse_server_date);
+
+        if let Some(server_date) = server_date {
+            server_offset().observe(server_date, sent + (received - sent) / 2);
+        }
+
+        let status = resp.status();
+        let body = resp.bytes().await.map_err(|_| GetInfoError::Network)?;
+
+        if let Some(recorder) = &self.recorder {
+            let exchange = Exchange::new(request, received + server_offset().get(), status.as_u16(), server_date, &body);
+
+            if let Err(x) = recorder.record(&exchange) {
+                eprintln!("Couldn't record response: {}", x);
+            }
+        }
+
+        // Proxies in front of the API send error pages rather than JSON
+        if status.is_server_error() {
+            return Err(GetInfoError::Network);
+        }
+
+        parse_response(&body).map_err(|x| match x {
+            GetInfoError::Decode(x) => GetInfoError::Decode(format!("{}: {}", request.path, x)),
+            x => x,
+        })
+    }
+}
+
+/// Turn a response body into either the expected response or the API error
+/// it contains. Decode errors say which field was the problem.
+pub fn parse_response<R: DeserializeOwned>(body: &[u8]) -> Result<R, GetInfoError> {
+    let value: serde_json::Value = serde_json::from_slice(body)
+        .map_err(|x| GetInfoError::Decode(format!("not JSON ({})", x)))?;
+
+    if value.get("error").is_some() {
+        let error: AccessErrorStructure = decode(value)?;
+
+        return match error.error.code {
+            6 => Err(GetInfoError::InvalidId),
+            2 => Err(GetInfoError::WrongKey),
+            x => Err(GetInfoError::Other(x as u8)),
+        };
+    }
+
+    decode(value)
+}
+
+/// Decode a JSON value, keeping track of where in it any error happened
+fn decode<R: DeserializeOwned>(value: serde_json::Value) -> Result<R, GetInfoError> {
+    serde_path_to_error::deserialize(value).map_err(|x| {
+        let path = x.path().to_string();
+        let inner = x.into_inner();
+
+        GetInfoError::Decode(match path.as_str() {
+            "." => inner.to_string(),
+            path => format!("{} at {}", inner, path),
+        })
+    })
+}
+
+impl GetInfoError {
+    /// Whether the error might go away if the request is tried again
+    pub fn is_transient(&self) -> bool {
+        match self {
+            GetInfoError::Network => true,
+            GetInfoError::Other(x) => TRANSIENT_CODES.contains(x),
+            _ => false,
+        }
+    }
+}
+
+/// Client used by the whole app
+static CLIENT: LazyLock<RwLock<Arc<ApiClient>>> = LazyLock::new(|| {
+    RwLock::new(Arc::new(ApiClient::new(ApiConfig::default())))
+});
+
+/// Get the shared client
+pub fn client() -> Arc<ApiClient> {
+    CLIENT.read().unwrap().clone()
+}
+
+/// Replace the shared client
+pub fn install(client: ApiClient) {
+    *CLIENT.write().unwrap() = Arc::new(client);
+}
\ No newline at end of file
@@ -0,0 +1,254 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use chrono::{DateTime, TimeDelta};
+use reqwest::Url;
+use serde::Deserialize;
+use torn_api::models::FactionMembersResponse;
+use torn_api::request::ApiRequest;
+use crate::api::api::GetInfoError;
+use crate::api::client::{backoff, parse_response, ApiClient, ApiConfig, ApiHealth, CircuitBreaker};
+use crate::api::recording::{Recorder, Recording};
+
+/// Requests a fake server has had, as the raw request line and headers
+type Requests = Arc<Mutex<Vec<String>>>;
+
+/// Local HTTP server that sends back canned responses in order, repeating the
+/// last one. Returns its base URL and the requests it's had.
+fn fake_server(responses: &[(u16, &str)]) -> (Url, Requests) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+    let requests = Requests::default();
+
+    let responses = responses.iter().map(|(x, y)| (*x, y.to_string())).collect::<Vec<_>>();
+    let log = requests.clone();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { return };
+
+            // Only GETs are sent, so the request ends with the headers
+            let mut request = vec![];
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(x) => request.extend_from_slice(&buf[..x]),
+                }
+            }
+
+            let hit = {
+                let mut log = log.lock().unwrap();
+                log.push(String::from_utf8_lossy(&request).to_string());
+                log.len() - 1
+            };
+            let (status, body) = &responses[hit.min(responses.len() - 1)];
+
+            let _ = write!(stream,
+                "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status, body.len(), body);
+        }
+    });
+
+    (url, requests)
+}
+
+/// Settings for a fast test against `url`
+fn config(url: Url) -> ApiConfig {
+    ApiConfig {
+        base_url: url,
+        key_in_header: false,
+        timeout: Duration::from_secs(5),
+        retries: 2,
+        backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(5),
+        breaker_threshold: 2,
+        breaker_cooldown: Duration::from_secs(60),
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct Name {
+    name: String,
+}
+
+/// A request with no parameters
+fn request() -> ApiRequest {
+    ApiRequest { path: "/user".to_string(), parameters: vec![] }
+}
+
+/// Number of requests a fake server has had
+fn hits(requests: &Requests) -> usize {
+    requests.lock().unwrap().len()
+}
+
+#[tokio::test]
+async fn retries_transient_failures() {
+    let (url, requests) = fake_server(&[
+        (502, "<html>Bad gateway</html>"),
+        (200, r#"{"error": {"code": 17, "error": "Backend error"}}"#),
+        (200, r#"{"name": "Test"}"#),
+    ]);
+    let client = ApiClient::new(config(url));
+
+    let resp: Result<Name, _> = client.run(&request()).await;
+    assert_eq!(resp, Ok(Name { name: "Test".to_string() }));
+    assert_eq!(hits(&requests), 3);
+    assert_eq!(client.health(), ApiHealth::Ok);
+}
+
+#[tokio::test]
+async fn api_errors_not_retried() {
+    let (url, requests) = fake_server(&[(200, r#"{"error": {"code": 2, "error": "Incorrect key"}}"#)]);
+    let client = ApiClient::new(config(url));
+
+    let resp: Result<Name, _> = client.run(&request()).await;
+    assert_eq!(resp, Err(GetInfoError::WrongKey));
+    assert_eq!(hits(&requests), 1);
+}
+
+#[tokio::test]
+async fn breaker_pauses_requests() {
+    let (url, requests) = fake_server(&[(503, "")]);
+    let client = ApiClient::new(config(url));
+
+    for _ in 0..2 {
+        let resp: Result<Name, _> = client.run(&request()).await;
+        assert_eq!(resp, Err(GetInfoError::Network));
+    }
+
+    // Each run was tried three times, and then the breaker opened
+    assert_eq!(hits(&requests), 6);
+    assert!(matches!(client.health(), ApiHealth::Paused(_)));
+
+    let resp: Result<Name, _> = client.run(&request()).await;
+    assert_eq!(resp, Err(GetInfoError::Paused));
+    assert_eq!(hits(&requests), 6);
+}
+
+#[tokio::test]
+async fn unreachable_server() {
+    // Bind and drop to get a port nothing is listening on
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let url = Url::parse(&format!("http://127.0.0.1:{}", port)).unwrap();
+    let client = ApiClient::new(ApiConfig { retries: 0, ..config(url) });
+
+    let resp: Result<Name, _> = client.run(&request()).await;
+    assert_eq!(resp, Err(GetInfoError::Network));
+    assert_eq!(client.health(), ApiHealth::Degraded(1));
+}
+
+#[test]
+fn backoff_grows_and_caps() {
+    let config = ApiConfig::default();
+
+    assert_eq!(backoff(&config, 0, 1.0), Duration::from_millis(500));
+    assert_eq!(backoff(&config, 0, 0.0), Duration::from_millis(250));
+    assert_eq!(backoff(&config, 2, 1.0), Duration::from_secs(2));
+    assert_eq!(backoff(&config, 30, 1.0), config.max_backoff);
+}
+
+#[test]
+fn breaker_half_open() {
+    let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let breaker = CircuitBreaker::new(1, Duration::from_secs(60));
+
+    breaker.failure(now);
+    assert!(!breaker.allow(now + TimeDelta::seconds(59)));
+    assert!(breaker.allow(now + TimeDelta::seconds(60)));
+
+    // Only one request tests the water
+    assert!(!breaker.allow(now + TimeDelta::seconds(60)));
+    assert!(!breaker.allow(now + TimeDelta::seconds(90)));
+
+    // A failure while testing the water reopens it straight away
+    breaker.failure(now + TimeDelta::seconds(60));
+    assert!(!breaker.allow(now + TimeDelta::seconds(61)));
+
+    // A test that never reports back doesn't block requests forever
+    assert!(breaker.allow(now + TimeDelta::seconds(120)));
+    assert!(!breaker.allow(now + TimeDelta::seconds(121)));
+    assert!(breaker.allow(now + TimeDelta::seconds(180)));
+
+    breaker.success();
+    assert_eq!(breaker.health(now), ApiHealth::Ok);
+}
+
+#[test]
+fn parse_responses() {
+    assert_eq!(parse_response::<Name>(br#"{"name": "Test"}"#), Ok(Name { name: "Test".to_string() }));
+    assert_eq!(parse_response::<Name>(br#"{"error": {"code": 6, "error": "Incorrect ID"}}"#), Err(GetInfoError::InvalidId));
+    assert_eq!(parse_response::<Name>(br#"{"error": {"code": 5, "error": "Too many requests"}}"#), Err(GetInfoError::Other(5)));
+    assert!(matches!(parse_response::<Name>(b"<html></html>"), Err(GetInfoError::Decode(_))));
+    assert!(matches!(parse_response::<Name>(br#"{"other": 1}"#), Err(GetInfoError::Decode(_))));
+}
+
+#[test]
+fn decode_errors_name_the_field() {
+    let body = include_str!("fixtures/faction_members.json").replacen("\"Hospital\"", "7", 1);
+
+    let Err(GetInfoError::Decode(x)) = parse_response::<FactionMembersResponse>(body.as_bytes()) else {
+        panic!("schema change wasn't reported");
+    };
+    assert!(x.contains("members[0].status.state"), "{}", x);
+}
+
+#[test]
+fn urls_follow_request_path() {
+    let client = ApiClient::new(config(Url::parse("https://example.com/api/v2").unwrap()));
+    let members = ApiRequest {
+        path: "/faction/123/members".to_string(),
+        parameters: vec![("comment", "a&b c".to_string()), ("key", "abc=".to_string())],
+    };
+
+    let (url, auth) = client.prepare(&members).unwrap();
+    assert_eq!(url.as_str(), "https://example.com/api/v2/faction/123/members?comment=a%26b+c&key=abc%3D");
+    assert_eq!(auth, None);
+
+    let (url, _) = client.prepare(&request()).unwrap();
+    assert_eq!(url.as_str(), "https://example.com/api/v2/user");
+}
+
+#[tokio::test]
+async fn key_in_header() {
+    let (url, requests) = fake_server(&[(200, r#"{"name": "Test"}"#)]);
+    let client = ApiClient::new(ApiConfig { key_in_header: true, ..config(url) });
+    let request = ApiRequest {
+        path: "/user".to_string(),
+        parameters: vec![("id", "1".to_string()), ("key", "secret".to_string())],
+    };
+
+    let resp: Result<Name, _> = client.run(&request).await;
+    assert!(resp.is_ok());
+
+    let sent = requests.lock().unwrap()[0].to_lowercase();
+    assert!(sent.starts_with("get /user?id=1 "));
+    assert!(sent.contains("authorization: apikey secret\r\n"));
+}
+
+#[tokio::test]
+async fn records_without_key() {
+    let path = std::env::temp_dir().join(format!("torndkt_test_record_{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let (url, _) = fake_server(&[(502, "<html>Bad gateway</html>"), (200, r#"{"name": "Test"}"#)]);
+    let client = ApiClient::new(config(url)).with_recorder(Recorder::create(&path).unwrap());
+    let request = ApiRequest {
+        path: "/user".to_string(),
+        parameters: vec![("key", "secret".to_string())],
+    };
+
+    let resp: Result<Name, _> = client.run(&request).await;
+    assert!(resp.is_ok());
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert!(!contents.contains("secret"));
+
+    // Failed attempts are kept too, as they're what needs diagnosing
+    let recording = Recording::parse(&contents).unwrap();
+    let statuses = recording.exchanges.iter().map(|x| x.status).collect::<Vec<_>>();
+    assert_eq!(statuses, vec![502, 200]);
+    assert_eq!(recording.exchanges[1].body, serde_json::json!({"name": "Test"}));
+}
\ No newline at end of file
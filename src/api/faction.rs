@@ -0,0 +1,62 @@
+//! Faction lookups
+#![warn(missing_docs)]
+
+use chrono::{DateTime, Utc};
+use torn_api::models::{Attack, FactionId, FactionMember, FactionOngoingChain, FactionRankedWar};
+use torn_api::parameters::{ApiFrom, ApiLimit100, ApiSortDesc};
+use torn_api::request::models::{FactionAttacksRequest, FactionChainForIdRequest, FactionMembersForIdRequest, FactionWarsForIdRequest};
+use crate::api::api::{run_model, GetInfoError};
+use crate::api::cache::{entity_cache, CacheKey, FACTION_CHAIN, FACTION_MEMBERS, FACTION_WARS};
+
+/// Get everyone in a faction. Served from the cache if it was fetched
+/// recently.
+pub async fn faction_members(apikey: &str, faction_id: u32) -> Result<Vec<FactionMember>, GetInfoError> {
+    let req = FactionMembersForIdRequest::builder(FactionId(faction_id as i32))
+        .api_key_public(apikey)
+        .build();
+
+    entity_cache()
+        .get_or_fetch(CacheKey::new(FACTION_MEMBERS, faction_id, apikey), || async move { Ok(run_model(req).await?.members) })
+        .await
+}
+
+/// Get a faction's current chain. Served from the cache if it was fetched
+/// recently.
+pub async fn faction_chain(apikey: &str, faction_id: u32) -> Result<FactionOngoingChain, GetInfoError> {
+    let req = FactionChainForIdRequest::builder(FactionId(faction_id as i32))
+        .api_key_public(apikey)
+        .build();
+
+    entity_cache()
+        .get_or_fetch(CacheKey::new(FACTION_CHAIN, faction_id, apikey), || async move { Ok(run_model(req).await?.chain) })
+        .await
+}
+
+/// Get the ranked war a faction is in, if any. Served from the cache if it was
+/// fetched recently.
+pub async fn faction_ranked_war(apikey: &str, faction_id: u32) -> Result<Option<FactionRankedWar>, GetInfoError> {
+    let req = FactionWarsForIdRequest::builder(FactionId(faction_id as i32))
+        .api_key_public(apikey)
+        .build();
+
+    entity_cache()
+        .get_or_fetch(CacheKey::new(FACTION_WARS, faction_id, apikey), || async move { Ok(run_model(req).await?.wars.ranked) })
+        .await
+}
+
+/// Most attacks the API returns at once
+pub const ATTACKS_PAGE: usize = 100;
+
+/// Get attacks made by and on the key owner's faction, oldest first, starting
+/// at `from`. At most [`ATTACKS_PAGE`] are returned, so fetch again from the
+/// last one's end to get more. Not cached, as it's only fetched to be saved.
+pub async fn faction_attacks(apikey: &str, from: Option<DateTime<Utc>>) -> Result<Vec<Attack>, GetInfoError> {
+    let req = FactionAttacksRequest::builder()
+        .maybe_api_from(from.map(|x| ApiFrom::new(x.timestamp() as i32)))
+        .api_limit100(ApiLimit100::new(ATTACKS_PAGE as i32).unwrap())
+        .api_sort_desc(ApiSortDesc::Asc)
+        .api_key_limited(apikey)
+        .build();
+
+    Ok(run_model(req).await?.attacks)
+}
\ No newline at end of file
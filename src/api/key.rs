@@ -0,0 +1,218 @@
+//! API key introspection
+#![warn(missing_docs)]
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::sync::{LazyLock, Mutex};
+use serde::{Deserialize, Serialize};
+use torn_api::request::{ApiRequest, IntoRequest};
+use torn_api::request::models::KeyInfoRequest;
+use crate::api::api::{run_request, GetInfoError};
+use crate::api::user::user_profile;
+
+/// Access level of an API key, as set by the key owner when creating it. Each
+/// level can do everything the levels below it can.
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AccessLevel {
+    /// Public data only
+    Public,
+    /// Public data, plus some of the owner's own data
+    Minimal,
+    /// Most data, including faction attacks
+    Limited,
+    /// Everything
+    Full,
+}
+
+impl AccessLevel {
+    /// Convert from the numeric level the API reports
+    pub fn from_level(level: u8) -> Option<AccessLevel> {
+        match level {
+            1 => Some(AccessLevel::Public),
+            2 => Some(AccessLevel::Minimal),
+            3 => Some(AccessLevel::Limited),
+            4 => Some(AccessLevel::Full),
+            _ => None,
+        }
+    }
+}
+
+impl Display for AccessLevel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccessLevel::Public => write!(f, "Public"),
+            AccessLevel::Minimal => write!(f, "Minimal"),
+            AccessLevel::Limited => write!(f, "Limited"),
+            AccessLevel::Full => write!(f, "Full"),
+        }
+    }
+}
+
+/// Key owner, as given in the key info response
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct KeyOwner {
+    /// Owner's user id
+    pub id: u32,
+
+    /// Owner's faction, if they're in one
+    pub faction_id: Option<u32>,
+}
+
+/// Key access details, as given in the key info response
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct KeyAccess {
+    /// Numeric access level, 1 (public) to 4 (full)
+    pub level: u8,
+
+    /// Human readable access type, e.g. "Limited Access" or "Custom"
+    #[serde(rename = "type")]
+    pub access_type: String,
+}
+
+/// Body of the key info response
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct KeyInfoDetails {
+    /// Selections the key can use, keyed by section (`user`, `faction`, etc)
+    #[serde(default)]
+    pub selections: HashMap<String, Vec<String>>,
+
+    /// Who the key belongs to
+    pub user: KeyOwner,
+
+    /// What the key can access
+    pub access: KeyAccess,
+}
+
+/// Key info response
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct KeyInfo {
+    /// The actual info
+    pub info: KeyInfoDetails,
+}
+
+/// A single API selection, e.g. `faction/chain`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Selection {
+    /// API section, e.g. `user` or `faction`
+    pub section: &'static str,
+
+    /// Selection within the section
+    pub name: &'static str,
+}
+
+impl Selection {
+    /// Shorthand constructor
+    pub const fn new(section: &'static str, name: &'static str) -> Selection {
+        Selection { section, name }
+    }
+}
+
+impl Display for Selection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.section, self.name)
+    }
+}
+
+/// What a key is allowed to do
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyCapabilities {
+    /// Access level of the key
+    pub access: AccessLevel,
+
+    /// Access type as reported by the API, e.g. "Limited Access" or "Custom"
+    pub access_type: String,
+
+    /// Available selections, keyed by section
+    pub selections: HashMap<String, HashSet<String>>,
+
+    /// Faction of the key's owner, if they're in one
+    pub faction_id: Option<u32>,
+}
+
+impl KeyCapabilities {
+    /// Build from a key info response. An access level we don't know about
+    /// is a decode error.
+    pub fn from_info(info: &KeyInfoDetails) -> Result<KeyCapabilities, GetInfoError> {
+        let access = AccessLevel::from_level(info.access.level)
+            .ok_or_else(|| GetInfoError::Decode(format!("unknown access level {} at info.access.level", info.access.level)))?;
+
+        Ok(KeyCapabilities {
+            access,
+            access_type: info.access.access_type.clone(),
+            selections: info.selections.iter()
+                .map(|(section, names)| (section.clone(), names.iter().cloned().collect()))
+                .collect(),
+            faction_id: info.user.faction_id,
+        })
+    }
+
+    /// Whether the key can use a selection
+    pub fn allows(&self, selection: &Selection) -> bool {
+        self.selections
+            .get(selection.section)
+            .is_some_and(|x| x.contains(selection.name))
+    }
+
+    /// The selections out of `required` that the key can't use
+    pub fn missing(&self, required: &[Selection]) -> Vec<Selection> {
+        required.iter()
+            .filter(|x| !self.allows(x))
+            .copied()
+            .collect()
+    }
+}
+
+/// Key capabilities that have already been looked up, keyed by API key
+static CAPABILITY_CACHE: LazyLock<Mutex<HashMap<String, KeyCapabilities>>> = LazyLock::new(Default::default);
+
+/// Get what a key can do. Only hits the API the first time for each key.
+pub async fn key_capabilities(apikey: &str) -> Result<KeyCapabilities, GetInfoError> {
+    if let Some(x) = CAPABILITY_CACHE.lock().unwrap().get(apikey) {
+        return Ok(x.clone());
+    }
+
+    let info = fetch_key_info(apikey).await?;
+    let caps = KeyCapabilities::from_info(&info.info)?;
+
+    CAPABILITY_CACHE.lock().unwrap().insert(apikey.to_string(), caps.clone());
+    Ok(caps)
+}
+
+/// Get the raw key info response
+async fn fetch_key_info(apikey: &str) -> Result<KeyInfo, GetInfoError> {
+    let req: ApiRequest = KeyInfoRequest::builder()
+        .api_key_public(apikey)
+        .build()
+        .into_request().1;
+
+    run_request(&req).await
+}
+
+/// Result of testing an API key
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyReport {
+    /// Owner's username
+    pub owner_name: String,
+
+    /// Owner's user id
+    pub owner_id: u32,
+
+    /// What the key can do
+    pub capabilities: KeyCapabilities,
+}
+
+/// Check a key against the key info endpoint, and look up who owns it. Always
+/// hits the API, and refreshes the capability cache while it's at it.
+pub async fn test_key(apikey: &str) -> Result<KeyReport, GetInfoError> {
+    let key_info = fetch_key_info(apikey).await?;
+    let capabilities = KeyCapabilities::from_info(&key_info.info)?;
+    CAPABILITY_CACHE.lock().unwrap().insert(apikey.to_string(), capabilities.clone());
+
+    let owner = user_profile(apikey, key_info.info.user.id).await?;
+
+    Ok(KeyReport {
+        owner_name: owner.name,
+        owner_id: key_info.info.user.id,
+        capabilities,
+    })
+}
\ No newline at end of file
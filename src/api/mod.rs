@@ -3,7 +3,35 @@
 #![warn(missing_docs)]
 
 /// tornapi request module
+#[allow(clippy::module_inception)]
 pub mod api;
 
+/// API key introspection
+pub mod key;
+
+/// Faction lookups
+pub mod faction;
+
+/// User lookups
+pub mod user;
+
+/// Response cache
+pub mod cache;
+
+/// HTTP client with retries
+pub mod client;
+
+/// Recording and replaying API traffic
+pub mod recording;
+
+#[cfg(test)]
+mod api_test;
+
+#[cfg(test)]
+mod cache_test;
+
+#[cfg(test)]
+mod client_test;
+
 #[cfg(test)]
-mod api_test;
\ No newline at end of file
+mod recording_test;
\ No newline at end of file
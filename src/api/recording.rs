@@ -0,0 +1,240 @@
+//! Recording API traffic to a file, and playing it back later. Recordings
+//! are JSON lines, one request and response per line, with the API key left
+//! out. They can be replayed through the app to see what it showed at the
+//! time, or loaded in tests as fixtures.
+#![warn(missing_docs)]
+
+use std::fmt::{Display, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use torn_api::request::ApiRequest;
+use crate::api::client::KEY_PARAMETER;
+use crate::clock::Clock;
+
+/// One request and the response it got
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Exchange {
+    /// When the response arrived, by Torn's clock
+    pub at: DateTime<Utc>,
+
+    /// Request path, e.g. `/user/1/profile`
+    pub path: String,
+
+    /// Query parameters, without the key
+    pub query: Vec<(String, String)>,
+
+    /// HTTP status code
+    pub status: u16,
+
+    /// Server time from the `Date` header, if there was one
+    pub server_date: Option<DateTime<Utc>>,
+
+    /// Response body. Kept as a string if it wasn't JSON.
+    pub body: serde_json::Value,
+}
+
+impl Exchange {
+    /// Record a response to `request`
+    pub fn new(request: &ApiRequest, at: DateTime<Utc>, status: u16, server_date: Option<DateTime<Utc>>, body: &[u8]) -> Exchange {
+        let body = serde_json::from_slice(body)
+            .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(body).into_owned()));
+
+        Exchange {
+            at,
+            path: request.path.clone(),
+            query: redact(request),
+            status,
+            server_date,
+            body,
+        }
+    }
+
+    /// The response body as it was sent
+    pub fn body_bytes(&self) -> Vec<u8> {
+        match &self.body {
+            serde_json::Value::String(x) => x.clone().into_bytes(),
+            x => serde_json::to_vec(x).unwrap(),
+        }
+    }
+}
+
+/// A request's query parameters, minus the key
+pub fn redact(request: &ApiRequest) -> Vec<(String, String)> {
+    request.parameters.iter()
+        .filter(|(name, _)| *name != KEY_PARAMETER)
+        .map(|(name, value)| (name.to_string(), value.clone()))
+        .collect()
+}
+
+/// Reasons a recording couldn't be written or read
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordingError {
+    /// Couldn't open, read or write the file
+    Io(String),
+    /// A line isn't a recorded exchange
+    Invalid(usize, String),
+    /// Nothing was recorded
+    Empty,
+}
+
+impl Display for RecordingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordingError::Io(x) => write!(f, "File error: {}", x),
+            RecordingError::Invalid(line, x) => write!(f, "Line {} isn't a recorded request: {}", line, x),
+            RecordingError::Empty => write!(f, "Recording is empty"),
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+/// Appends exchanges to a recording file
+#[derive(Debug)]
+pub struct Recorder {
+    file: Mutex<BufWriter<File>>,
+}
+
+impl Recorder {
+    /// Record to `path`, adding to the end if it already exists
+    pub fn create(path: &Path) -> Result<Recorder, RecordingError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|x| RecordingError::Io(x.to_string()))?;
+
+        Ok(Recorder { file: Mutex::new(BufWriter::new(file)) })
+    }
+
+    /// Write an exchange. Flushed straight away so a crash doesn't lose the
+    /// end of the session.
+    pub fn record(&self, exchange: &Exchange) -> Result<(), RecordingError> {
+        let mut file = self.file.lock().unwrap();
+
+        serde_json::to_writer(&mut *file, exchange)
+            .map_err(|x| RecordingError::Io(x.to_string()))?;
+        writeln!(file)
+            .and_then(|_| file.flush())
+            .map_err(|x| RecordingError::Io(x.to_string()))
+    }
+}
+
+/// A recorded session
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    /// Exchanges, oldest first
+    pub exchanges: Vec<Exchange>,
+}
+
+impl Recording {
+    /// Parse a recording from the contents of a file
+    pub fn parse(input: &str) -> Result<Recording, RecordingError> {
+        let mut exchanges = input.lines()
+            .enumerate()
+            .filter(|(_, x)| !x.trim().is_empty())
+            .map(|(i, x)| serde_json::from_str::<Exchange>(x).map_err(|e| RecordingError::Invalid(i + 1, e.to_string())))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if exchanges.is_empty() {
+            return Err(RecordingError::Empty);
+        }
+
+        exchanges.sort_by_key(|x| x.at);
+        Ok(Recording { exchanges })
+    }
+
+    /// Load a recording file
+    pub fn load(path: &Path) -> Result<Recording, RecordingError> {
+        let input = std::fs::read_to_string(path).map_err(|x| RecordingError::Io(x.to_string()))?;
+        Recording::parse(&input)
+    }
+
+    /// When the first response arrived
+    pub fn start(&self) -> DateTime<Utc> {
+        self.exchanges[0].at
+    }
+
+    /// The response to `request` as it stood at `at`: the latest one received
+    /// by then, or the first one if it hadn't been made yet
+    pub fn find(&self, request: &ApiRequest, at: DateTime<Utc>) -> Option<&Exchange> {
+        let query = redact(request);
+        let mut matching = self.exchanges.iter()
+            .filter(|x| x.path == request.path && x.query == query)
+            .peekable();
+
+        let first = *matching.peek()?;
+        Some(matching.take_while(|x| x.at <= at).last().unwrap_or(first))
+    }
+}
+
+/// Time during a replay. Starts at the beginning of the recording and runs at
+/// `speed` times real time.
+#[derive(Clone)]
+pub struct ReplayClock {
+    start: DateTime<Utc>,
+    started: DateTime<Utc>,
+    speed: f64,
+    local: Arc<dyn Clock>,
+}
+
+impl ReplayClock {
+    /// Clock starting at `start` now, by the `local` clock
+    pub fn new(start: DateTime<Utc>, speed: f64, local: Arc<dyn Clock>) -> ReplayClock {
+        ReplayClock {
+            start,
+            started: local.now(),
+            speed: speed.max(0.0),
+            local,
+        }
+    }
+
+    /// How fast the replay runs
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+}
+
+impl std::fmt::Debug for ReplayClock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReplayClock")
+            .field("start", &self.start)
+            .field("started", &self.started)
+            .field("speed", &self.speed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Clock for ReplayClock {
+    fn now(&self) -> DateTime<Utc> {
+        let elapsed = (self.local.now() - self.started).num_milliseconds() as f64 * self.speed;
+        self.start + TimeDelta::milliseconds(elapsed as i64)
+    }
+}
+
+/// A recording being played back in place of the API
+#[derive(Debug)]
+pub struct Replay {
+    /// What's being played back
+    pub recording: Recording,
+
+    /// Where the replay is up to
+    pub clock: Arc<ReplayClock>,
+}
+
+impl Replay {
+    /// Start playing `recording` back at `speed` times real time
+    pub fn new(recording: Recording, speed: f64, local: Arc<dyn Clock>) -> Replay {
+        let clock = Arc::new(ReplayClock::new(recording.start(), speed, local));
+        Replay { recording, clock }
+    }
+
+    /// The recorded response to `request`, as of the current replay time
+    pub fn find(&self, request: &ApiRequest) -> Option<&Exchange> {
+        self.recording.find(request, self.clock.now())
+    }
+}
\ No newline at end of file
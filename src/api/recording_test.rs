@@ -0,0 +1,98 @@
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, TimeDelta, Utc};
+use torn_api::models::FactionOngoingChainResponse;
+use torn_api::request::ApiRequest;
+use crate::api::api::GetInfoError;
+use crate::api::client::{ApiClient, ApiConfig};
+use crate::api::recording::{redact, Recording, RecordingError, Replay};
+use crate::clock::Clock;
+
+/// Clock that only moves when told to
+struct ManualClock(Mutex<DateTime<Utc>>);
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// Session with a chain checked three times, the last time failing
+fn session() -> Recording {
+    Recording::parse(include_str!("fixtures/session.jsonl")).unwrap()
+}
+
+/// Request for the chain in the session
+fn chain_request() -> ApiRequest {
+    ApiRequest {
+        path: "/faction/100/chain".to_string(),
+        parameters: vec![("key", "secret".to_string())],
+    }
+}
+
+#[test]
+fn key_is_redacted() {
+    let request = ApiRequest {
+        path: "/user/1/profile".to_string(),
+        parameters: vec![("key", "secret".to_string()), ("comment", "torndkt".to_string())],
+    };
+
+    assert_eq!(redact(&request), vec![("comment".to_string(), "torndkt".to_string())]);
+}
+
+#[test]
+fn finds_response_as_of_time() {
+    let recording = session();
+    let start = recording.start();
+    let request = chain_request();
+
+    let current = |at| recording.find(&request, at).unwrap().body["chain"]["current"].clone();
+
+    // Before anything was fetched, the first response stands in
+    assert_eq!(current(start - TimeDelta::seconds(10)), 120);
+    assert_eq!(current(start + TimeDelta::seconds(59)), 120);
+    assert_eq!(current(start + TimeDelta::seconds(60)), 121);
+
+    let other = ApiRequest { path: "/faction/200/chain".to_string(), parameters: vec![] };
+    assert_eq!(recording.find(&other, start), None);
+}
+
+#[test]
+fn bad_recordings() {
+    assert_eq!(Recording::parse("\n\n"), Err(RecordingError::Empty));
+    assert!(matches!(Recording::parse("{}\n"), Err(RecordingError::Invalid(1, _))));
+}
+
+#[tokio::test]
+async fn replays_through_client() {
+    let recording = session();
+    let start = recording.start();
+
+    let local = Arc::new(ManualClock(Mutex::new(DateTime::from_timestamp(1_800_000_000, 0).unwrap())));
+    let client = ApiClient::new(ApiConfig::default())
+        .with_replay(Replay::new(recording, 10.0, local.clone()));
+
+    // The replay starts at the start of the recording, whatever the local
+    // time is
+    let replay = client.replay().unwrap();
+    assert_eq!(replay.clock.now(), start);
+
+    let response: FactionOngoingChainResponse = client.run(&chain_request()).await.unwrap();
+    assert_eq!(response.chain.current, 120);
+
+    let profile = ApiRequest { path: "/user/2/profile".to_string(), parameters: vec![("comment", "torndkt".to_string())] };
+    assert_eq!(client.run::<serde_json::Value>(&profile).await, Err(GetInfoError::InvalidId));
+
+    let unrecorded = ApiRequest { path: "/user/3/profile".to_string(), parameters: vec![] };
+    assert_eq!(client.run::<serde_json::Value>(&unrecorded).await, Err(GetInfoError::Network));
+
+    // 6 seconds at 10x is a minute into the recording
+    *local.0.lock().unwrap() += TimeDelta::seconds(6);
+    assert_eq!(replay.clock.now(), start + TimeDelta::seconds(60));
+
+    let response: FactionOngoingChainResponse = client.run(&chain_request()).await.unwrap();
+    assert_eq!(response.chain.current, 121);
+
+    // Errors that were recorded are replayed too
+    *local.0.lock().unwrap() += TimeDelta::seconds(6);
+    assert_eq!(client.run::<FactionOngoingChainResponse>(&chain_request()).await, Err(GetInfoError::Network));
+}
\ No newline at end of file
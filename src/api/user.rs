@@ -0,0 +1,20 @@
+//! User lookups
+#![warn(missing_docs)]
+
+use torn_api::models::UserId;
+use torn_api::models::user_profile_response::Profile;
+use torn_api::request::models::UserProfileForIdRequest;
+use crate::api::api::{run_model, GetInfoError};
+use crate::api::cache::{entity_cache, CacheKey, USER_PROFILE};
+
+/// Get a user's profile, including their status. Served from the cache if it
+/// was fetched recently.
+pub async fn user_profile(apikey: &str, id: u32) -> Result<Profile, GetInfoError> {
+    let req = UserProfileForIdRequest::builder(UserId(id as i32))
+        .api_key_public(apikey)
+        .build();
+
+    entity_cache()
+        .get_or_fetch(CacheKey::new(USER_PROFILE, id, apikey), || async move { Ok(run_model(req).await?.profile) })
+        .await
+}
\ No newline at end of file
@@ -0,0 +1,176 @@
+//! Adding lots of targets at once, from pasted lists of ids, profile links,
+//! names or whole factions
+#![warn(missing_docs)]
+
+use std::collections::HashMap;
+use uniquevec::UniqueVec;
+use crate::store::User;
+
+/// Longest name Torn allows
+const MAX_NAME_LENGTH: usize = 16;
+
+/// One thing pasted into the bulk add box
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BulkEntry {
+    /// A user id, typed in or taken from a profile link
+    User(u32),
+
+    /// A faction, standing in for all of its members
+    Faction(u32),
+
+    /// A username, which needs looking up
+    Name(String),
+
+    /// Something that couldn't be made sense of
+    Invalid(String),
+}
+
+/// Split pasted text into entries. Ids, names and links can be separated by
+/// commas, semicolons, spaces or new lines. Factions are given as
+/// `faction:<id>` or a faction profile link.
+pub fn parse(input: &str) -> Vec<BulkEntry> {
+    let mut entries = vec![];
+
+    let tokens = input
+        .split(|x: char| x == ',' || x == ';' || x.is_whitespace())
+        .filter(|x| !x.is_empty());
+
+    for token in tokens {
+        // Torn writes users as "Name [id]", in which case the id is enough
+        if let Some(id) = token.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+            if let Some(BulkEntry::Name(_)) = entries.last() {
+                entries.pop();
+            }
+
+            entries.push(parse_id(id).map_or(BulkEntry::Invalid(token.to_string()), BulkEntry::User));
+            continue;
+        }
+
+        entries.push(parse_token(token));
+    }
+
+    entries
+}
+
+/// Work out what a single token is
+fn parse_token(token: &str) -> BulkEntry {
+    let lower = token.to_ascii_lowercase();
+    let invalid = || BulkEntry::Invalid(token.to_string());
+
+    if lower.contains("factions.php") {
+        return query_param(token, "ID").and_then(parse_id).map_or_else(invalid, BulkEntry::Faction);
+    }
+
+    if lower.contains(".php") || lower.contains("torn.com") {
+        return query_param(token, "XID").and_then(parse_id).map_or_else(invalid, BulkEntry::User);
+    }
+
+    if let Some((prefix, id)) = token.split_once(':')
+        && (prefix.eq_ignore_ascii_case("faction") || prefix.eq_ignore_ascii_case("f")) {
+        return parse_id(id).map_or_else(invalid, BulkEntry::Faction);
+    }
+
+    if token.chars().all(|x| x.is_ascii_digit()) {
+        return parse_id(token).map_or_else(invalid, BulkEntry::User);
+    }
+
+    let is_name = token.len() <= MAX_NAME_LENGTH
+        && token.chars().all(|x| x.is_ascii_alphanumeric() || x == '_' || x == '-');
+
+    if is_name {
+        BulkEntry::Name(token.to_string())
+    }
+    else {
+        invalid()
+    }
+}
+
+/// Parse a non-zero id
+fn parse_id(input: &str) -> Option<u32> {
+    input.parse().ok().filter(|x| *x != 0)
+}
+
+/// Get a query parameter from a link, ignoring case in the parameter name
+fn query_param<'a>(url: &'a str, name: &str) -> Option<&'a str> {
+    let (_, query) = url.split_once('?')?;
+    let query = query.split('#').next().unwrap_or_default();
+
+    query.split('&')
+        .filter_map(|x| x.split_once('='))
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value)
+}
+
+/// Factions mentioned in the entries, whose members need fetching
+pub fn factions(entries: &[BulkEntry]) -> Vec<u32> {
+    let mut factions = UniqueVec::new();
+
+    for entry in entries {
+        if let BulkEntry::Faction(x) = entry {
+            factions.push(*x);
+        }
+    }
+
+    factions.to_vec()
+}
+
+/// Entries turned into user ids
+#[derive(Debug, Clone)]
+pub struct BulkResult {
+    /// Ids to add, in the order they were given
+    pub ids: UniqueVec<u32>,
+
+    /// Ids given more than once
+    pub duplicates: usize,
+
+    /// Names and factions that couldn't be looked up
+    pub unresolved: Vec<String>,
+
+    /// Things that weren't ids, names or links
+    pub invalid: Vec<String>,
+}
+
+/// Turn entries into user ids. Factions expand to the members in `factions`,
+/// and names are looked up among those members and the `known` users.
+pub fn resolve(entries: &[BulkEntry], factions: &HashMap<u32, Vec<User>>, known: &[User]) -> BulkResult {
+    let mut result = BulkResult {
+        ids: UniqueVec::new(),
+        duplicates: 0,
+        unresolved: vec![],
+        invalid: vec![],
+    };
+
+    fn push(result: &mut BulkResult, id: u32) {
+        if result.ids.push(id).is_some() {
+            result.duplicates += 1;
+        }
+    }
+
+    for entry in entries {
+        match entry {
+            BulkEntry::User(x) => push(&mut result, *x),
+            BulkEntry::Faction(x) => match factions.get(x) {
+                Some(members) => {
+                    for member in members {
+                        push(&mut result, member.id);
+                    }
+                }
+                None => result.unresolved.push(format!("faction {}", x)),
+            },
+            BulkEntry::Name(name) => {
+                let found = factions.values()
+                    .flatten()
+                    .chain(known)
+                    .find(|x| x.name.eq_ignore_ascii_case(name));
+
+                match found {
+                    Some(x) => push(&mut result, x.id),
+                    None => result.unresolved.push(name.clone()),
+                }
+            }
+            BulkEntry::Invalid(x) => result.invalid.push(x.clone()),
+        }
+    }
+
+    result
+}
\ No newline at end of file
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+use chrono::DateTime;
+use crate::bulk::{factions, parse, resolve, BulkEntry};
+use crate::store::User;
+
+/// A faction member
+fn member(id: u32, name: &str) -> User {
+    User {
+        id,
+        name: name.to_string(),
+        hospital_until: DateTime::UNIX_EPOCH,
+        faction_id: None,
+    }
+}
+
+#[test]
+fn parse_entries() {
+    let input = "1234, 5678\n\
+        https://www.torn.com/profiles.php?XID=4321#/\n\
+        https://www.torn.com/factions.php?step=profile&ID=99\n\
+        faction:100; Chedburn [1]\n\
+        SomeName, 0, what?!";
+
+    assert_eq!(parse(input), vec![
+        BulkEntry::User(1234),
+        BulkEntry::User(5678),
+        BulkEntry::User(4321),
+        BulkEntry::Faction(99),
+        BulkEntry::Faction(100),
+        BulkEntry::User(1),
+        BulkEntry::Name("SomeName".to_string()),
+        BulkEntry::Invalid("0".to_string()),
+        BulkEntry::Invalid("what?!".to_string()),
+    ]);
+}
+
+#[test]
+fn parse_bad_links() {
+    assert_eq!(parse("https://www.torn.com/profiles.php?XID=abc"), vec![
+        BulkEntry::Invalid("https://www.torn.com/profiles.php?XID=abc".to_string()),
+    ]);
+    assert_eq!(parse("https://www.torn.com/index.php"), vec![
+        BulkEntry::Invalid("https://www.torn.com/index.php".to_string()),
+    ]);
+}
+
+#[test]
+fn resolve_dedupes() {
+    let entries = parse("1, 2, faction:10, Bob, alice, Nobody, 2, faction:11");
+    assert_eq!(factions(&entries), vec![10, 11]);
+
+    let fetched = HashMap::from([(10, vec![member(2, "Bob"), member(3, "Carol")])]);
+    let result = resolve(&entries, &fetched, &[member(4, "Alice")]);
+
+    assert_eq!(*result.ids, vec![1, 2, 3, 4]);
+    // 2 appears directly twice, once through the faction, and once as Bob
+    assert_eq!(result.duplicates, 3);
+    assert_eq!(result.unresolved, vec!["Nobody".to_string(), "faction 11".to_string()]);
+    assert!(result.invalid.is_empty());
+}
@@ -0,0 +1,555 @@
+//! Charts of how ranked wars went, drawn from the history database: both
+//! sides' scores, the lead, hits per hour, chain length and respect per hit.
+//! Works for the war going on now and any recorded before. The same window
+//! has the war's [`Leaderboard`] and the enemy's activity [`Heatmap`].
+#![warn(missing_docs)]
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use chrono::{DateTime, Utc};
+use eframe::egui;
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+use crate::activity::{Heatmap, DAYS};
+use crate::history::{ChainPoint, History, HistoryError, ScorePoint, WarRecord};
+use crate::leaderboard::{Column, Leaderboard};
+use crate::store::Attack;
+use crate::util::csv_field;
+
+/// How often charts of a war that's still going are reloaded
+const RELOAD_INTERVAL: chrono::TimeDelta = chrono::TimeDelta::minutes(1);
+
+/// Which chart a series goes on
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Axis {
+    /// Scores and the lead, in points
+    Score,
+
+    /// Hits, chain and respect, which are much smaller numbers
+    Activity,
+}
+
+/// One line on a chart
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    /// Name shown in the legend and the CSV
+    pub name: String,
+
+    /// Which chart it goes on
+    pub axis: Axis,
+
+    /// Hours since the war started, and the value then
+    pub points: Vec<[f64; 2]>,
+}
+
+/// Everything charted for one war
+#[derive(Debug, Clone, PartialEq)]
+pub struct WarCharts {
+    /// The war
+    pub war: WarRecord,
+
+    /// Lines, in legend order
+    pub series: Vec<Series>,
+}
+
+impl WarCharts {
+    /// Work out the series for `war` from what was recorded. `faction_id` is
+    /// the side counted as ours; if it wasn't in the war, the first side seen
+    /// is used. `now` is the end of a war that's still going.
+    pub fn build(war: &WarRecord, faction_id: u32, scores: &[ScorePoint], chains: &[ChainPoint], attacks: &[Attack], now: DateTime<Utc>) -> WarCharts {
+        let hours = |at: DateTime<Utc>| (at - war.start).num_seconds() as f64 / 3600.0;
+
+        let ours_id = our_side(scores, faction_id);
+        let name = |ours: bool| scores.iter()
+            .find(|x| (x.faction_id == ours_id) == ours)
+            .map_or(if ours { "Us" } else { "Them" }, |x| x.name.as_str());
+
+        let mut ours = vec![];
+        let mut theirs = vec![];
+        let mut lead: Vec<[f64; 2]> = vec![];
+        let (mut our_score, mut their_score) = (0.0, 0.0);
+
+        for point in scores {
+            let x = hours(point.at);
+
+            if point.faction_id == ours_id {
+                our_score = point.score as f64;
+                ours.push([x, our_score]);
+            }
+            else {
+                their_score = point.score as f64;
+                theirs.push([x, their_score]);
+            }
+
+            // Both sides are usually recorded together, so only keep the lead
+            // once both are in
+            match lead.last_mut() {
+                Some([last, value]) if *last == x => *value = our_score - their_score,
+                _ => lead.push([x, our_score - their_score]),
+            }
+        }
+
+        // Our successful war hits, bucketed by hour of the war
+        let end = war.end.unwrap_or(now);
+        let mut buckets = BTreeMap::<i64, (u32, f64)>::new();
+        for attack in attacks {
+            let ours = attack.attacker.as_ref().is_some_and(|x| x.faction_id == Some(ours_id));
+            if !ours || !attack.ranked_war || attack.respect_gain <= 0.0 || attack.ended < war.start || attack.ended > end {
+                continue;
+            }
+
+            let bucket = buckets.entry((attack.ended - war.start).num_hours()).or_default();
+            bucket.0 += 1;
+            bucket.1 += attack.respect_gain;
+        }
+
+        let hits = buckets.iter()
+            .map(|(hour, (hits, _))| [*hour as f64 + 0.5, *hits as f64])
+            .collect();
+        let respect = buckets.iter()
+            .map(|(hour, (hits, respect))| [*hour as f64 + 0.5, respect / *hits as f64])
+            .collect();
+        let chain = chains.iter()
+            .map(|x| [hours(x.at), x.current as f64])
+            .collect();
+
+        WarCharts {
+            war: war.clone(),
+            series: vec![
+                Series { name: format!("{} score", name(true)), axis: Axis::Score, points: ours },
+                Series { name: format!("{} score", name(false)), axis: Axis::Score, points: theirs },
+                Series { name: "Lead".to_string(), axis: Axis::Score, points: lead },
+                Series { name: "Hits per hour".to_string(), axis: Axis::Activity, points: hits },
+                Series { name: "Chain".to_string(), axis: Axis::Activity, points: chain },
+                Series { name: "Respect per hit".to_string(), axis: Axis::Activity, points: respect },
+            ],
+        }
+    }
+
+    /// Load everything recorded for `war` and chart it
+    pub fn load(history: &History, war: &WarRecord, faction_id: u32, now: DateTime<Utc>) -> Result<WarCharts, HistoryError> {
+        let end = war.end.unwrap_or(now);
+        let scores = history.war_scores(war.id)?;
+
+        // Chains and attacks are only recorded for our side
+        let ours = our_side(&scores, faction_id);
+
+        let chains = history.chains(ours, war.start, end)?;
+        let attacks = history.attacks(ours, war.start, end)?;
+
+        Ok(WarCharts::build(war, faction_id, &scores, &chains, &attacks, now))
+    }
+
+    /// Every series as CSV, one row per point
+    pub fn to_csv(&self) -> String {
+        let mut csv = "war,series,hours,value\n".to_string();
+
+        for series in &self.series {
+            for [x, y] in &series.points {
+                writeln!(csv, "{},{},{},{}", self.war.id, csv_field(&series.name), x, y).unwrap();
+            }
+        }
+
+        csv
+    }
+}
+
+/// Which side of a war is ours: `faction_id` if it was in it, or the first
+/// side seen otherwise
+pub fn our_side(scores: &[ScorePoint], faction_id: u32) -> u32 {
+    if scores.iter().any(|x| x.faction_id == faction_id) {
+        faction_id
+    }
+    else {
+        scores.first().map_or(faction_id, |x| x.faction_id)
+    }
+}
+
+/// Tabs of the war history window
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Tab {
+    /// Score and activity charts
+    Charts,
+
+    /// What each member did
+    Leaderboard,
+
+    /// When the enemy is online
+    Activity,
+}
+
+/// State of the war history window
+#[derive(Debug)]
+pub struct WarWindow {
+    /// Whether it's showing
+    open: bool,
+
+    /// Tab being shown
+    tab: Tab,
+
+    /// Wars to pick from, newest first
+    wars: Vec<WarRecord>,
+
+    /// War being shown
+    selected: Option<u32>,
+
+    /// Charts for it, or why they couldn't be loaded
+    charts: Option<Result<WarCharts, String>>,
+
+    /// Leaderboard for it
+    leaderboard: Option<Result<Leaderboard, String>>,
+
+    /// Column the leaderboard is sorted by, and whether biggest first
+    sort: (Column, bool),
+
+    /// Factions whose activity has been recorded
+    enemies: Vec<u32>,
+
+    /// Faction whose activity is being shown. The selected war's enemy by
+    /// default.
+    enemy: Option<u32>,
+
+    /// Its activity
+    heatmap: Option<Result<Heatmap, String>>,
+
+    /// When they were loaded
+    loaded_at: Option<DateTime<Utc>>,
+
+    /// Whether to zoom back out on the next frame
+    reset_zoom: bool,
+
+    /// Where to export to
+    csv_path: String,
+
+    /// Result of the last export
+    status: Option<Result<String, String>>,
+}
+
+impl Default for WarWindow {
+    fn default() -> WarWindow {
+        WarWindow {
+            open: false,
+            tab: Tab::Charts,
+            wars: vec![],
+            selected: None,
+            charts: None,
+            leaderboard: None,
+            sort: (Column::Respect, true),
+            enemies: vec![],
+            enemy: None,
+            heatmap: None,
+            loaded_at: None,
+            reset_zoom: false,
+            csv_path: "war.csv".to_string(),
+            status: None,
+        }
+    }
+}
+
+impl WarWindow {
+    /// Open the window, reloading the list of wars
+    pub fn show_window(&mut self) {
+        self.open = true;
+        self.loaded_at = None;
+    }
+
+    /// Draw the window, if it's open. Everything comes from `history`, with
+    /// `faction_id` as our side.
+    pub fn show(&mut self, ctx: &egui::Context, history: Option<&History>, faction_id: Option<u32>, now: DateTime<Utc>) {
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+        egui::Window::new("War history")
+            .open(&mut open)
+            .default_size([700.0, 500.0])
+            .show(ctx, |ui| {
+                let Some(history) = history else {
+                    ui.label("History isn't being recorded.");
+                    return;
+                };
+
+                let ongoing = self.charts.as_ref()
+                    .and_then(|x| x.as_ref().ok())
+                    .is_some_and(|x| x.war.end.is_none());
+                let stale = self.loaded_at.is_none_or(|x| ongoing && now - x >= RELOAD_INTERVAL);
+
+                if stale {
+                    self.reload(history, faction_id.unwrap_or_default(), now);
+                }
+
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.tab, Tab::Charts, "Charts");
+                    ui.selectable_value(&mut self.tab, Tab::Leaderboard, "Leaderboard");
+                    ui.selectable_value(&mut self.tab, Tab::Activity, "Activity");
+                });
+
+                self.controls(ui, history, faction_id.unwrap_or_default(), now);
+
+                match self.tab {
+                    Tab::Charts => match &self.charts {
+                        None => { ui.label("No wars have been recorded yet."); }
+                        Some(Err(x)) => { ui.colored_label(egui::Color32::RED, x); }
+                        Some(Ok(charts)) => {
+                            let height = (ui.available_height() / 2.0 - 10.0).max(120.0);
+                            let reset = std::mem::take(&mut self.reset_zoom);
+
+                            plot(ui, charts, Axis::Score, height, reset);
+                            plot(ui, charts, Axis::Activity, height, reset);
+                        }
+                    },
+                    Tab::Leaderboard => match &mut self.leaderboard {
+                        None => { ui.label("No wars have been recorded yet."); }
+                        Some(Err(x)) => { ui.colored_label(egui::Color32::RED, x); }
+                        Some(Ok(leaderboard)) => table(ui, leaderboard, &mut self.sort),
+                    },
+                    Tab::Activity => self.activity(ui, history),
+                }
+            });
+
+        self.open = open;
+    }
+
+    /// War picker, zoom and export
+    fn controls(&mut self, ui: &mut egui::Ui, history: &History, faction_id: u32, now: DateTime<Utc>) {
+        ui.horizontal(|ui| {
+            let label = |x: &WarRecord| match x.end {
+                None => format!("War {} (ongoing)", x.id),
+                Some(_) => format!("War {} ({})", x.id, x.start.format("%Y-%m-%d")),
+            };
+
+            let selected = self.wars.iter().find(|x| Some(x.id) == self.selected).map(label).unwrap_or_default();
+            let mut choice = self.selected;
+
+            egui::ComboBox::from_id_salt("war_history_war")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for war in &self.wars {
+                        ui.selectable_value(&mut choice, Some(war.id), label(war));
+                    }
+                });
+
+            if choice != self.selected {
+                self.selected = choice;
+                self.enemy = None;
+                self.reload(history, faction_id, now);
+            }
+
+            if ui.button("Reload").clicked() {
+                self.reload(history, faction_id, now);
+            }
+
+            if self.tab == Tab::Charts && ui.button("Reset zoom").clicked() {
+                self.reset_zoom = true;
+            }
+
+            ui.separator();
+
+            ui.text_edit_singleline(&mut self.csv_path);
+
+            // Whichever tab is showing
+            let csv = match self.tab {
+                Tab::Charts => self.charts.as_ref().and_then(|x| x.as_ref().ok()).map(WarCharts::to_csv),
+                Tab::Leaderboard => self.leaderboard.as_ref().and_then(|x| x.as_ref().ok()).map(Leaderboard::to_csv),
+                Tab::Activity => self.heatmap.as_ref().and_then(|x| x.as_ref().ok()).map(Heatmap::to_csv),
+            };
+            if ui.add_enabled(csv.is_some(), egui::Button::new("Export CSV")).clicked()
+                && let Some(csv) = csv {
+                self.status = Some(std::fs::write(&self.csv_path, csv)
+                    .map(|_| format!("Saved to {}", self.csv_path))
+                    .map_err(|x| x.to_string()));
+            }
+        });
+
+        match &self.status {
+            Some(Ok(x)) => { ui.label(x); }
+            Some(Err(x)) => { ui.colored_label(egui::Color32::RED, x); }
+            None => {}
+        }
+
+        if self.tab == Tab::Charts {
+            ui.label("Scroll to pan, ctrl+scroll to zoom, and drag with the right button to zoom to an area. \
+                Click a name in the legend to hide it.");
+        }
+    }
+
+    /// Load the list of wars, the selected war's charts and leaderboard, and
+    /// the enemy's activity again
+    fn reload(&mut self, history: &History, faction_id: u32, now: DateTime<Utc>) {
+        self.loaded_at = Some(now);
+
+        self.wars = match history.wars() {
+            Ok(x) => x,
+            Err(x) => {
+                self.charts = Some(Err(x.to_string()));
+                self.leaderboard = Some(Err(x.to_string()));
+                self.heatmap = Some(Err(x.to_string()));
+                return;
+            }
+        };
+
+        // Newest war by default, which is the current one if there is one
+        if !self.wars.iter().any(|x| Some(x.id) == self.selected) {
+            self.selected = self.wars.first().map(|x| x.id);
+        }
+
+        let war = self.wars.iter().find(|x| Some(x.id) == self.selected);
+
+        self.charts = war.map(|war| WarCharts::load(history, war, faction_id, now).map_err(|x| x.to_string()));
+        self.leaderboard = war.map(|war| Leaderboard::load(history, war, faction_id, now)
+            .map(|mut x| {
+                x.sort(self.sort.0, self.sort.1);
+                x
+            })
+            .map_err(|x| x.to_string()));
+
+        self.enemies = history.activity_factions().unwrap_or_default();
+
+        // The selected war's other side if its activity's been recorded, or
+        // the most recently polled faction if not
+        if !self.enemies.iter().any(|x| Some(*x) == self.enemy) {
+            let theirs = war.and_then(|war| {
+                let scores = history.war_scores(war.id).ok()?;
+                let ours = our_side(&scores, faction_id);
+                scores.iter().find(|x| x.faction_id != ours).map(|x| x.faction_id)
+            });
+
+            self.enemy = theirs.filter(|x| self.enemies.contains(x)).or(self.enemies.first().copied());
+        }
+
+        self.load_heatmap(history);
+    }
+
+    /// Load the chosen enemy's activity
+    fn load_heatmap(&mut self, history: &History) {
+        self.heatmap = self.enemy.map(|x| Heatmap::load(history, x).map_err(|x| x.to_string()));
+    }
+
+    /// Faction picker and heatmap
+    fn activity(&mut self, ui: &mut egui::Ui, history: &History) {
+        if self.enemies.is_empty() {
+            ui.label("No enemy activity has been recorded yet. It's polled every 15 minutes while \
+                torndkt is open during a ranked war, or all the time for a faction picked with torndkt history watch.");
+            return;
+        }
+
+        let mut choice = self.enemy;
+        egui::ComboBox::from_id_salt("war_history_enemy")
+            .selected_text(choice.map(|x| format!("Faction {}", x)).unwrap_or_default())
+            .show_ui(ui, |ui| {
+                for enemy in &self.enemies {
+                    ui.selectable_value(&mut choice, Some(*enemy), format!("Faction {}", enemy));
+                }
+            });
+
+        if choice != self.enemy {
+            self.enemy = choice;
+            self.load_heatmap(history);
+        }
+
+        match &self.heatmap {
+            None => {}
+            Some(Err(x)) => { ui.colored_label(egui::Color32::RED, x); }
+            Some(Ok(heatmap)) => {
+                ui.label("Average members active in each hour, in TCT. Blank hours haven't been polled yet.");
+                heatmap_grid(ui, heatmap);
+            }
+        }
+    }
+}
+
+/// Draw one chart. Both share an x axis, so zooming one zooms the other.
+fn plot(ui: &mut egui::Ui, charts: &WarCharts, axis: Axis, height: f32, reset: bool) {
+    let id = match axis {
+        Axis::Score => "war_score",
+        Axis::Activity => "war_activity",
+    };
+
+    let mut plot = Plot::new(id)
+        .legend(Legend::default())
+        .height(height)
+        .link_axis("war_history", [true, false])
+        .link_cursor("war_history", [true, false])
+        .x_axis_label("Hours since start");
+
+    if reset {
+        plot = plot.reset();
+    }
+
+    plot.show(ui, |plot_ui| {
+        for series in charts.series.iter().filter(|x| x.axis == axis) {
+            plot_ui.line(Line::new(PlotPoints::from(series.points.clone())).name(&series.name));
+        }
+    });
+}
+
+/// Draw a leaderboard. Clicking a heading sorts by it, and clicking it again
+/// flips the order.
+fn table(ui: &mut egui::Ui, leaderboard: &mut Leaderboard, sort: &mut (Column, bool)) {
+    if leaderboard.members.is_empty() {
+        ui.label("No attacks have been recorded for this war.");
+        return;
+    }
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        egui::Grid::new("war_leaderboard")
+            .striped(true)
+            .show(ui, |ui| {
+                for column in Column::ALL {
+                    let arrow = match *sort {
+                        (x, true) if x == column => " ⏷",
+                        (x, false) if x == column => " ⏶",
+                        _ => "",
+                    };
+
+                    if ui.button(format!("{}{}", column.title(), arrow)).clicked() {
+                        // Names read best A to Z, numbers biggest first
+                        *sort = if sort.0 == column { (column, !sort.1) } else { (column, column != Column::Name) };
+                        leaderboard.sort(sort.0, sort.1);
+                    }
+                }
+                ui.end_row();
+
+                for member in &leaderboard.members {
+                    for column in Column::ALL {
+                        ui.label(column.value(member));
+                    }
+                    ui.end_row();
+                }
+            });
+    });
+}
+
+/// Draw a heatmap, one row per day. The busier the hour, the redder it is.
+fn heatmap_grid(ui: &mut egui::Ui, heatmap: &Heatmap) {
+    let peak = heatmap.peak().max(1.0);
+
+    egui::ScrollArea::horizontal().show(ui, |ui| {
+        egui::Grid::new("enemy_activity")
+            .spacing([2.0, 2.0])
+            .show(ui, |ui| {
+                ui.label("");
+                for hour in 0..24 {
+                    ui.monospace(format!("{:02}", hour));
+                }
+                ui.end_row();
+
+                for (day, row) in heatmap.cells.iter().enumerate() {
+                    ui.label(DAYS[day]);
+
+                    for (hour, cell) in row.iter().enumerate() {
+                        let (text, alpha) = match cell {
+                            Some(x) => (format!("{:>2.0}", x), (x / peak * 255.0) as u8),
+                            None => ("  ".to_string(), 0),
+                        };
+
+                        ui.label(egui::RichText::new(text)
+                            .monospace()
+                            .background_color(egui::Color32::from_rgba_unmultiplied(220, 60, 40, alpha)))
+                            .on_hover_text(format!("{} {:02}:00, {} hour(s) sampled", DAYS[day], hour, heatmap.samples[day][hour]));
+                    }
+                    ui.end_row();
+                }
+            });
+    });
+}
\ No newline at end of file
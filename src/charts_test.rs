@@ -0,0 +1,64 @@
+use chrono::TimeDelta;
+use crate::charts::WarCharts;
+use crate::history::History;
+use crate::store::Chain;
+use crate::test_history::{attacks, memory, now, war};
+
+/// Two hours of a war: we go ahead, then they overtake
+fn history() -> History {
+    let history = memory();
+    let hours = |x: f64| now() + TimeDelta::seconds((x * 3600.0) as i64);
+
+    history.record_war(&war(0, 0, None), now()).unwrap();
+    history.record_war(&war(30, 10, None), hours(1.0)).unwrap();
+    history.record_war(&war(30, 50, Some(hours(2.0))), hours(2.0)).unwrap();
+
+    let chain = |current| Chain { current, max: 250, breaks_at: None, cooldown_until: None, modifier: 1.0 };
+    history.record_chain(100, &chain(120), hours(0.5)).unwrap();
+    history.record_chain(100, &chain(125), hours(1.5)).unwrap();
+
+    history.record_attacks(100, &attacks()).unwrap();
+
+    history
+}
+
+#[test]
+fn war_series() {
+    let history = history();
+    let war = history.wars().unwrap().remove(0);
+    let charts = WarCharts::load(&history, &war, 100, now() + TimeDelta::days(1)).unwrap();
+
+    let series = |name: &str| charts.series.iter().find(|x| x.name == name).unwrap().points.clone();
+
+    assert_eq!(series("Ours score"), [[0.0, 0.0], [1.0, 30.0]]);
+    assert_eq!(series("Theirs score"), [[0.0, 0.0], [1.0, 10.0], [2.0, 50.0]]);
+    assert_eq!(series("Lead"), [[0.0, 0.0], [1.0, 20.0], [2.0, -20.0]]);
+    assert_eq!(series("Chain"), [[0.5, 120.0], [1.5, 125.0]]);
+
+    // Only our successful war hits count: not the assist, the outside hit or
+    // their hit on us
+    assert_eq!(series("Hits per hour"), [[0.5, 1.0]]);
+    assert_eq!(series("Respect per hit"), [[0.5, 10.5]]);
+
+    // The other side's view of the same war
+    let theirs = WarCharts::load(&history, &war, 200, now()).unwrap();
+    assert_eq!(theirs.series[0].name, "Theirs score");
+    assert!(theirs.series[3].points.is_empty());
+}
+
+#[test]
+fn csv_export() {
+    let history = history();
+    let war = history.wars().unwrap().remove(0);
+    let mut charts = WarCharts::load(&history, &war, 100, now()).unwrap();
+    charts.series[0].name = "Ours, \"the best\"".to_string();
+
+    let csv = charts.to_csv();
+    let lines = csv.lines().collect::<Vec<_>>();
+
+    assert_eq!(lines[0], "war,series,hours,value");
+    assert_eq!(lines[1], "7,\"Ours, \"\"the best\"\"\",0,0");
+    assert_eq!(lines[2], "7,\"Ours, \"\"the best\"\"\",1,30");
+    assert_eq!(lines[3], "7,Theirs score,0,0");
+    assert_eq!(lines.len(), 1 + charts.series.iter().map(|x| x.points.len()).sum::<usize>());
+}
\ No newline at end of file
@@ -0,0 +1,259 @@
+//! Command line arguments
+#![warn(missing_docs)]
+
+use std::path::PathBuf;
+use clap::{ArgAction, Parser, Subcommand};
+use reqwest::Url;
+use crate::api::client::DEFAULT_BASE_URL;
+use crate::leaderboard::Column;
+use crate::webhooks::WebhookEvent;
+
+/// Parser for a number from `min` to `max`. Clap's ranges only work for
+/// integers.
+fn between(min: f64, max: f64) -> impl Fn(&str) -> Result<f64, String> + Clone + Send + Sync + 'static {
+    move |x| match x.parse::<f64>() {
+        Ok(value) if (min..=max).contains(&value) => Ok(value),
+        Ok(_) => Err(format!("must be from {} to {}", min, max)),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// A desktop tool for torn, mainly designed for ranked warring
+#[derive(Parser, Debug, Clone)]
+#[command(version)]
+pub struct Args {
+    /// Use this persistence file instead of the one in the config directory
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Open this profile instead of the one that was open last
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Seconds before an API request is given up on, from 0.1 to 300
+    #[arg(long, value_name = "SECS", default_value_t = 10.0, value_parser = between(0.1, 300.0))]
+    pub api_timeout: f64,
+
+    /// Times a failed API request is retried
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub api_retries: u32,
+
+    /// Base URL for API requests
+    #[arg(long, value_name = "URL", default_value = DEFAULT_BASE_URL)]
+    pub api_url: Url,
+
+    /// Send the API key in a header rather than the URL, so it never appears
+    /// in logs
+    #[arg(long)]
+    pub key_in_header: bool,
+
+    /// Record every API request and response to this file. The key is left
+    /// out.
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Play back a recorded session instead of using the API
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+
+    /// Serve monitors, war score and chain status as JSON on this port, on
+    /// localhost only, for other tools to read
+    #[arg(long, value_name = "PORT")]
+    pub serve: Option<u16>,
+
+    /// Run in the terminal instead of opening a window
+    #[arg(long)]
+    pub tui: bool,
+
+    /// How many times faster than real time to play a recording back, from
+    /// 0.1 to 1000
+    #[arg(long, value_name = "X", default_value_t = 1.0, requires = "replay", value_parser = between(0.1, 1000.0))]
+    pub replay_speed: f64,
+
+    /// Print JSON instead of text from subcommands
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Run a single query and exit, instead of opening a window
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// One-shot queries, for scripts
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Show whether users are in hospital, and for how long
+    Status {
+        /// User ids
+        #[arg(required = true)]
+        ids: Vec<u32>,
+    },
+
+    /// Show a faction's members and their statuses
+    Faction {
+        /// Faction id
+        id: u32,
+    },
+
+    /// Show the current chain and ranked war score
+    War {
+        /// Faction to show, instead of the key owner's
+        #[arg(long, value_name = "ID")]
+        faction: Option<u32>,
+    },
+
+    /// List or change the saved monitors of the open profile
+    Monitors {
+        #[command(subcommand)]
+        action: MonitorsCommand,
+    },
+
+    /// List or change where events are sent
+    Webhooks {
+        #[command(subcommand)]
+        action: WebhooksCommand,
+    },
+
+    /// Look back at recorded wars, or change what history is kept
+    History {
+        #[command(subcommand)]
+        action: HistoryCommand,
+    },
+}
+
+/// Changes to the saved monitors
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum MonitorsCommand {
+    /// List monitors, by group
+    List,
+
+    /// Add monitors for users or whole factions
+    Add {
+        /// User ids, profile links or faction:<id>
+        #[arg(required = true)]
+        targets: Vec<String>,
+
+        /// Group to add to, created if it doesn't exist. The first group if
+        /// not given.
+        #[arg(long)]
+        group: Option<String>,
+    },
+
+    /// Remove monitors for users
+    Remove {
+        /// User ids
+        #[arg(required = true)]
+        ids: Vec<u32>,
+
+        /// Only remove from this group
+        #[arg(long)]
+        group: Option<String>,
+    },
+}
+
+
+/// Changes to the webhooks
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum WebhooksCommand {
+    /// List webhooks
+    List,
+
+    /// Add a webhook, or change one that's already there
+    Add {
+        /// URL to POST events to
+        url: String,
+
+        /// Only send this event. Can be given more than once. All events if
+        /// not given.
+        #[arg(long = "event", value_name = "EVENT")]
+        events: Vec<WebhookEvent>,
+
+        /// JSON to send instead of the default payload, with {name}
+        /// placeholders, e.g. '{"content": "{message}"}'
+        #[arg(long)]
+        template: Option<String>,
+    },
+
+    /// Remove a webhook
+    Remove {
+        /// Its URL
+        url: String,
+    },
+
+    /// Set how many seconds left on the chain count as about to break
+    ChainBelow {
+        /// Seconds
+        secs: u32,
+    },
+
+    /// Send a test event, to check webhooks work
+    Test {
+        /// Only send to this URL, instead of every webhook
+        url: Option<String>,
+    },
+}
+
+/// History queries and settings
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum HistoryCommand {
+    /// List recorded ranked wars
+    Wars,
+
+    /// Show how a war's score went
+    War {
+        /// War id. The latest war if not given.
+        id: Option<u32>,
+    },
+
+    /// Show how much each member of our faction did in a war
+    Leaderboard {
+        /// War id. The latest war if not given.
+        war: Option<u32>,
+
+        /// Column to sort by. Numbers go biggest first, names A to Z.
+        #[arg(long, value_enum, default_value = "respect")]
+        sort: Column,
+
+        /// Side of the war to show, instead of the key owner's faction
+        #[arg(long, value_name = "ID")]
+        faction: Option<u32>,
+
+        /// Print CSV instead of a table
+        #[arg(long)]
+        csv: bool,
+    },
+
+    /// Show when an enemy faction's members are usually online, by hour of
+    /// the week in TCT
+    Activity {
+        /// Faction id. The most recently polled faction if not given.
+        faction: Option<u32>,
+
+        /// Print CSV instead of a table
+        #[arg(long)]
+        csv: bool,
+    },
+
+    /// Record a faction's activity even when not at war with them. The
+    /// enemy in a ranked war is always recorded.
+    Watch {
+        /// Faction id. Stops watching if not given.
+        faction: Option<u32>,
+    },
+
+    /// Turn recording history on or off
+    Record {
+        /// true or false
+        #[arg(action = ArgAction::Set)]
+        enabled: bool,
+    },
+
+    /// Set how many days of history are kept. 0 keeps it forever.
+    Retention {
+        /// Days
+        days: u32,
+    },
+
+    /// Delete history older than the retention period now
+    Prune,
+}
\ No newline at end of file
@@ -0,0 +1,56 @@
+use clap::Parser;
+use crate::cli::{Args, Command, MonitorsCommand, WebhooksCommand};
+use crate::webhooks::WebhookEvent;
+
+#[test]
+fn no_subcommand_opens_window() {
+    let args = Args::try_parse_from(["torndkt", "--profile", "War"]).unwrap();
+    assert_eq!(args.command, None);
+    assert!(!args.json);
+}
+
+#[test]
+fn subcommands() {
+    let args = Args::try_parse_from(["torndkt", "status", "1", "2", "--json"]).unwrap();
+    assert_eq!(args.command, Some(Command::Status { ids: vec![1, 2] }));
+    assert!(args.json);
+
+    let args = Args::try_parse_from(["torndkt", "monitors", "add", "1", "faction:2", "--group", "War"]).unwrap();
+    assert_eq!(args.command, Some(Command::Monitors {
+        action: MonitorsCommand::Add {
+            targets: vec!["1".to_string(), "faction:2".to_string()],
+            group: Some("War".to_string()),
+        },
+    }));
+
+    let args = Args::try_parse_from(["torndkt", "webhooks", "add", "http://localhost/", "--event", "chain_low", "--event", "key_error"]).unwrap();
+    assert_eq!(args.command, Some(Command::Webhooks {
+        action: WebhooksCommand::Add {
+            url: "http://localhost/".to_string(),
+            events: vec![WebhookEvent::ChainLow, WebhookEvent::KeyError],
+            template: None,
+        },
+    }));
+
+    assert!(Args::try_parse_from(["torndkt", "status"]).is_err());
+    assert!(Args::try_parse_from(["torndkt", "faction", "abc"]).is_err());
+}
+
+#[test]
+fn api_timeout_is_bounded() {
+    assert_eq!(Args::try_parse_from(["torndkt", "--api-timeout", "2.5"]).unwrap().api_timeout, 2.5);
+
+    for x in ["0", "inf", "1e20", "NaN", "soon"] {
+        assert!(Args::try_parse_from(["torndkt", "--api-timeout", x]).is_err(), "{}", x);
+    }
+}
+
+#[test]
+fn replay_speed_is_bounded() {
+    let args = Args::try_parse_from(["torndkt", "--replay", "war.jsonl", "--replay-speed", "60"]).unwrap();
+    assert_eq!(args.replay_speed, 60.0);
+
+    for x in ["0.01", "1e300", "-1"] {
+        assert!(Args::try_parse_from(["torndkt", "--replay", "war.jsonl", "--replay-speed", x]).is_err(), "{}", x);
+    }
+}
\ No newline at end of file
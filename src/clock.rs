@@ -0,0 +1,105 @@
+//! Time sources for monitors and timers.
+//!
+//! Everything that needs "now" should ask a [`Clock`] instead of calling
+//! `Utc::now()` directly, so countdowns can be tested with a fixed time and
+//! corrected for drift against Torn's server time.
+#![warn(missing_docs)]
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use chrono::{DateTime, TimeDelta, Utc};
+
+/// A source of the current time
+pub trait Clock: Send + Sync {
+    /// The current time, according to this clock
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The local system clock
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that's stuck at one point in time. Mainly for tests.
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+/// Weight given to each new sample when smoothing the offset. Server
+/// timestamps only have second resolution, so individual samples are noisy.
+const OFFSET_SMOOTHING: f64 = 0.2;
+
+/// Offset between the local clock and Torn's server clock, shared between the
+/// API layer (which measures it) and any [`ServerSyncedClock`]s (which use it).
+#[derive(Debug, Default)]
+pub struct ClockOffset {
+    /// Server time minus local time, in milliseconds
+    millis: AtomicI64,
+
+    /// Whether any samples have been taken yet
+    synced: AtomicBool,
+}
+
+impl ClockOffset {
+    /// Get the current offset (server time minus local time)
+    pub fn get(&self) -> TimeDelta {
+        TimeDelta::milliseconds(self.millis.load(Ordering::Relaxed))
+    }
+
+    /// Overwrite the current offset
+    pub fn set(&self, offset: TimeDelta) {
+        self.millis.store(offset.num_milliseconds(), Ordering::Relaxed);
+        self.synced.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the offset has been measured or set at least once
+    pub fn is_synced(&self) -> bool {
+        self.synced.load(Ordering::Relaxed)
+    }
+
+    /// Feed in a server timestamp and the local time it was observed at. The
+    /// first sample is taken as-is, later ones are blended in with an
+    /// exponential moving average.
+    pub fn observe(&self, server: DateTime<Utc>, local: DateTime<Utc>) {
+        let sample = (server - local).num_milliseconds();
+
+        if !self.is_synced() {
+            self.set(TimeDelta::milliseconds(sample));
+            return;
+        }
+
+        let current = self.millis.load(Ordering::Relaxed);
+        let smoothed = current as f64 + (sample - current) as f64 * OFFSET_SMOOTHING;
+        self.millis.store(smoothed.round() as i64, Ordering::Relaxed);
+    }
+}
+
+/// The local system clock, corrected by an offset to match Torn's server time
+#[derive(Debug, Clone, Default)]
+pub struct ServerSyncedClock {
+    offset: Arc<ClockOffset>,
+}
+
+impl ServerSyncedClock {
+    /// Create a clock that follows the given offset
+    pub fn new(offset: Arc<ClockOffset>) -> ServerSyncedClock {
+        ServerSyncedClock { offset }
+    }
+}
+
+impl Clock for ServerSyncedClock {
+    fn now(&self) -> DateTime<Utc> {
+        SystemClock.now() + self.offset.get()
+    }
+}
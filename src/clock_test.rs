@@ -0,0 +1,51 @@
+use std::sync::Arc;
+use chrono::{DateTime, TimeDelta, Utc};
+use crate::clock::{Clock, ClockOffset, FixedClock, ServerSyncedClock};
+
+#[test]
+fn fixed_clock_is_fixed() {
+    let time = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let clock = FixedClock(time);
+
+    assert_eq!(clock.now(), time);
+    assert_eq!(clock.now(), time);
+}
+
+#[test]
+fn synced_clock_applies_offset() {
+    let offset = Arc::new(ClockOffset::default());
+    let clock = ServerSyncedClock::new(offset.clone());
+    offset.set(TimeDelta::seconds(20));
+
+    let diff = clock.now() - Utc::now();
+    assert!((diff - TimeDelta::seconds(20)).abs() < TimeDelta::seconds(1));
+}
+
+#[test]
+fn offset_first_sample_is_exact() {
+    let local = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let offset = ClockOffset::default();
+    assert!(!offset.is_synced());
+
+    offset.observe(local + TimeDelta::seconds(20), local);
+    assert!(offset.is_synced());
+    assert_eq!(offset.get(), TimeDelta::seconds(20));
+}
+
+#[test]
+fn offset_is_smoothed() {
+    let local = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let offset = ClockOffset::default();
+    offset.observe(local + TimeDelta::seconds(20), local);
+
+    // One outlier shouldn't drag the offset all the way
+    offset.observe(local + TimeDelta::seconds(30), local);
+    assert!(offset.get() > TimeDelta::seconds(20));
+    assert!(offset.get() < TimeDelta::seconds(25));
+
+    // But it should converge on a consistent value
+    for _ in 0..100 {
+        offset.observe(local - TimeDelta::seconds(2), local);
+    }
+    assert!((offset.get() + TimeDelta::seconds(2)).abs() < TimeDelta::milliseconds(10));
+}
@@ -0,0 +1,518 @@
+//! One-shot subcommands, for scripts. They use the same key, store and saved
+//! monitors as the window, and print plain text or JSON.
+#![warn(missing_docs)]
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use serde::Serialize;
+use crate::activity::{Heatmap, DAYS};
+use crate::api::api::GetInfoError;
+use crate::api::faction::faction_members;
+use crate::bulk;
+use crate::history::{History, ScorePoint};
+use crate::leaderboard::{Column, Leaderboard};
+use crate::cli::{Command, HistoryCommand, MonitorsCommand, WebhooksCommand};
+use crate::monitors::basic::SimpleHospMonitor;
+use crate::monitors::core::{Monitor, MonitorState, MonitorSummary};
+use crate::persistence::{PersistedData, PersistenceError};
+use crate::store::{Chain, RankedWar, User};
+use crate::util::to_hms;
+use crate::webhooks::{deliver, DeliveryConfig, Event, Webhook, WebhookError, WebhookEvent};
+use crate::ExampleApp;
+
+/// Environment variable a script can put the key's passphrase in, when the key
+/// is kept in an encrypted file
+pub const PASSPHRASE_VAR: &str = "TORNDKT_PASSPHRASE";
+
+/// Reasons a command failed
+#[derive(Debug)]
+pub enum CommandError {
+    /// No API key has been set up
+    NoKey,
+    /// The API said no
+    Api(GetInfoError),
+    /// The key's owner isn't in a faction, or the key can't tell
+    NoFaction,
+    /// Couldn't save the changed monitors
+    Save(PersistenceError),
+    /// Nothing matched what was asked for
+    NotFound(String),
+    /// A webhook couldn't be sent to
+    Webhook(WebhookError),
+    /// History isn't being kept, or couldn't be read
+    History(String),
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::NoKey => write!(f, "No API key. Set one in the window, or set {} if it's protected by a passphrase.", PASSPHRASE_VAR),
+            CommandError::Api(x) => write!(f, "{}. {}", x, x.help_text()),
+            CommandError::NoFaction => write!(f, "The key's owner isn't in a faction. Give one with --faction."),
+            CommandError::Save(x) => write!(f, "Couldn't save settings: {}", x),
+            CommandError::NotFound(x) => write!(f, "Not found: {}", x),
+            CommandError::Webhook(x) => write!(f, "{}", x),
+            CommandError::History(x) => write!(f, "History: {}", x),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+impl From<GetInfoError> for CommandError {
+    fn from(err: GetInfoError) -> CommandError {
+        CommandError::Api(err)
+    }
+}
+
+/// The key owner's chain and ranked war
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WarReport {
+    /// Faction they're for
+    pub faction_id: u32,
+
+    /// Current chain
+    pub chain: Option<Chain>,
+
+    /// Ranked war the faction is in, if any
+    pub war: Option<RankedWar>,
+}
+
+/// Run a subcommand. Output goes to stdout, as JSON if `json` is set.
+pub async fn run(app: &mut ExampleApp, command: Command, json: bool) -> Result<(), CommandError> {
+    match command {
+        Command::Status { ids } => {
+            unlock(app)?;
+
+            app.store.refresh_users(&app.apikey, ids.clone()).await?;
+
+            let summaries = ids.iter()
+                .map(|x| SimpleHospMonitor::new(*x).summary(&app.store, app.clock.as_ref()))
+                .collect::<Vec<_>>();
+
+            print(json, &summaries, || summaries.iter().map(summary_line).collect());
+        }
+        Command::Faction { id } => {
+            unlock(app)?;
+
+            let members = faction_members(&app.apikey, id).await?
+                .iter()
+                .map(|x| User::from_member(id, x))
+                .collect::<Vec<_>>();
+
+            for member in &members {
+                app.store.insert_user(member.id, Ok(member.clone()));
+            }
+
+            let summaries = members.iter()
+                .map(|x| SimpleHospMonitor::new(x.id).summary(&app.store, app.clock.as_ref()))
+                .collect::<Vec<_>>();
+
+            print(json, &members, || summaries.iter().map(summary_line).collect());
+        }
+        Command::War { faction } => {
+            unlock(app)?;
+            app.refresh_key_caps();
+
+            let faction_id = faction
+                .or(app.key_caps.as_ref().and_then(|x| x.faction_id))
+                .ok_or(CommandError::NoFaction)?;
+
+            app.store.refresh_faction(&app.apikey, faction_id, app.clock.as_ref()).await?;
+
+            let report = WarReport {
+                faction_id,
+                chain: app.store.chain(faction_id),
+                war: app.store.war(faction_id).flatten(),
+            };
+
+            print(json, &report, || war_lines(&report, app));
+        }
+        // Settings and history don't need a key
+        Command::Monitors { action } => monitors(app, action, json).await?,
+        Command::Webhooks { action } => webhooks(app, action, json).await?,
+        Command::History { action } => history(app, action, json)?,
+    }
+
+    Ok(())
+}
+
+/// Load the key, using the passphrase from the environment if it needs one
+fn unlock(app: &mut ExampleApp) -> Result<(), CommandError> {
+    if app.passphrase_open {
+        app.passphrase_buf = std::env::var(PASSPHRASE_VAR).unwrap_or_default();
+        app.submit_passphrase();
+    }
+
+    if app.apikey.is_empty() {
+        return Err(CommandError::NoKey);
+    }
+
+    Ok(())
+}
+
+/// Print `value` as JSON, or the lines from `text` otherwise
+fn print<T: Serialize>(json: bool, value: &T, text: impl FnOnce() -> Vec<String>) {
+    if json {
+        println!("{}", serde_json::to_string_pretty(value).unwrap());
+    }
+    else {
+        for line in text() {
+            println!("{}", line);
+        }
+    }
+}
+
+/// One line describing a monitor's target
+fn summary_line(summary: &MonitorSummary) -> String {
+    let status = match &summary.state {
+        MonitorState::Unknown => "not fetched".to_string(),
+        MonitorState::Error(x) => x.to_string(),
+        MonitorState::Waiting => format!("in hospital, {} left", to_hms(summary.time_left)),
+        MonitorState::Ready => "out".to_string(),
+        MonitorState::Info => String::new(),
+    };
+
+    format!("{:<10} {:<16} {}", summary.target, summary.name, status)
+}
+
+/// Lines describing the chain and war
+fn war_lines(report: &WarReport, app: &ExampleApp) -> Vec<String> {
+    let mut lines = vec![];
+
+    match &report.chain {
+        Some(chain) if let Some(breaks_at) = chain.breaks_at => {
+            let secs = (breaks_at - app.clock.now()).num_seconds().max(0);
+            lines.push(format!("Chain: {}/{}, {} left", chain.current, chain.max, to_hms(secs)));
+        }
+        _ => lines.push("Chain: none".to_string()),
+    }
+
+    match report.war.as_ref().and_then(|x| x.sides(report.faction_id).map(|y| (x, y))) {
+        Some((war, (ours, theirs))) => {
+            lines.push(format!("War: {} {} - {} {}", ours.name, ours.score, theirs.score, theirs.name));
+            lines.push(format!("Lead: {} of {}", ours.score as i64 - theirs.score as i64, war.target));
+        }
+        None => lines.push("War: none".to_string()),
+    }
+
+    lines
+}
+
+/// Run a `monitors` subcommand against the open profile
+async fn monitors(app: &mut ExampleApp, action: MonitorsCommand, json: bool) -> Result<(), CommandError> {
+    match action {
+        MonitorsCommand::List => {
+            let groups = &app.profile().groups;
+
+            print(json, groups, || {
+                groups.iter()
+                    .flat_map(|group| group.monitors.iter().map(|x| format!("{:<16} {} {}", group.name, x, x.target_label())))
+                    .collect()
+            });
+
+            return Ok(());
+        }
+        MonitorsCommand::Add { targets, group } => {
+            // Only factions need the API, so a key is only needed for those
+            let entries = bulk::parse(&targets.join(" "));
+
+            let mut factions = HashMap::new();
+            for id in bulk::factions(&entries) {
+                unlock(app)?;
+                let members = faction_members(&app.apikey, id).await?;
+                factions.insert(id, members.iter().map(|x| User::from_member(id, x)).collect::<Vec<_>>());
+            }
+
+            let result = bulk::resolve(&entries, &factions, &[]);
+            for x in result.unresolved.iter().chain(&result.invalid) {
+                eprintln!("Skipping {}, names can't be looked up here", x);
+            }
+
+            let profile = app.profile_mut();
+            let index = match group {
+                Some(x) => profile.group_named(&x),
+                None if profile.groups.is_empty() => profile.group_named("Monitors"),
+                None => 0,
+            };
+
+            let added = profile.add_users(index, result.ids.iter().copied());
+            if !json {
+                println!("Added {} monitor(s)", added);
+            }
+        }
+        MonitorsCommand::Remove { ids, group } => {
+            let profile = app.profile_mut();
+            let index = match group {
+                Some(x) => Some(profile.groups.iter().position(|y| y.name == x)
+                    .ok_or_else(|| CommandError::NotFound(format!("group \"{}\"", x)))?),
+                None => None,
+            };
+
+            let removed = profile.remove_users(index, &ids);
+            if removed == 0 {
+                return Err(CommandError::NotFound(format!("monitors for {:?}", ids)));
+            }
+
+            if !json {
+                println!("Removed {} monitor(s)", removed);
+            }
+        }
+    }
+
+    PersistedData::from(&*app)
+        .save(&app.paths.persistence)
+        .map_err(CommandError::Save)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&app.profile().groups).unwrap());
+    }
+
+    Ok(())
+}
+
+/// Run a `webhooks` subcommand, on the open profile's webhooks
+async fn webhooks(app: &mut ExampleApp, action: WebhooksCommand, json: bool) -> Result<(), CommandError> {
+    let now = app.clock.now();
+    let settings = &mut app.profile_mut().webhooks;
+
+    match action {
+        WebhooksCommand::List => {
+            print(json, settings, || {
+                let mut lines = vec![format!("Chain counts as low with {}s left", settings.chain_below)];
+                lines.extend(settings.hooks.iter().map(|x| {
+                    let events = if x.events.is_empty() {
+                        "all events".to_string()
+                    }
+                    else {
+                        x.events.iter().map(|x| x.name()).collect::<Vec<_>>().join(", ")
+                    };
+
+                    format!("{} ({}{})", x.url, events, if x.template.is_some() { ", templated" } else { "" })
+                }));
+                lines
+            });
+
+            return Ok(());
+        }
+        WebhooksCommand::Add { url, events, template } => {
+            let hook = Webhook { url, events, template };
+
+            // Templates are checked now rather than when something happens
+            Event::new(WebhookEvent::Test, now, String::new())
+                .payload(&hook)
+                .map_err(CommandError::Webhook)?;
+
+            match settings.hooks.iter_mut().find(|x| x.url == hook.url) {
+                Some(x) => *x = hook,
+                None => settings.hooks.push(hook),
+            }
+        }
+        WebhooksCommand::Remove { url } => {
+            let count = settings.hooks.len();
+            settings.hooks.retain(|x| x.url != url);
+
+            if settings.hooks.len() == count {
+                return Err(CommandError::NotFound(format!("webhook {}", url)));
+            }
+        }
+        WebhooksCommand::ChainBelow { secs } => settings.chain_below = secs,
+        WebhooksCommand::Test { url } => {
+            let hooks = settings.hooks.iter()
+                .filter(|x| url.as_ref().is_none_or(|y| x.url == *y))
+                .collect::<Vec<_>>();
+
+            if hooks.is_empty() {
+                return Err(CommandError::NotFound(url.map_or("webhooks".to_string(), |x| format!("webhook {}", x))));
+            }
+
+            let event = Event::new(WebhookEvent::Test, now, "Test event from torndkt".to_string());
+            let client = reqwest::Client::new();
+
+            for hook in hooks {
+                deliver(&client, hook, &event, &DeliveryConfig::default()).await
+                    .map_err(CommandError::Webhook)?;
+
+                if !json {
+                    println!("Sent to {}", hook.url);
+                }
+            }
+
+            return Ok(());
+        }
+    }
+
+    PersistedData::from(&*app)
+        .save(&app.paths.persistence)
+        .map_err(CommandError::Save)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&app.profile().webhooks).unwrap());
+    }
+
+    Ok(())
+}
+
+/// Run a `history` subcommand
+fn history(app: &mut ExampleApp, action: HistoryCommand, json: bool) -> Result<(), CommandError> {
+    // Settings can be changed without the database
+    let query = |app: &ExampleApp| -> Result<std::sync::Arc<History>, CommandError> {
+        app.history.clone().ok_or_else(|| CommandError::History(
+            app.history_error.clone().unwrap_or_else(|| "not being recorded".to_string())
+        ))
+    };
+
+    match action {
+        HistoryCommand::Wars => {
+            let wars = query(app)?.wars().map_err(|x| CommandError::History(x.to_string()))?;
+
+            print(json, &wars, || wars.iter().map(|x| {
+                let end = x.end.map_or("ongoing".to_string(), |y| format!("ended {}", y.format("%Y-%m-%d %H:%M")));
+                format!("{:<8} started {}, {}, target {}", x.id, x.start.format("%Y-%m-%d %H:%M"), end, x.target)
+            }).collect());
+
+            return Ok(());
+        }
+        HistoryCommand::War { id } => {
+            let history = query(app)?;
+            let id = match id {
+                Some(x) => x,
+                None => history.wars().map_err(|x| CommandError::History(x.to_string()))?
+                    .first()
+                    .ok_or_else(|| CommandError::NotFound("recorded wars".to_string()))?
+                    .id,
+            };
+
+            let scores = history.war_scores(id).map_err(|x| CommandError::History(x.to_string()))?;
+            if scores.is_empty() {
+                return Err(CommandError::NotFound(format!("scores for war {}", id)));
+            }
+
+            print(json, &scores, || score_lines(&scores));
+            return Ok(());
+        }
+        HistoryCommand::Leaderboard { war, sort, faction, csv } => {
+            let history = query(app)?;
+            let wars = history.wars().map_err(|x| CommandError::History(x.to_string()))?;
+            let war = match war {
+                Some(id) => wars.iter().find(|x| x.id == id)
+                    .ok_or_else(|| CommandError::NotFound(format!("war {}", id)))?,
+                None => wars.first().ok_or_else(|| CommandError::NotFound("recorded wars".to_string()))?,
+            };
+
+            // The key's only needed to find out which side we're on
+            let faction_id = match faction {
+                Some(x) => x,
+                None => {
+                    unlock(app)?;
+                    app.refresh_key_caps();
+                    app.key_caps.as_ref().and_then(|x| x.faction_id).ok_or(CommandError::NoFaction)?
+                }
+            };
+
+            let mut leaderboard = Leaderboard::load(&history, war, faction_id, app.clock.now())
+                .map_err(|x| CommandError::History(x.to_string()))?;
+            leaderboard.sort(sort, sort != Column::Name);
+
+            if csv {
+                print!("{}", leaderboard.to_csv());
+            }
+            else {
+                print(json, &leaderboard, || leaderboard_lines(&leaderboard));
+            }
+
+            return Ok(());
+        }
+        HistoryCommand::Activity { faction, csv } => {
+            let history = query(app)?;
+            let faction_id = match faction {
+                Some(x) => x,
+                None => *history.activity_factions().map_err(|x| CommandError::History(x.to_string()))?
+                    .first()
+                    .ok_or_else(|| CommandError::NotFound("recorded activity".to_string()))?,
+            };
+
+            let heatmap = Heatmap::load(&history, faction_id).map_err(|x| CommandError::History(x.to_string()))?;
+            if heatmap.is_empty() {
+                return Err(CommandError::NotFound(format!("activity of faction {}", faction_id)));
+            }
+
+            if csv {
+                print!("{}", heatmap.to_csv());
+            }
+            else {
+                print(json, &heatmap, || heatmap_lines(&heatmap));
+            }
+
+            return Ok(());
+        }
+        HistoryCommand::Prune => {
+            let count = query(app)?.prune(app.clock.now()).map_err(|x| CommandError::History(x.to_string()))?;
+
+            if !json {
+                println!("Deleted {} old record(s)", count);
+            }
+
+            return Ok(());
+        }
+        HistoryCommand::Watch { faction } => app.history_settings.watch = faction,
+        HistoryCommand::Record { enabled } => app.history_settings.enabled = enabled,
+        HistoryCommand::Retention { days } => app.history_settings.retention_days = days,
+    }
+
+    PersistedData::from(&*app)
+        .save(&app.paths.persistence)
+        .map_err(CommandError::Save)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&app.history_settings).unwrap());
+    }
+
+    Ok(())
+}
+
+/// A header, then one line per member
+fn leaderboard_lines(leaderboard: &Leaderboard) -> Vec<String> {
+    let row = |values: Vec<String>| format!("{:<20} {:>6} {:>9} {:>8} {:>7} {:>7} {:>13}",
+        values[0], values[1], values[2], values[3], values[4], values[5], values[6]);
+
+    let mut lines = vec![row(Column::ALL.iter().map(|x| x.title().to_string()).collect())];
+    lines.extend(leaderboard.members.iter()
+        .map(|member| row(Column::ALL.iter().map(|x| x.value(member)).collect())));
+    lines
+}
+
+/// A row of hours, then one row per day of averages. Hours never polled are
+/// left blank.
+fn heatmap_lines(heatmap: &Heatmap) -> Vec<String> {
+    let hours = (0..24).map(|x| format!("{:>3}", x)).collect::<String>();
+    let mut lines = vec![format!("TCT {}", hours)];
+
+    for (day, row) in heatmap.cells.iter().enumerate() {
+        let cells = row.iter()
+            .map(|x| x.map_or("  -".to_string(), |y| format!("{:>3.0}", y)))
+            .collect::<String>();
+        lines.push(format!("{} {}", DAYS[day], cells));
+    }
+
+    lines
+}
+
+/// One line per score change, with both sides' scores as they stood
+fn score_lines(scores: &[ScorePoint]) -> Vec<String> {
+    let mut current: Vec<&ScorePoint> = vec![];
+
+    scores.iter().map(|point| {
+        match current.iter_mut().find(|x| x.faction_id == point.faction_id) {
+            Some(x) => *x = point,
+            None => current.push(point),
+        }
+
+        let sides = current.iter()
+            .map(|x| format!("{} {}", x.name, x.score))
+            .collect::<Vec<_>>()
+            .join(" - ");
+
+        format!("{}  {}", point.at.format("%Y-%m-%d %H:%M:%S"), sides)
+    }).collect()
+}
\ No newline at end of file
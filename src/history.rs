@@ -0,0 +1,626 @@
+//! Local history of what torndkt has seen, in a SQLite database next to the
+//! settings: status changes, attacks, chain progress, war scores and when
+//! enemy faction members were last active. Nothing is stored unless it's
+//! changed since last time, and anything older than the retention period is
+//! pruned.
+#![warn(missing_docs)]
+
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::sync::Mutex;
+use chrono::{DateTime, TimeDelta, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use rusqlite::types::Type;
+use serde::de::IntoDeserializer;
+use serde::{Deserialize, Serialize};
+use torn_api::models::FactionAttackResult;
+use crate::api::api::GetInfoError;
+use crate::store::{Attack, Chain, Combatant, LastAction, RankedWar, User};
+
+/// Days of history kept, by default
+const DEFAULT_RETENTION_DAYS: u32 = 90;
+
+/// How often old history is pruned while running
+const PRUNE_INTERVAL: TimeDelta = TimeDelta::hours(1);
+
+/// How often an enemy faction's last actions are polled. Often enough that
+/// every hour gets at least one poll.
+const ACTIVITY_INTERVAL: TimeDelta = TimeDelta::minutes(15);
+
+/// Schema changes, in order. The database's `user_version` is how many have
+/// been applied, so to change the schema, add to the end.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE statuses (
+        at INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        faction_id INTEGER,
+        hospital_until INTEGER NOT NULL
+    );
+    CREATE INDEX statuses_user ON statuses (user_id, at);
+
+    CREATE TABLE chains (
+        at INTEGER NOT NULL,
+        faction_id INTEGER NOT NULL,
+        current INTEGER NOT NULL,
+        max INTEGER NOT NULL,
+        modifier REAL NOT NULL,
+        breaks_at INTEGER
+    );
+    CREATE INDEX chains_faction ON chains (faction_id, at);
+
+    CREATE TABLE wars (
+        id INTEGER PRIMARY KEY,
+        start INTEGER NOT NULL,
+        end INTEGER,
+        target INTEGER NOT NULL,
+        last_seen INTEGER NOT NULL
+    );
+
+    CREATE TABLE war_scores (
+        at INTEGER NOT NULL,
+        war_id INTEGER NOT NULL,
+        faction_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        score INTEGER NOT NULL,
+        chain INTEGER NOT NULL
+    );
+    CREATE INDEX war_scores_war ON war_scores (war_id, at);
+
+    CREATE TABLE attacks (
+        id INTEGER PRIMARY KEY,
+        faction_id INTEGER NOT NULL,
+        started INTEGER NOT NULL,
+        ended INTEGER NOT NULL,
+        attacker_id INTEGER,
+        attacker_name TEXT,
+        attacker_faction INTEGER,
+        defender_id INTEGER NOT NULL,
+        defender_name TEXT NOT NULL,
+        defender_faction INTEGER,
+        result TEXT NOT NULL,
+        respect_gain REAL NOT NULL,
+        respect_loss REAL NOT NULL,
+        chain INTEGER NOT NULL,
+        ranked_war INTEGER NOT NULL,
+        interrupted INTEGER NOT NULL,
+        fair_fight REAL NOT NULL
+    );
+    CREATE INDEX attacks_faction ON attacks (faction_id, ended);",
+
+    "CREATE TABLE activity (
+        faction_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        last_action INTEGER NOT NULL,
+        PRIMARY KEY (faction_id, user_id, last_action)
+    ) WITHOUT ROWID;
+    CREATE INDEX activity_time ON activity (faction_id, last_action);
+
+    CREATE TABLE activity_polls (
+        at INTEGER NOT NULL,
+        faction_id INTEGER NOT NULL
+    );
+    CREATE INDEX activity_polls_faction ON activity_polls (faction_id, at);",
+];
+
+/// Saved history settings
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HistorySettings {
+    /// Whether to record history at all
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    /// Days to keep history for. 0 keeps it forever.
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u32,
+
+    /// Faction whose activity is recorded even when we're not at war with
+    /// them. The enemy in a ranked war always is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watch: Option<u32>,
+}
+
+/// Serde default for [`HistorySettings::enabled`]
+fn default_enabled() -> bool {
+    true
+}
+
+/// Serde default for [`HistorySettings::retention_days`]
+fn default_retention_days() -> u32 {
+    DEFAULT_RETENTION_DAYS
+}
+
+impl Default for HistorySettings {
+    fn default() -> HistorySettings {
+        HistorySettings {
+            enabled: true,
+            retention_days: DEFAULT_RETENTION_DAYS,
+            watch: None,
+        }
+    }
+}
+
+impl HistorySettings {
+    /// Whether these are the defaults, so they can be left out of the file
+    pub fn is_default(&self) -> bool {
+        *self == HistorySettings::default()
+    }
+
+    /// How long history is kept, if it isn't kept forever
+    pub fn retention(&self) -> Option<TimeDelta> {
+        (self.retention_days > 0).then(|| TimeDelta::days(self.retention_days as i64))
+    }
+}
+
+/// Reasons history couldn't be read or written
+#[derive(Debug)]
+pub enum HistoryError {
+    /// Couldn't create the database's directory
+    Io(std::io::Error),
+    /// The database itself failed
+    Sqlite(rusqlite::Error),
+    /// The database is from a newer version of torndkt
+    UnsupportedVersion(u32),
+    /// Something to record couldn't be fetched from the API. Says what.
+    Fetch(&'static str, GetInfoError),
+}
+
+impl Display for HistoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HistoryError::Io(x) => write!(f, "File I/O error: {}", x),
+            HistoryError::Sqlite(x) => write!(f, "Database error: {}", x),
+            HistoryError::UnsupportedVersion(x) => {
+                write!(f, "History database is version {}, but only up to {} is supported", x, MIGRATIONS.len())
+            }
+            HistoryError::Fetch(what, x) => write!(f, "Couldn't fetch {}: {}. {}", what, x, x.help_text()),
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {}
+
+impl From<std::io::Error> for HistoryError {
+    fn from(err: std::io::Error) -> HistoryError {
+        HistoryError::Io(err)
+    }
+}
+
+impl From<rusqlite::Error> for HistoryError {
+    fn from(err: rusqlite::Error) -> HistoryError {
+        HistoryError::Sqlite(err)
+    }
+}
+
+/// A ranked war that's been seen
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WarRecord {
+    /// War id
+    pub id: u32,
+
+    /// When it started
+    pub start: DateTime<Utc>,
+
+    /// When it ended, if it has
+    pub end: Option<DateTime<Utc>>,
+
+    /// Lead needed to win
+    pub target: u32,
+}
+
+/// One side's war score at a point in time
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ScorePoint {
+    /// When the score was seen
+    pub at: DateTime<Utc>,
+
+    /// Faction id
+    pub faction_id: u32,
+
+    /// Faction name
+    pub name: String,
+
+    /// Score
+    pub score: u32,
+
+    /// Their chain at the time
+    pub chain: u32,
+}
+
+/// A faction's chain at a point in time
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ChainPoint {
+    /// When the chain was seen
+    pub at: DateTime<Utc>,
+
+    /// Hits in the chain
+    pub current: u32,
+
+    /// Next bonus milestone
+    pub max: u32,
+}
+
+/// How many of a faction's members were active in an hour it was polled in
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ActivityHour {
+    /// Start of the hour
+    pub hour: DateTime<Utc>,
+
+    /// Members whose last action was in it
+    pub active: u32,
+}
+
+/// The history database
+#[derive(Debug)]
+pub struct History {
+    connection: Mutex<Connection>,
+    retention: Option<TimeDelta>,
+    last_prune: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl History {
+    /// Open the database at `path`, creating it if it doesn't exist. A path
+    /// of `:memory:` makes one that only lasts as long as it's open.
+    pub fn open(path: &Path, settings: &HistorySettings) -> Result<History, HistoryError> {
+        if let Some(dir) = path.parent() && !dir.as_os_str().is_empty() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        History::new(Connection::open(path)?, settings)
+    }
+
+    /// Bring the schema up to date
+    fn new(mut connection: Connection, settings: &HistorySettings) -> Result<History, HistoryError> {
+        let version: u32 = connection.query_row("PRAGMA user_version", [], |x| x.get(0))?;
+        if version as usize > MIGRATIONS.len() {
+            return Err(HistoryError::UnsupportedVersion(version));
+        }
+
+        let transaction = connection.transaction()?;
+        for migration in &MIGRATIONS[version as usize..] {
+            transaction.execute_batch(migration)?;
+        }
+        transaction.pragma_update(None, "user_version", MIGRATIONS.len() as u32)?;
+        transaction.commit()?;
+
+        Ok(History {
+            connection: Mutex::new(connection),
+            retention: settings.retention(),
+            last_prune: Mutex::default(),
+        })
+    }
+
+    /// Record users whose status has changed since they were last recorded.
+    /// Returns how many were.
+    pub fn record_users(&self, users: &[User], at: DateTime<Utc>) -> Result<usize, HistoryError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let mut count = 0;
+
+        for user in users {
+            let last: Option<(String, Option<u32>, i64)> = transaction.query_row(
+                "SELECT name, faction_id, hospital_until FROM statuses WHERE user_id = ?1 ORDER BY at DESC LIMIT 1",
+                params![user.id],
+                |x| Ok((x.get(0)?, x.get(1)?, x.get(2)?)),
+            ).optional()?;
+
+            let current = (user.name.clone(), user.faction_id, user.hospital_until.timestamp());
+            if last.as_ref() == Some(&current) {
+                continue;
+            }
+
+            transaction.execute(
+                "INSERT INTO statuses (at, user_id, name, faction_id, hospital_until) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![at.timestamp(), user.id, current.0, current.1, current.2],
+            )?;
+            count += 1;
+        }
+
+        transaction.commit()?;
+        Ok(count)
+    }
+
+    /// Record a faction's chain, if it's moved since it was last recorded.
+    /// Returns whether it had.
+    pub fn record_chain(&self, faction_id: u32, chain: &Chain, at: DateTime<Utc>) -> Result<bool, HistoryError> {
+        let connection = self.connection.lock().unwrap();
+
+        let last: Option<(u32, u32)> = connection.query_row(
+            "SELECT current, max FROM chains WHERE faction_id = ?1 ORDER BY at DESC LIMIT 1",
+            params![faction_id],
+            |x| Ok((x.get(0)?, x.get(1)?)),
+        ).optional()?;
+
+        if last == Some((chain.current, chain.max)) {
+            return Ok(false);
+        }
+
+        connection.execute(
+            "INSERT INTO chains (at, faction_id, current, max, modifier, breaks_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![at.timestamp(), faction_id, chain.current, chain.max, chain.modifier, chain.breaks_at.map(|x| x.timestamp())],
+        )?;
+
+        Ok(true)
+    }
+
+    /// Record a ranked war, and the score of each side that's changed since
+    /// it was last recorded. Returns how many scores were.
+    pub fn record_war(&self, war: &RankedWar, at: DateTime<Utc>) -> Result<usize, HistoryError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+
+        transaction.execute(
+            "INSERT INTO wars (id, start, end, target, last_seen) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (id) DO UPDATE SET end = excluded.end, target = excluded.target, last_seen = excluded.last_seen",
+            params![war.id, war.start.timestamp(), war.end.map(|x| x.timestamp()), war.target, at.timestamp()],
+        )?;
+
+        let mut count = 0;
+        for side in &war.factions {
+            let last: Option<(u32, u32)> = transaction.query_row(
+                "SELECT score, chain FROM war_scores WHERE war_id = ?1 AND faction_id = ?2 ORDER BY at DESC LIMIT 1",
+                params![war.id, side.id],
+                |x| Ok((x.get(0)?, x.get(1)?)),
+            ).optional()?;
+
+            if last == Some((side.score, side.chain)) {
+                continue;
+            }
+
+            transaction.execute(
+                "INSERT INTO war_scores (at, war_id, faction_id, name, score, chain) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![at.timestamp(), war.id, side.id, side.name, side.score, side.chain],
+            )?;
+            count += 1;
+        }
+
+        transaction.commit()?;
+        Ok(count)
+    }
+
+    /// Record attacks from a faction's attack log. Ones already recorded are
+    /// skipped. Returns how many were new.
+    pub fn record_attacks(&self, faction_id: u32, attacks: &[Attack]) -> Result<usize, HistoryError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let mut count = 0;
+
+        for attack in attacks {
+            let attacker = attack.attacker.as_ref();
+            count += transaction.execute(
+                "INSERT OR IGNORE INTO attacks (id, faction_id, started, ended, attacker_id, attacker_name,
+                    attacker_faction, defender_id, defender_name, defender_faction, result, respect_gain,
+                    respect_loss, chain, ranked_war, interrupted, fair_fight)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+                params![
+                    attack.id, faction_id, attack.started.timestamp(), attack.ended.timestamp(),
+                    attacker.map(|x| x.id), attacker.map(|x| &x.name), attacker.and_then(|x| x.faction_id),
+                    attack.defender.id, attack.defender.name, attack.defender.faction_id, attack.result.to_string(),
+                    attack.respect_gain, attack.respect_loss, attack.chain, attack.ranked_war,
+                    attack.interrupted, attack.fair_fight,
+                ],
+            )?;
+        }
+
+        transaction.commit()?;
+        Ok(count)
+    }
+
+    /// When the last recorded attack in a faction's log ended, to fetch newer
+    /// ones from
+    pub fn last_attack(&self, faction_id: u32) -> Result<Option<DateTime<Utc>>, HistoryError> {
+        let connection = self.connection.lock().unwrap();
+        let ended: Option<i64> = connection.query_row(
+            "SELECT MAX(ended) FROM attacks WHERE faction_id = ?1",
+            params![faction_id],
+            |x| x.get(0),
+        )?;
+
+        Ok(ended.and_then(|x| DateTime::from_timestamp(x, 0)))
+    }
+
+    /// Every ranked war recorded, newest first
+    pub fn wars(&self) -> Result<Vec<WarRecord>, HistoryError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare("SELECT id, start, end, target FROM wars ORDER BY start DESC")?;
+
+        let wars = statement.query_map([], |x| {
+            Ok(WarRecord {
+                id: x.get(0)?,
+                start: timestamp(x.get(1)?),
+                end: x.get::<_, Option<i64>>(2)?.map(timestamp),
+                target: x.get(3)?,
+            })
+        })?;
+
+        Ok(wars.collect::<Result<_, _>>()?)
+    }
+
+    /// Both sides' scores over the course of a war, oldest first
+    pub fn war_scores(&self, war_id: u32) -> Result<Vec<ScorePoint>, HistoryError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT at, faction_id, name, score, chain FROM war_scores WHERE war_id = ?1 ORDER BY at, faction_id"
+        )?;
+
+        let scores = statement.query_map(params![war_id], |x| {
+            Ok(ScorePoint {
+                at: timestamp(x.get(0)?),
+                faction_id: x.get(1)?,
+                name: x.get(2)?,
+                score: x.get(3)?,
+                chain: x.get(4)?,
+            })
+        })?;
+
+        Ok(scores.collect::<Result<_, _>>()?)
+    }
+
+    /// A faction's chain between two times, oldest first
+    pub fn chains(&self, faction_id: u32, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<ChainPoint>, HistoryError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT at, current, max FROM chains WHERE faction_id = ?1 AND at BETWEEN ?2 AND ?3 ORDER BY at"
+        )?;
+
+        let chains = statement.query_map(params![faction_id, from.timestamp(), to.timestamp()], |x| {
+            Ok(ChainPoint {
+                at: timestamp(x.get(0)?),
+                current: x.get(1)?,
+                max: x.get(2)?,
+            })
+        })?;
+
+        Ok(chains.collect::<Result<_, _>>()?)
+    }
+
+    /// Attacks in a faction's log that ended between two times, oldest first
+    pub fn attacks(&self, faction_id: u32, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<Attack>, HistoryError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT id, started, ended, attacker_id, attacker_name, attacker_faction, defender_id, defender_name,
+                defender_faction, result, respect_gain, respect_loss, chain, ranked_war, interrupted, fair_fight
+             FROM attacks WHERE faction_id = ?1 AND ended BETWEEN ?2 AND ?3 ORDER BY ended, id"
+        )?;
+
+        let attacks = statement.query_map(params![faction_id, from.timestamp(), to.timestamp()], |x| {
+            let attacker = match x.get::<_, Option<u32>>(3)? {
+                Some(id) => Some(Combatant { id, name: x.get(4)?, faction_id: x.get(5)? }),
+                None => None,
+            };
+
+            Ok(Attack {
+                id: x.get(0)?,
+                started: timestamp(x.get(1)?),
+                ended: timestamp(x.get(2)?),
+                attacker,
+                defender: Combatant { id: x.get(6)?, name: x.get(7)?, faction_id: x.get(8)? },
+                result: attack_result(9, &x.get::<_, String>(9)?)?,
+                respect_gain: x.get(10)?,
+                respect_loss: x.get(11)?,
+                chain: x.get(12)?,
+                ranked_war: x.get(13)?,
+                interrupted: x.get(14)?,
+                fair_fight: x.get(15)?,
+            })
+        })?;
+
+        Ok(attacks.collect::<Result<_, _>>()?)
+    }
+
+    /// Whether a faction's activity hasn't been polled for a while, or ever
+    pub fn activity_due(&self, faction_id: u32, now: DateTime<Utc>) -> Result<bool, HistoryError> {
+        let connection = self.connection.lock().unwrap();
+        let last: Option<i64> = connection.query_row(
+            "SELECT MAX(at) FROM activity_polls WHERE faction_id = ?1",
+            params![faction_id],
+            |x| x.get(0),
+        )?;
+
+        Ok(last.is_none_or(|x| now - timestamp(x) >= ACTIVITY_INTERVAL))
+    }
+
+    /// Record a poll of a faction's members' last actions. Returns how many
+    /// hadn't been seen before.
+    pub fn record_activity(&self, faction_id: u32, actions: &[LastAction], at: DateTime<Utc>) -> Result<usize, HistoryError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+
+        transaction.execute(
+            "INSERT INTO activity_polls (at, faction_id) VALUES (?1, ?2)",
+            params![at.timestamp(), faction_id],
+        )?;
+
+        let mut count = 0;
+        for action in actions {
+            count += transaction.execute(
+                "INSERT OR IGNORE INTO activity (faction_id, user_id, last_action) VALUES (?1, ?2, ?3)",
+                params![faction_id, action.user_id, action.at.timestamp()],
+            )?;
+        }
+
+        transaction.commit()?;
+        Ok(count)
+    }
+
+    /// Factions whose activity has been recorded, most recently polled first
+    pub fn activity_factions(&self) -> Result<Vec<u32>, HistoryError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT faction_id FROM activity_polls GROUP BY faction_id ORDER BY MAX(at) DESC"
+        )?;
+
+        let factions = statement.query_map([], |x| x.get(0))?;
+        Ok(factions.collect::<Result<_, _>>()?)
+    }
+
+    /// How many of a faction's members were active in each hour it was
+    /// polled in, oldest first. Hours it wasn't polled in are left out, as
+    /// nothing's known about them.
+    pub fn activity(&self, faction_id: u32) -> Result<Vec<ActivityHour>, HistoryError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT polls.hour, COUNT(DISTINCT activity.user_id)
+             FROM (SELECT DISTINCT at / 3600 AS hour FROM activity_polls WHERE faction_id = ?1) polls
+             LEFT JOIN activity ON activity.faction_id = ?1
+                AND activity.last_action BETWEEN polls.hour * 3600 AND polls.hour * 3600 + 3599
+             GROUP BY polls.hour
+             ORDER BY polls.hour"
+        )?;
+
+        let hours = statement.query_map(params![faction_id], |x| {
+            Ok(ActivityHour {
+                hour: timestamp(x.get::<_, i64>(0)? * 3600),
+                active: x.get(1)?,
+            })
+        })?;
+
+        Ok(hours.collect::<Result<_, _>>()?)
+    }
+
+    /// Delete everything older than the retention period at `now`. Returns
+    /// how many rows went.
+    pub fn prune(&self, now: DateTime<Utc>) -> Result<usize, HistoryError> {
+        *self.last_prune.lock().unwrap() = Some(now);
+
+        let Some(retention) = self.retention else { return Ok(0) };
+        let cutoff = (now - retention).timestamp();
+
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let mut count = 0;
+
+        let tables = [
+            ("statuses", "at"), ("chains", "at"), ("war_scores", "at"), ("attacks", "ended"), ("wars", "last_seen"),
+            ("activity", "last_action"), ("activity_polls", "at"),
+        ];
+
+        for (table, column) in tables {
+            count += transaction.execute(&format!("DELETE FROM {} WHERE {} < ?1", table, column), params![cutoff])?;
+        }
+
+        transaction.commit()?;
+        Ok(count)
+    }
+
+    /// Prune if it hasn't been done for a while
+    pub fn prune_if_due(&self, now: DateTime<Utc>) -> Result<usize, HistoryError> {
+        let due = self.last_prune.lock().unwrap().is_none_or(|x| now - x >= PRUNE_INTERVAL);
+        if !due {
+            return Ok(0);
+        }
+
+        self.prune(now)
+    }
+}
+
+/// Read back an attack result, which is stored as its name
+fn attack_result(column: usize, name: &str) -> rusqlite::Result<FactionAttackResult> {
+    FactionAttackResult::deserialize(name.into_deserializer())
+        .map_err(|x: serde::de::value::Error| rusqlite::Error::FromSqlConversionFailure(column, Type::Text, Box::new(x)))
+}
+
+/// Convert a stored Unix timestamp
+fn timestamp(secs: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(secs, 0).unwrap_or_default()
+}
\ No newline at end of file
@@ -0,0 +1,93 @@
+use std::path::Path;
+use chrono::TimeDelta;
+use rusqlite::Connection;
+use torn_api::models::FactionAttackResult;
+use crate::history::{History, HistoryError, HistorySettings};
+use crate::store::{Chain, User};
+use crate::test_history::{attacks, memory, now, war};
+
+#[test]
+fn only_changes_are_recorded() {
+    let history = memory();
+    let mut user = User { id: 1, name: "Test".to_string(), hospital_until: now(), faction_id: Some(100) };
+
+    assert_eq!(history.record_users(&[user.clone()], now()).unwrap(), 1);
+    assert_eq!(history.record_users(&[user.clone()], now() + TimeDelta::seconds(30)).unwrap(), 0);
+    user.hospital_until += TimeDelta::minutes(5);
+    assert_eq!(history.record_users(&[user], now() + TimeDelta::seconds(60)).unwrap(), 1);
+
+    let chain = Chain { current: 10, max: 25, breaks_at: None, cooldown_until: None, modifier: 1.0 };
+    assert!(history.record_chain(100, &chain, now()).unwrap());
+    assert!(!history.record_chain(100, &chain, now()).unwrap());
+
+    assert_eq!(history.record_war(&war(0, 0, None), now()).unwrap(), 2);
+    assert_eq!(history.record_war(&war(0, 0, None), now() + TimeDelta::seconds(30)).unwrap(), 0);
+    assert_eq!(history.record_war(&war(12, 0, None), now() + TimeDelta::seconds(60)).unwrap(), 1);
+
+    let wars = history.wars().unwrap();
+    assert_eq!(wars.len(), 1);
+    assert_eq!((wars[0].id, wars[0].target), (7, 3000));
+
+    let scores = history.war_scores(7).unwrap()
+        .iter()
+        .map(|x| (x.at - now(), x.faction_id, x.score))
+        .collect::<Vec<_>>();
+    assert_eq!(scores, [
+        (TimeDelta::zero(), 100, 0),
+        (TimeDelta::zero(), 200, 0),
+        (TimeDelta::seconds(60), 100, 12),
+    ]);
+}
+
+#[test]
+fn attacks_are_recorded_once() {
+    let history = memory();
+    let attacks = attacks();
+
+    assert_eq!(attacks[0].attacker.as_ref().unwrap().name, "Alice");
+    assert_eq!(attacks[0].result, FactionAttackResult::Hospitalized);
+    assert_eq!(attacks[4].attacker, None);
+
+    assert_eq!(history.last_attack(100).unwrap(), None);
+    assert_eq!(history.record_attacks(100, &attacks[..3]).unwrap(), 3);
+    assert_eq!(history.record_attacks(100, &attacks).unwrap(), 2);
+    assert_eq!(history.last_attack(100).unwrap(), Some(now() + TimeDelta::seconds(270)));
+}
+
+#[test]
+fn prunes_old_history() {
+    let settings = HistorySettings { enabled: true, retention_days: 1, watch: None };
+    let history = History::open(Path::new(":memory:"), &settings).unwrap();
+
+    history.record_war(&war(0, 0, None), now()).unwrap();
+    history.record_attacks(100, &attacks()).unwrap();
+    history.record_war(&war(5, 0, None), now() + TimeDelta::days(2)).unwrap();
+
+    // The war was seen recently, but its first scores and the attacks are old
+    assert_eq!(history.prune(now() + TimeDelta::days(2)).unwrap(), 7);
+    assert_eq!(history.war_scores(7).unwrap().len(), 1);
+    assert_eq!(history.wars().unwrap().len(), 1);
+    assert_eq!(history.last_attack(100).unwrap(), None);
+
+    // Only due once an hour
+    assert_eq!(history.prune_if_due(now() + TimeDelta::days(3)).unwrap(), 0);
+}
+
+#[test]
+fn newer_databases_are_refused() {
+    let path = std::env::temp_dir().join(format!("torndkt-history-{}.sqlite", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    History::open(&path, &HistorySettings::default()).unwrap()
+        .record_war(&war(1, 2, None), now()).unwrap();
+
+    // Opening again keeps what's there
+    let history = History::open(&path, &HistorySettings::default()).unwrap();
+    assert_eq!(history.war_scores(7).unwrap().len(), 2);
+    drop(history);
+
+    Connection::open(&path).unwrap().pragma_update(None, "user_version", 99).unwrap();
+    assert!(matches!(History::open(&path, &HistorySettings::default()), Err(HistoryError::UnsupportedVersion(99))));
+
+    std::fs::remove_file(&path).unwrap();
+}
\ No newline at end of file
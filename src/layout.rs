@@ -0,0 +1,289 @@
+//! Sharing monitor layouts between members, as a copy-pasteable code or a
+//! JSON file. Layouts never include API keys.
+#![warn(missing_docs)]
+
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use serde::{Deserialize, Serialize};
+use crate::monitors::selection::MonitorList;
+use crate::profile::{MonitorGroup, Profile};
+
+/// Prefix for layout codes, including the format version
+const CODE_PREFIX: &str = "TDKT1.";
+
+/// Current layout format version
+const LAYOUT_VERSION: u32 = 1;
+
+/// Biggest layout that will be imported, to stop a malicious code from
+/// decompressing into something enormous
+const MAX_LAYOUT_SIZE: u64 = 1024 * 1024;
+
+/// Settings that get shared along with a layout. They're only taken on
+/// when replacing, see [`apply`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LayoutSettings {
+    /// UI scale
+    pub uiscale: f32,
+}
+
+/// A shareable layout
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Layout {
+    /// Format version
+    pub version: u32,
+
+    /// Name of the profile it was exported from
+    pub name: String,
+
+    /// Monitor groups
+    pub groups: Vec<MonitorGroup>,
+
+    /// Shared settings
+    pub settings: LayoutSettings,
+}
+
+/// Reasons a layout couldn't be imported
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutError {
+    /// Not a layout code or JSON
+    NotALayout,
+    /// Looks like a layout code, but is damaged
+    Corrupt,
+    /// Too big to import
+    TooLarge,
+    /// From a newer version of torndkt
+    UnsupportedVersion(u32),
+    /// Decoded, but the contents don't make sense
+    Invalid(String),
+    /// Couldn't read or write a layout file
+    Io(String),
+}
+
+impl Display for LayoutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayoutError::NotALayout => write!(f, "Not a layout code or layout file"),
+            LayoutError::Corrupt => write!(f, "Layout code is damaged. Check it was copied in full."),
+            LayoutError::TooLarge => write!(f, "Layout is too large"),
+            LayoutError::UnsupportedVersion(x) => write!(f, "Layout is from a newer version of torndkt (format {})", x),
+            LayoutError::Invalid(x) => write!(f, "Invalid layout: {}", x),
+            LayoutError::Io(x) => write!(f, "File error: {}", x),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+impl Layout {
+    /// Take a layout from a profile
+    pub fn from_profile(profile: &Profile) -> Layout {
+        Layout {
+            version: LAYOUT_VERSION,
+            name: profile.name.clone(),
+            groups: profile.groups.clone(),
+            settings: LayoutSettings { uiscale: profile.uiscale },
+        }
+    }
+
+    /// Encode as a compact code for pasting into chat
+    pub fn to_code(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap();
+
+        let mut encoder = DeflateEncoder::new(vec![], Compression::best());
+        encoder.write_all(&json).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        format!("{}{}", CODE_PREFIX, BASE64.encode(compressed))
+    }
+
+    /// Encode as pretty JSON for saving to a file
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Decode either a layout code or layout JSON, and check it makes sense
+    pub fn parse(input: &str) -> Result<Layout, LayoutError> {
+        let input = input.trim();
+
+        let json = if let Some(code) = input.strip_prefix(CODE_PREFIX) {
+            // Chat clients like to wrap long codes
+            let code = code.split_whitespace().collect::<String>();
+            let compressed = BASE64.decode(code).map_err(|_| LayoutError::Corrupt)?;
+
+            let mut json = vec![];
+            DeflateDecoder::new(compressed.as_slice())
+                .take(MAX_LAYOUT_SIZE + 1)
+                .read_to_end(&mut json)
+                .map_err(|_| LayoutError::Corrupt)?;
+
+            if json.len() as u64 > MAX_LAYOUT_SIZE {
+                return Err(LayoutError::TooLarge);
+            }
+
+            json
+        }
+        else if input.starts_with('{') {
+            if input.len() as u64 > MAX_LAYOUT_SIZE {
+                return Err(LayoutError::TooLarge);
+            }
+
+            input.as_bytes().to_vec()
+        }
+        else {
+            return Err(LayoutError::NotALayout);
+        };
+
+        let value: serde_json::Value = serde_json::from_slice(&json).map_err(|_| LayoutError::Corrupt)?;
+        let version = value.get("version")
+            .and_then(|x| x.as_u64())
+            .ok_or(LayoutError::NotALayout)?;
+
+        if version > LAYOUT_VERSION as u64 {
+            return Err(LayoutError::UnsupportedVersion(version as u32));
+        }
+
+        let layout: Layout = serde_json::from_value(value)
+            .map_err(|x| LayoutError::Invalid(x.to_string()))?;
+        layout.validate()?;
+
+        Ok(layout)
+    }
+
+    /// Check the contents make sense
+    fn validate(&self) -> Result<(), LayoutError> {
+        for group in &self.groups {
+            if group.name.trim().is_empty() {
+                return Err(LayoutError::Invalid("group with no name".to_string()));
+            }
+
+            for monitor in &group.monitors {
+                match monitor {
+                    MonitorList::None => {
+                        return Err(LayoutError::Invalid(format!("empty monitor in group \"{}\"", group.name)));
+                    }
+                    MonitorList::Simple(x) if x.id == 0 => {
+                        return Err(LayoutError::Invalid(format!("monitor with no id in group \"{}\"", group.name)));
+                    }
+                    MonitorList::Leaderboard(x) if x.top == 0 => {
+                        return Err(LayoutError::Invalid(format!("leaderboard showing no one in group \"{}\"", group.name)));
+                    }
+                    MonitorList::Simple(_) | MonitorList::Leaderboard(_) => {}
+                }
+            }
+        }
+
+        if !(0.5..=4.0).contains(&self.settings.uiscale) {
+            return Err(LayoutError::Invalid(format!("UI scale {} is out of range", self.settings.uiscale)));
+        }
+
+        Ok(())
+    }
+
+    /// Save as JSON to a file
+    pub fn save(&self, path: &str) -> Result<(), LayoutError> {
+        std::fs::write(path, self.to_json()).map_err(|x| LayoutError::Io(x.to_string()))
+    }
+
+    /// Read a layout file for [`Layout::parse`]. Files too big to be a layout
+    /// aren't read at all.
+    pub fn read_file(path: &str) -> Result<String, LayoutError> {
+        let io = |x: std::io::Error| LayoutError::Io(x.to_string());
+
+        if std::fs::metadata(path).map_err(io)?.len() > MAX_LAYOUT_SIZE {
+            return Err(LayoutError::TooLarge);
+        }
+
+        std::fs::read_to_string(path).map_err(io)
+    }
+}
+
+/// How to deal with an imported layout clashing with what's already there
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ImportMode {
+    /// Add new groups and monitors, leaving existing ones alone. Groups with
+    /// the same name are combined, and monitors already in them are skipped.
+    #[default]
+    Merge,
+
+    /// Throw away the current groups and use the imported ones
+    Replace,
+}
+
+/// What an import will do, for showing before it's applied
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ImportPreview {
+    /// Groups that will be created
+    pub new_groups: Vec<String>,
+
+    /// Monitors that will be added, with the group they'll go in
+    pub added: Vec<(String, MonitorList)>,
+
+    /// Monitors skipped because they're already there
+    pub duplicates: usize,
+
+    /// Groups that will be removed (only when replacing)
+    pub removed_groups: Vec<String>,
+}
+
+/// Work out what importing `layout` into `profile` would do
+pub fn preview(profile: &Profile, layout: &Layout, mode: ImportMode) -> ImportPreview {
+    let mut preview = ImportPreview::default();
+    let mut result = profile.clone();
+    apply_inner(&mut result, layout, mode, Some(&mut preview));
+
+    preview
+}
+
+/// Import `layout` into `profile`. Replacing also takes the layout's
+/// settings.
+pub fn apply(profile: &mut Profile, layout: &Layout, mode: ImportMode) {
+    apply_inner(profile, layout, mode, None);
+}
+
+/// Shared logic for [`preview`] and [`apply`]
+fn apply_inner(profile: &mut Profile, layout: &Layout, mode: ImportMode, mut preview: Option<&mut ImportPreview>) {
+    if mode == ImportMode::Replace {
+        if let Some(preview) = preview.as_deref_mut() {
+            preview.removed_groups = profile.groups.iter().map(|x| x.name.clone()).collect();
+        }
+
+        profile.groups.clear();
+        profile.uiscale = layout.settings.uiscale;
+    }
+
+    for group in &layout.groups {
+        let index = match profile.groups.iter().position(|x| x.name == group.name) {
+            Some(x) => x,
+            None => {
+                if let Some(preview) = preview.as_deref_mut() {
+                    preview.new_groups.push(group.name.clone());
+                }
+
+                profile.groups.push(MonitorGroup::new(&group.name));
+                profile.groups.len() - 1
+            }
+        };
+
+        for monitor in &group.monitors {
+            let existing = &mut profile.groups[index].monitors;
+
+            if existing.iter().any(|x| x.same_target(monitor)) {
+                if let Some(preview) = preview.as_deref_mut() {
+                    preview.duplicates += 1;
+                }
+                continue;
+            }
+
+            if let Some(preview) = preview.as_deref_mut() {
+                preview.added.push((group.name.clone(), monitor.clone()));
+            }
+
+            existing.push(monitor.clone());
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,94 @@
+use crate::layout::{apply, preview, ImportMode, Layout, LayoutError};
+use crate::monitors::basic::SimpleHospMonitor;
+use crate::monitors::selection::MonitorList;
+use crate::profile::{MonitorGroup, Profile};
+
+/// A simple monitor for a user
+fn simple(id: u32) -> MonitorList {
+    MonitorList::Simple(SimpleHospMonitor::new(id))
+}
+
+/// A profile with one group per entry in `groups`
+fn profile(groups: &[(&str, &[u32])]) -> Profile {
+    let mut profile = Profile::new("Test");
+    profile.groups = groups.iter()
+        .map(|(name, ids)| MonitorGroup {
+            name: name.to_string(),
+            monitors: ids.iter().map(|x| simple(*x)).collect(),
+        })
+        .collect();
+
+    profile
+}
+
+#[test]
+fn code_round_trip() {
+    let layout = Layout::from_profile(&profile(&[("Enemies", &[1, 2, 3])]));
+    let code = layout.to_code();
+
+    assert!(code.starts_with("TDKT1."));
+    assert_eq!(Layout::parse(&code).unwrap(), layout);
+    assert_eq!(Layout::parse(&layout.to_json()).unwrap(), layout);
+
+    // Wrapped by a chat client
+    let (start, end) = code.split_at(20);
+    assert_eq!(Layout::parse(&format!("{}\n{}", start, end)).unwrap(), layout);
+}
+
+#[test]
+fn reject_bad_input() {
+    assert_eq!(Layout::parse("hello"), Err(LayoutError::NotALayout));
+    assert_eq!(Layout::parse("TDKT1.!!!!"), Err(LayoutError::Corrupt));
+
+    let layout = Layout::from_profile(&profile(&[("Enemies", &[0])]));
+    assert!(matches!(Layout::parse(&layout.to_code()), Err(LayoutError::Invalid(_))));
+
+    let newer = "{\"version\": 99, \"name\": \"x\", \"groups\": [], \"settings\": {\"uiscale\": 1.5}}";
+    assert_eq!(Layout::parse(newer), Err(LayoutError::UnsupportedVersion(99)));
+}
+
+#[test]
+fn merge_skips_duplicates() {
+    let mut current = profile(&[("Enemies", &[1, 2])]);
+    let layout = Layout::from_profile(&profile(&[("Enemies", &[2, 3]), ("Chain", &[4])]));
+
+    let planned = preview(&current, &layout, ImportMode::Merge);
+    assert_eq!(planned.new_groups, vec!["Chain".to_string()]);
+    assert_eq!(planned.added.len(), 2);
+    assert_eq!(planned.duplicates, 1);
+    assert!(planned.removed_groups.is_empty());
+
+    apply(&mut current, &layout, ImportMode::Merge);
+    assert_eq!(current, profile(&[("Enemies", &[1, 2, 3]), ("Chain", &[4])]));
+}
+
+#[test]
+fn replace_drops_existing() {
+    let mut current = profile(&[("Enemies", &[1, 2])]);
+    let mut layout = Layout::from_profile(&profile(&[("Chain", &[4])]));
+    layout.settings.uiscale = 2.0;
+
+    let planned = preview(&current, &layout, ImportMode::Replace);
+    assert_eq!(planned.removed_groups, vec!["Enemies".to_string()]);
+
+    // Merging leaves the UI scale alone, replacing takes it
+    let mut merged = current.clone();
+    apply(&mut merged, &layout, ImportMode::Merge);
+    assert_eq!(merged.uiscale, 1.5);
+    apply(&mut current, &layout, ImportMode::Replace);
+    assert_eq!(current, Profile { uiscale: 2.0, ..profile(&[("Chain", &[4])]) });
+}
+
+#[test]
+fn big_files_arent_read() {
+    let path = std::env::temp_dir().join(format!("torndkt-layout-{}.json", std::process::id()));
+    let layout = Layout::from_profile(&profile(&[("Enemies", &[1])]));
+
+    layout.save(path.to_str().unwrap()).unwrap();
+    assert_eq!(Layout::parse(&Layout::read_file(path.to_str().unwrap()).unwrap()).unwrap(), layout);
+
+    std::fs::File::options().write(true).open(&path).unwrap().set_len(2 * 1024 * 1024).unwrap();
+    assert_eq!(Layout::read_file(path.to_str().unwrap()), Err(LayoutError::TooLarge));
+
+    std::fs::remove_file(&path).unwrap();
+}
\ No newline at end of file
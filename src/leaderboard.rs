@@ -0,0 +1,201 @@
+//! Who's pulling their weight in a ranked war: attacks from the history
+//! database, added up per member of our faction. The whole table is a tab of
+//! the war history window, and the top few for the latest war can be shown
+//! in a group as a [`LeaderboardMonitor`](crate::monitors::leaderboard::LeaderboardMonitor).
+#![warn(missing_docs)]
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use torn_api::models::FactionAttackResult;
+use crate::charts::our_side;
+use crate::history::{History, HistoryError, WarRecord};
+use crate::store::Attack;
+use crate::util::csv_field;
+
+/// A member's contribution to a war
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct MemberStats {
+    /// User id
+    pub id: u32,
+
+    /// Username
+    pub name: String,
+
+    /// Successful hits on the enemy that counted for the war
+    pub hits: u32,
+
+    /// Respect gained from those hits
+    pub respect: f64,
+
+    /// Attacks where someone else finished the target off
+    pub assists: u32,
+
+    /// Attacks they made that didn't win
+    pub losses: u32,
+
+    /// Average fair fight of their war hits
+    pub fair_fight: f64,
+
+    /// Successful hits during the war that didn't count for it
+    pub outside_hits: u32,
+}
+
+/// Column a leaderboard can be sorted by
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[value(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Column {
+    /// Username
+    Name,
+    /// War hits
+    Hits,
+    /// Respect gained
+    Respect,
+    /// Assists
+    Assists,
+    /// Losses
+    Losses,
+    /// Average fair fight
+    FairFight,
+    /// Hits outside the war
+    OutsideHits,
+}
+
+impl Column {
+    /// Every column, in display order
+    pub const ALL: [Column; 7] = [Column::Name, Column::Hits, Column::Respect, Column::Assists, Column::Losses, Column::FairFight, Column::OutsideHits];
+
+    /// Column heading
+    pub fn title(self) -> &'static str {
+        match self {
+            Column::Name => "Name",
+            Column::Hits => "Hits",
+            Column::Respect => "Respect",
+            Column::Assists => "Assists",
+            Column::Losses => "Losses",
+            Column::FairFight => "Avg FF",
+            Column::OutsideHits => "Outside hits",
+        }
+    }
+
+    /// The column's value for a member, as shown
+    pub fn value(self, member: &MemberStats) -> String {
+        match self {
+            Column::Name => member.name.clone(),
+            Column::Hits => member.hits.to_string(),
+            Column::Respect => format!("{:.2}", member.respect),
+            Column::Assists => member.assists.to_string(),
+            Column::Losses => member.losses.to_string(),
+            Column::FairFight => format!("{:.2}", member.fair_fight),
+            Column::OutsideHits => member.outside_hits.to_string(),
+        }
+    }
+
+    /// Order two members by this column, smallest first
+    fn compare(self, a: &MemberStats, b: &MemberStats) -> std::cmp::Ordering {
+        match self {
+            Column::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            Column::Hits => a.hits.cmp(&b.hits),
+            Column::Respect => a.respect.total_cmp(&b.respect),
+            Column::Assists => a.assists.cmp(&b.assists),
+            Column::Losses => a.losses.cmp(&b.losses),
+            Column::FairFight => a.fair_fight.total_cmp(&b.fair_fight),
+            Column::OutsideHits => a.outside_hits.cmp(&b.outside_hits),
+        }
+    }
+}
+
+/// Every member's contribution to one war
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Leaderboard {
+    /// War id
+    pub war_id: u32,
+
+    /// Members who made any attacks during it
+    pub members: Vec<MemberStats>,
+}
+
+impl Leaderboard {
+    /// Add up attacks by members of `faction_id` that ended between `from`
+    /// and `to`. Sorted by respect, most first.
+    pub fn build(war_id: u32, faction_id: u32, from: DateTime<Utc>, to: DateTime<Utc>, attacks: &[Attack]) -> Leaderboard {
+        let mut members = HashMap::<u32, MemberStats>::new();
+        let mut fair_fight = HashMap::<u32, f64>::new();
+
+        for attack in attacks {
+            let Some(attacker) = attack.attacker.as_ref().filter(|x| x.faction_id == Some(faction_id)) else { continue };
+            if attack.ended < from || attack.ended > to {
+                continue;
+            }
+
+            let member = members.entry(attacker.id).or_insert_with(|| MemberStats {
+                id: attacker.id,
+                name: attacker.name.clone(),
+                ..MemberStats::default()
+            });
+
+            match attack.result {
+                FactionAttackResult::Assist => member.assists += 1,
+                // The attacker didn't win
+                FactionAttackResult::Lost | FactionAttackResult::Stalemate
+                | FactionAttackResult::Timeout | FactionAttackResult::Escape => member.losses += 1,
+                _ if attack.respect_gain > 0.0 && attack.ranked_war => {
+                    member.hits += 1;
+                    member.respect += attack.respect_gain;
+                    *fair_fight.entry(attacker.id).or_default() += attack.fair_fight;
+                }
+                _ if attack.respect_gain > 0.0 => member.outside_hits += 1,
+                _ => {}
+            }
+        }
+
+        let mut members = members.into_values()
+            .map(|mut x| {
+                if x.hits > 0 {
+                    x.fair_fight = fair_fight[&x.id] / x.hits as f64;
+                }
+                x
+            })
+            .collect::<Vec<_>>();
+
+        // Ties go by name, so the order doesn't depend on the hash map
+        members.sort_by(|a, b| Column::Name.compare(a, b));
+
+        let mut leaderboard = Leaderboard { war_id, members };
+        leaderboard.sort(Column::Respect, true);
+        leaderboard
+    }
+
+    /// Load the attacks made during `war` and add them up. `faction_id` is
+    /// our side, as for [`our_side`].
+    pub fn load(history: &History, war: &WarRecord, faction_id: u32, now: DateTime<Utc>) -> Result<Leaderboard, HistoryError> {
+        let end = war.end.unwrap_or(now);
+        let ours = our_side(&history.war_scores(war.id)?, faction_id);
+        let attacks = history.attacks(ours, war.start, end)?;
+
+        Ok(Leaderboard::build(war.id, ours, war.start, end, &attacks))
+    }
+
+    /// Sort by a column. Members that tie stay in the order they were in.
+    pub fn sort(&mut self, column: Column, descending: bool) {
+        self.members.sort_by(|a, b| {
+            let order = column.compare(a, b);
+            if descending { order.reverse() } else { order }
+        });
+    }
+
+    /// The leaderboard as CSV, one row per member
+    pub fn to_csv(&self) -> String {
+        let mut csv = "war,id,name,hits,respect,assists,losses,fair_fight,outside_hits\n".to_string();
+
+        for x in &self.members {
+            writeln!(csv, "{},{},{},{},{},{},{},{},{}", self.war_id, x.id, csv_field(&x.name), x.hits,
+                x.respect, x.assists, x.losses, x.fair_fight, x.outside_hits).unwrap();
+        }
+
+        csv
+    }
+}
\ No newline at end of file
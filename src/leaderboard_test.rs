@@ -0,0 +1,46 @@
+use chrono::TimeDelta;
+use crate::history::History;
+use crate::leaderboard::{Column, Leaderboard};
+use crate::test_history::{attacks, memory, now, war};
+
+fn history() -> History {
+    let history = memory();
+
+    history.record_war(&war(0, 0, None), now()).unwrap();
+    history.record_attacks(100, &attacks()).unwrap();
+
+    history
+}
+
+#[test]
+fn member_stats() {
+    let history = history();
+    let war = history.wars().unwrap().remove(0);
+    let leaderboard = Leaderboard::load(&history, &war, 100, now() + TimeDelta::hours(1)).unwrap();
+
+    // Eve's hit on Bob and the stealthed attack aren't ours
+    let names = leaderboard.members.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["Alice", "Bob"]);
+
+    let alice = &leaderboard.members[0];
+    assert_eq!((alice.hits, alice.respect, alice.fair_fight, alice.outside_hits), (1, 10.5, 3.0, 1));
+    assert_eq!((alice.assists, alice.losses), (0, 0));
+
+    let bob = &leaderboard.members[1];
+    assert_eq!((bob.hits, bob.assists, bob.fair_fight), (0, 1, 0.0));
+}
+
+#[test]
+fn sorting_and_export() {
+    let history = history();
+    let war = history.wars().unwrap().remove(0);
+    let mut leaderboard = Leaderboard::load(&history, &war, 100, now() + TimeDelta::hours(1)).unwrap();
+
+    leaderboard.sort(Column::Assists, true);
+    assert_eq!(leaderboard.members[0].name, "Bob");
+
+    leaderboard.sort(Column::Name, false);
+    assert_eq!(leaderboard.to_csv(), "war,id,name,hits,respect,assists,losses,fair_fight,outside_hits\n\
+        7,1,Alice,1,10.5,0,0,3,1\n\
+        7,2,Bob,0,0,1,0,0,0\n");
+}
\ No newline at end of file
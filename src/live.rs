@@ -0,0 +1,250 @@
+//! Opt-in local server exposing what torndkt is showing, so bots and overlays
+//! can read it without their own API key. Only listens on localhost.
+//!
+//! `GET /snapshot` has everything, `/monitors`, `/war` and `/chain` have parts
+//! of it, and `/events` is a server-sent events stream with a `snapshot`
+//! event each time something changes.
+//!
+//! Requests are refused unless their `Host` is `127.0.0.1` or `localhost` with
+//! our port, so a web page can't reach the server by pointing a domain of its
+//! own at 127.0.0.1 (DNS rebinding).
+#![warn(missing_docs)]
+
+use std::convert::Infallible;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use axum::extract::{Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use futures::{stream, Stream};
+use serde::Serialize;
+use tokio::sync::watch;
+use crate::clock::Clock;
+use crate::monitors::core::{Monitor, MonitorState};
+use crate::profile::Profile;
+use crate::store::{Chain, EntityStore, RankedWar};
+
+/// A monitor, as shown to other tools
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MonitorSnapshot {
+    /// Group it's in
+    pub group: String,
+
+    /// What it watches, e.g. a user id
+    pub target: String,
+
+    /// Name of what it watches, if known
+    pub name: String,
+
+    /// How it's doing
+    pub state: MonitorState,
+
+    /// When its timer runs out, if it has one running
+    pub ends_at: Option<DateTime<Utc>>,
+}
+
+/// Everything torndkt is showing. Times are absolute, so a snapshot only
+/// changes when something actually happens, not every time a countdown ticks.
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct Snapshot {
+    /// Open profile
+    pub profile: String,
+
+    /// Faction the chain and war are for
+    pub faction_id: Option<u32>,
+
+    /// Monitors in the open profile
+    pub monitors: Vec<MonitorSnapshot>,
+
+    /// The faction's chain
+    pub chain: Option<Chain>,
+
+    /// The faction's ranked war
+    pub war: Option<RankedWar>,
+}
+
+impl Snapshot {
+    /// Take a snapshot of a profile's monitors, and a faction's chain and war
+    pub fn new(profile: &Profile, faction_id: Option<u32>, store: &EntityStore, clock: &dyn Clock) -> Snapshot {
+        let monitors = profile.groups.iter()
+            .flat_map(|group| group.monitors.iter().map(move |x| (group, x)))
+            .map(|(group, monitor)| {
+                let summary = monitor.summary(store, clock);
+
+                MonitorSnapshot {
+                    group: group.name.clone(),
+                    target: summary.target,
+                    name: summary.name,
+                    state: summary.state,
+                    ends_at: summary.ends_at,
+                }
+            })
+            .collect();
+
+        Snapshot {
+            profile: profile.name.clone(),
+            faction_id,
+            monitors,
+            chain: faction_id.and_then(|x| store.chain(x)),
+            war: faction_id.and_then(|x| store.war(x)).flatten(),
+        }
+    }
+}
+
+/// Latest snapshot, shared between the app (which publishes it) and the
+/// server (which hands it out)
+pub struct LiveState {
+    sender: watch::Sender<Snapshot>,
+    clock: Arc<dyn Clock>,
+}
+
+impl LiveState {
+    /// Empty state, with `clock` used for the time in responses
+    pub fn new(clock: Arc<dyn Clock>) -> LiveState {
+        LiveState {
+            sender: watch::Sender::new(Snapshot::default()),
+            clock,
+        }
+    }
+
+    /// Replace the snapshot. Subscribers are only told if it's changed.
+    /// Returns whether it had.
+    pub fn publish(&self, snapshot: Snapshot) -> bool {
+        self.sender.send_if_modified(|x| {
+            let changed = *x != snapshot;
+            *x = snapshot;
+            changed
+        })
+    }
+
+    /// The latest snapshot
+    pub fn snapshot(&self) -> Snapshot {
+        self.sender.borrow().clone()
+    }
+}
+
+/// A response body, with the time it was generated so clients can work out
+/// countdowns without trusting their own clock
+#[derive(Serialize, Debug)]
+struct Timestamped<T: Serialize> {
+    /// Torn time when the response was made
+    now: DateTime<Utc>,
+
+    #[serde(flatten)]
+    body: T,
+}
+
+/// Monitors part of a snapshot
+#[derive(Serialize, Debug)]
+struct MonitorsBody {
+    profile: String,
+    monitors: Vec<MonitorSnapshot>,
+}
+
+/// War part of a snapshot
+#[derive(Serialize, Debug)]
+struct WarBody {
+    faction_id: Option<u32>,
+    war: Option<RankedWar>,
+}
+
+/// Chain part of a snapshot
+#[derive(Serialize, Debug)]
+struct ChainBody {
+    faction_id: Option<u32>,
+    chain: Option<Chain>,
+}
+
+/// Routes for the server, listening on `port`
+pub fn router(state: Arc<LiveState>, port: u16) -> Router {
+    Router::new()
+        .route("/snapshot", get(|State(x): State<Arc<LiveState>>| async move {
+            respond(&x, |s| s)
+        }))
+        .route("/monitors", get(|State(x): State<Arc<LiveState>>| async move {
+            respond(&x, |s| MonitorsBody { profile: s.profile, monitors: s.monitors })
+        }))
+        .route("/war", get(|State(x): State<Arc<LiveState>>| async move {
+            respond(&x, |s| WarBody { faction_id: s.faction_id, war: s.war })
+        }))
+        .route("/chain", get(|State(x): State<Arc<LiveState>>| async move {
+            respond(&x, |s| ChainBody { faction_id: s.faction_id, chain: s.chain })
+        }))
+        .route("/events", get(|State(x): State<Arc<LiveState>>| async move {
+            Sse::new(events(&x)).keep_alive(KeepAlive::default())
+        }))
+        .layer(middleware::from_fn_with_state(port, check_host))
+        .with_state(state)
+}
+
+/// Refuse requests that weren't addressed to us on localhost
+async fn check_host(State(port): State<u16>, request: Request, next: Next) -> Response {
+    let host = request.headers().get(header::HOST).and_then(|x| x.to_str().ok());
+    let allowed = [format!("127.0.0.1:{}", port), format!("localhost:{}", port)];
+
+    if host.is_some_and(|x| allowed.iter().any(|y| x.eq_ignore_ascii_case(y))) {
+        next.run(request).await
+    } else {
+        (StatusCode::FORBIDDEN, "Host must be 127.0.0.1 or localhost").into_response()
+    }
+}
+
+/// Part of the latest snapshot, stamped with the current time
+fn respond<T: Serialize>(state: &LiveState, part: impl FnOnce(Snapshot) -> T) -> Json<Timestamped<T>> {
+    Json(Timestamped {
+        now: state.clock.now(),
+        body: part(state.snapshot()),
+    })
+}
+
+/// The current snapshot, then each new one as it's published
+fn events(state: &LiveState) -> impl Stream<Item = Result<Event, Infallible>> + use<> {
+    let receiver = state.sender.subscribe();
+
+    stream::unfold((receiver, true), |(mut receiver, first)| async move {
+        if !first && receiver.changed().await.is_err() {
+            return None;
+        }
+
+        let snapshot = receiver.borrow_and_update().clone();
+        let event = Event::default()
+            .event("snapshot")
+            .json_data(&snapshot)
+            .unwrap();
+
+        Some((Ok(event), (receiver, false)))
+    })
+}
+
+/// Start serving on localhost at `port` (0 picks a free one), on a thread of
+/// its own. Returns the address it's listening on.
+pub fn spawn(port: u16, state: Arc<LiveState>) -> std::io::Result<SocketAddr> {
+    // Bound here rather than on the thread, so a port that's in use is
+    // reported straight away
+    let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()?;
+
+    std::thread::spawn(move || {
+        let result = runtime.block_on(async move {
+            let listener = tokio::net::TcpListener::from_std(listener)?;
+            axum::serve(listener, router(state, addr.port())).await
+        });
+
+        if let Err(x) = result {
+            eprintln!("Live server stopped: {}", x);
+        }
+    });
+
+    Ok(addr)
+}
\ No newline at end of file
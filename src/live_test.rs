@@ -0,0 +1,89 @@
+use std::sync::Arc;
+use chrono::{DateTime, TimeDelta};
+use crate::clock::FixedClock;
+use crate::live::{spawn, LiveState, Snapshot};
+use crate::monitors::core::MonitorState;
+use crate::profile::Profile;
+use crate::store::{EntityStore, User};
+
+/// Profile watching users 1 and 2, and a store where 1 is in hospital
+fn setup() -> (Profile, EntityStore, FixedClock) {
+    let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+
+    let mut profile = Profile::new("War");
+    profile.add_users(0, [1, 2]);
+
+    let store = EntityStore::default();
+    store.insert_user(1, Ok(User {
+        id: 1,
+        name: "Test".to_string(),
+        hospital_until: now + TimeDelta::seconds(90),
+        faction_id: None,
+    }));
+
+    (profile, store, FixedClock(now))
+}
+
+#[test]
+fn snapshot_only_changes_when_something_happens() {
+    let (profile, store, clock) = setup();
+    let live = LiveState::new(Arc::new(clock));
+
+    let snapshot = Snapshot::new(&profile, None, &store, &clock);
+    assert_eq!(snapshot.monitors[0].state, MonitorState::Waiting);
+    assert_eq!(snapshot.monitors[0].ends_at, Some(clock.0 + TimeDelta::seconds(90)));
+    assert_eq!(snapshot.monitors[1].state, MonitorState::Unknown);
+
+    assert!(live.publish(snapshot));
+
+    // Time passing doesn't change anything until the timer runs out
+    let later = FixedClock(clock.0 + TimeDelta::seconds(30));
+    assert!(!live.publish(Snapshot::new(&profile, None, &store, &later)));
+
+    let out = FixedClock(clock.0 + TimeDelta::seconds(90));
+    assert!(live.publish(Snapshot::new(&profile, None, &store, &out)));
+}
+
+#[tokio::test]
+async fn serves_json_and_events() {
+    let (profile, store, clock) = setup();
+    let live = Arc::new(LiveState::new(Arc::new(clock)));
+    live.publish(Snapshot::new(&profile, Some(100), &store, &clock));
+
+    let addr = spawn(0, live.clone()).unwrap();
+    assert!(addr.ip().is_loopback());
+
+    let body: serde_json::Value = reqwest::get(format!("http://{}/monitors", addr)).await.unwrap()
+        .json().await.unwrap();
+    assert_eq!(body["profile"], "War");
+    assert_eq!(body["monitors"][0]["name"], "Test");
+    assert_eq!(body["now"], "2023-11-14T22:13:20Z");
+
+    let body: serde_json::Value = reqwest::get(format!("http://{}/war", addr)).await.unwrap()
+        .json().await.unwrap();
+    assert_eq!(body["faction_id"], 100);
+    assert_eq!(body["war"], serde_json::Value::Null);
+
+    // The stream starts with the current snapshot, then sends changes
+    let mut events = reqwest::get(format!("http://{}/events", addr)).await.unwrap();
+    let first = String::from_utf8(events.chunk().await.unwrap().unwrap().to_vec()).unwrap();
+    assert!(first.starts_with("event: snapshot\n"), "{}", first);
+    assert!(first.contains("\"profile\":\"War\""));
+
+    live.publish(Snapshot::new(&Profile::new("Chain"), None, &store, &clock));
+    let second = String::from_utf8(events.chunk().await.unwrap().unwrap().to_vec()).unwrap();
+    assert!(second.contains("\"profile\":\"Chain\""), "{}", second);
+}
+
+#[tokio::test]
+async fn other_hosts_are_refused() {
+    let (_, _, clock) = setup();
+    let addr = spawn(0, Arc::new(LiveState::new(Arc::new(clock)))).unwrap();
+    let client = reqwest::Client::new();
+    let get = |host: String| client.get(format!("http://{}/snapshot", addr)).header("Host", host).send();
+
+    assert_eq!(get(format!("localhost:{}", addr.port())).await.unwrap().status(), 200);
+    assert_eq!(get(format!("127.0.0.1:{}", addr.port())).await.unwrap().status(), 200);
+    assert_eq!(get(format!("evil.example:{}", addr.port())).await.unwrap().status(), 403);
+    assert_eq!(get("localhost".to_string()).await.unwrap().status(), 403);
+}
\ No newline at end of file
@@ -6,46 +6,270 @@
     windows_subsystem = "windows"
 )]
 
+mod activity;
 mod api;
+mod bulk;
+mod charts;
+mod cli;
+mod clock;
+mod commands;
+mod history;
+mod layout;
+mod leaderboard;
+mod live;
 mod util;
 mod persistence;
+mod profile;
+mod refresh;
+mod store;
+mod tui;
+mod webhooks;
+/// Monitor implementations
 pub mod monitors;
 
-use crate::api::api::GetInfoError;
+#[cfg(test)]
+mod activity_test;
+
+#[cfg(test)]
+mod bulk_test;
+
+#[cfg(test)]
+mod charts_test;
+
+#[cfg(test)]
+mod cli_test;
+
+#[cfg(test)]
+mod clock_test;
+
+#[cfg(test)]
+mod history_test;
+
+#[cfg(test)]
+mod layout_test;
+
+#[cfg(test)]
+mod leaderboard_test;
+
+#[cfg(test)]
+mod live_test;
+
+#[cfg(test)]
+mod profile_test;
+
+#[cfg(test)]
+mod refresh_test;
+
+#[cfg(test)]
+mod store_test;
+
+#[cfg(test)]
+mod test_history;
+
+#[cfg(test)]
+mod tui_test;
+
+#[cfg(test)]
+mod webhooks_test;
+
+use crate::api::api::{server_offset, GetInfoError};
+use crate::api::cache::entity_cache;
+use crate::api::client::{client, install, ApiClient, ApiConfig, ApiHealth};
+use crate::api::recording::{Recorder, Recording, Replay};
+use crate::api::faction::faction_members;
+use crate::api::key::{key_capabilities, test_key, KeyCapabilities, KeyReport};
+use crate::clock::{Clock, ServerSyncedClock, SystemClock};
 use crate::monitors::basic::SimpleHospMonitor;
 use crate::monitors::core::{Monitor};
 use crate::monitors::selection::MonitorList;
-use crate::persistence::PersistedData;
-use chrono::{DateTime, Utc};
+use crate::persistence::{PersistedData, PersistenceError};
+use crate::persistence::files::ConfigPaths;
+use crate::persistence::keystore::{KeyBackend, KeyManager};
+use crate::profile::{valid_key_slot, MonitorGroup, Profile};
+use crate::refresh::{RefreshJob, RefreshOutcome, Refresher};
+use crate::store::{EntityStore, User};
+use crate::history::{History, HistorySettings};
+use crate::charts::WarWindow;
+use crate::layout::{ImportMode, Layout, LayoutError};
+use crate::live::{LiveState, Snapshot};
+use crate::webhooks::{DeliveryConfig, Notifier};
+use clap::Parser;
+use chrono::Utc;
 use eframe::emath::Vec2;
 use eframe::{egui, Storage};
 use futures::executor;
 use std::collections::HashMap;
-use uniquevec::UniqueVec;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 
 struct ExampleApp {
-    hosp_map: HashMap<String, DateTime<Utc>>,
+    /// All profiles. There's always at least one.
+    profiles: Vec<Profile>,
+
+    /// Index of the open profile
+    active: usize,
+
+    /// Name buffer for creating and renaming profiles
+    profile_name_buf: String,
+
+    /// Key slot buffer for the profile menu
+    key_slot_buf: String,
+
+    /// Name buffer for new groups
+    group_name_buf: String,
+
+    /// Whether the layout sharing window is open
+    layout_open: bool,
+
+    /// Layout code or JSON pasted in for importing
+    layout_input: String,
+
+    /// `layout_input` as it was last parsed, and what it parsed to, so it's
+    /// only decoded again when it changes
+    layout_parsed: Option<(String, Result<Layout, LayoutError>)>,
+
+    /// File path for exporting or importing layouts
+    layout_path: String,
+
+    /// How imported layouts are combined with the current one
+    import_mode: ImportMode,
+
+    /// Result of the last layout action, good or bad
+    layout_status: Option<Result<String, String>>,
+
+    /// Group the bulk add window is adding to, if it's open
+    bulk_group: Option<usize>,
+
+    /// Targets pasted in for bulk adding
+    bulk_input: String,
+
+    /// Members of factions looked up for bulk adding, by faction id
+    bulk_factions: HashMap<u32, Vec<User>>,
+
+    /// Error from the last faction lookup, if it failed
+    bulk_error: Option<String>,
 
-    monitors: Vec<MonitorList>,
-    idselbuf: String,
-    ids: UniqueVec<u32>,
-    uiscale: f32,
     pub apikey: String,
-    errmodal_open: bool,
-    first_update: bool
+    first_update: bool,
+
+    /// Error to show in the error modal, if any
+    api_error: Option<GetInfoError>,
+
+    /// Whether the API key is shown in plain text
+    show_key: bool,
+
+    /// Result of the last successful key test, shown until dismissed
+    key_report: Option<KeyReport>,
+
+    /// What the current key can do, if known
+    key_caps: Option<KeyCapabilities>,
+
+    /// Secure storage for the API key
+    keys: KeyManager,
+
+    /// Whether the passphrase prompt is open
+    passphrase_open: bool,
+
+    /// Set when the user skips the passphrase prompt, so it doesn't keep
+    /// popping up
+    passphrase_skipped: bool,
+
+    /// Passphrase input buffer
+    passphrase_buf: String,
+
+    /// Last error from the key store, if any
+    key_store_error: Option<String>,
+
+    /// Problem with loading saved settings, shown once at startup
+    load_notice: Option<String>,
+
+    /// Profile asked for with `--profile` that doesn't exist, offered to be
+    /// created at startup
+    missing_profile: Option<String>,
+
+    /// Where settings are saved
+    paths: ConfigPaths,
+
+    /// Error from the last attempt to save settings, if it failed
+    save_error: Option<String>,
+
+    /// Time source for countdowns, kept in line with Torn's clock, or the
+    /// replay's clock when playing a recording back
+    clock: Arc<dyn Clock>,
+
+    /// Everything fetched from the API, which monitors read from
+    store: Arc<EntityStore>,
+
+    /// Snapshot shared with the local server, and where it's listening, if
+    /// it's running
+    live: Option<(Arc<LiveState>, SocketAddr)>,
+
+    /// Sends events to the open profile's webhooks, if it has any
+    notifier: Option<Notifier>,
+
+    /// Why the webhooks couldn't be started
+    webhook_error: Option<String>,
+
+    /// What history is kept
+    history_settings: HistorySettings,
+
+    /// Where history is kept, if it's being kept
+    history: Option<Arc<History>>,
+
+    /// Error from the last attempt to save history, if it failed
+    history_error: Option<String>,
+
+    /// Refreshes in the background
+    refresher: Refresher,
+
+    /// War history charts and leaderboard
+    war_window: WarWindow,
 }
 
 impl Default for ExampleApp{
     fn default() -> Self {
         ExampleApp{
-            hosp_map: HashMap::new(),
-            idselbuf: String::new(),
-            ids: UniqueVec::new(),
+            bulk_group: None,
+            bulk_input: String::new(),
+            bulk_factions: HashMap::new(),
+            bulk_error: None,
             apikey: String::new(),
-            uiscale: 1.5,
-            errmodal_open: false,
+            api_error: None,
+            show_key: false,
+            key_report: None,
+            key_caps: None,
+            keys: KeyManager::new(KeyBackend::default(), ConfigPaths::default().key_file, ""),
+            passphrase_open: false,
+            passphrase_skipped: false,
+            passphrase_buf: String::new(),
+            key_store_error: None,
+            load_notice: None,
+            missing_profile: None,
+            paths: ConfigPaths::default(),
+            save_error: None,
             first_update: true,
-            monitors: vec![],
+            profiles: vec![Profile::default()],
+            active: 0,
+            profile_name_buf: String::new(),
+            key_slot_buf: String::new(),
+            group_name_buf: String::new(),
+            layout_open: false,
+            layout_input: String::new(),
+            layout_parsed: None,
+            layout_path: "layout.json".to_string(),
+            import_mode: ImportMode::default(),
+            layout_status: None,
+            clock: Arc::new(ServerSyncedClock::new(server_offset())),
+            store: Arc::default(),
+            live: None,
+            notifier: None,
+            webhook_error: None,
+            history_settings: HistorySettings::default(),
+            history: None,
+            history_error: None,
+            refresher: Refresher::default(),
+            war_window: WarWindow::default(),
         }
     }
 }
@@ -55,120 +279,1083 @@ impl ExampleApp {
         "torndkt v0.2.0"
     }
 
+    /// The open profile
+    fn profile(&self) -> &Profile {
+        &self.profiles[self.active]
+    }
+
+    /// The open profile
+    fn profile_mut(&mut self) -> &mut Profile {
+        &mut self.profiles[self.active]
+    }
+
+    /// Open a different profile, switching to its API key
+    fn switch_profile(&mut self, index: usize) {
+        // Make sure the outgoing key is saved in its own slot first
+        self.store_key();
+
+        self.active = index;
+        self.select_key_slot();
+        self.start_notifier();
+    }
+
+    /// (Re)start sending events to the open profile's webhooks. Replays are
+    /// of things that already happened, so they aren't announced.
+    fn start_notifier(&mut self) {
+        self.notifier = None;
+        self.webhook_error = None;
+
+        let settings = &self.profile().webhooks;
+        if settings.hooks.is_empty() || client().replay().is_some() {
+            return;
+        }
+
+        match Notifier::start(settings, DeliveryConfig::default()) {
+            Ok(x) => self.notifier = Some(x),
+            Err(x) => self.webhook_error = Some(x.to_string()),
+        }
+    }
+
+    /// Load the key for the open profile's key slot
+    fn select_key_slot(&mut self) {
+        let slot = self.profile().key_slot.clone();
+        self.key_slot_buf = slot.clone();
+
+        self.keys.select(&slot);
+        self.apikey.clear();
+        self.load_key();
+        self.refresh_key_caps();
+    }
+
+    /// Profile menu contents
+    fn profile_menu(&mut self, ui: &mut egui::Ui) {
+        let mut switch_to = None;
+        for (i, profile) in self.profiles.iter().enumerate() {
+            if ui.radio(i == self.active, &profile.name).clicked() {
+                switch_to = Some(i);
+            }
+        }
+
+        if let Some(i) = switch_to.filter(|x| *x != self.active) {
+            self.switch_profile(i);
+            ui.close_menu();
+        }
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.label("Name:");
+            ui.text_edit_singleline(&mut self.profile_name_buf);
+        });
+
+        let name = self.profile_name_buf.trim().to_string();
+        let name_ok = !name.is_empty() && !self.profiles.iter().any(|x| x.name == name);
+
+        ui.horizontal(|ui| {
+            if ui.add_enabled(name_ok, egui::Button::new("New")).clicked() {
+                self.profiles.push(Profile::new(&name));
+                self.profile_name_buf.clear();
+                self.switch_profile(self.profiles.len() - 1);
+            }
+
+            if ui.add_enabled(name_ok, egui::Button::new("Duplicate")).clicked() {
+                let mut profile = self.profile().clone();
+                profile.name = name.clone();
+
+                self.profiles.push(profile);
+                self.profile_name_buf.clear();
+                self.switch_profile(self.profiles.len() - 1);
+            }
+
+            if ui.add_enabled(name_ok, egui::Button::new("Rename")).clicked() {
+                self.profile_mut().name = name.clone();
+                self.profile_name_buf.clear();
+            }
+
+            if ui.add_enabled(self.profiles.len() > 1, egui::Button::new("Delete")).clicked() {
+                self.profiles.remove(self.active);
+                self.switch_profile(self.active.min(self.profiles.len() - 1));
+            }
+        });
+
+        ui.separator();
+
+        // Profiles sharing a key slot share a key
+        ui.horizontal(|ui| {
+            ui.label("API key slot:");
+            ui.text_edit_singleline(&mut self.key_slot_buf);
+
+            let slot = self.key_slot_buf.trim().to_string();
+            let changed = valid_key_slot(&slot) && slot != self.profile().key_slot;
+
+            if ui.add_enabled(changed, egui::Button::new("Use")).clicked() {
+                self.store_key();
+                self.profile_mut().key_slot = slot;
+                self.select_key_slot();
+            }
+        });
+
+        if !valid_key_slot(self.key_slot_buf.trim()) {
+            ui.colored_label(egui::Color32::RED, "Slot names can only have letters, digits, _ and -, up to 32");
+        }
+
+        ui.separator();
+
+        if ui.button("Share layout…").clicked() {
+            self.layout_open = true;
+            ui.close_menu();
+        }
+    }
+
+    /// The layout pasted in for importing. Only parsed again when the input
+    /// has changed, as decoding a code every frame adds up.
+    fn imported_layout(&mut self) -> Result<Layout, LayoutError> {
+        if self.layout_parsed.as_ref().is_none_or(|(input, _)| *input != self.layout_input) {
+            self.layout_parsed = Some((self.layout_input.clone(), Layout::parse(&self.layout_input)));
+        }
+
+        self.layout_parsed.as_ref().unwrap().1.clone()
+    }
+
+    /// Window for exporting and importing layouts
+    fn layout_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.layout_open;
+
+        egui::Window::new("Share layout")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let layout = Layout::from_profile(self.profile());
+
+                ui.heading("Export");
+                ui.label("Layouts never include your API key.");
+
+                ui.horizontal(|ui| {
+                    if ui.button("Copy code").clicked() {
+                        ctx.copy_text(layout.to_code());
+                        self.layout_status = Some(Ok("Layout code copied to clipboard".to_string()));
+                    }
+
+                    ui.label("File:");
+                    ui.text_edit_singleline(&mut self.layout_path);
+
+                    if ui.button("Save").clicked() {
+                        self.layout_status = Some(layout.save(&self.layout_path)
+                            .map(|_| format!("Saved to {}", self.layout_path))
+                            .map_err(|x| x.to_string()));
+                    }
+
+                    if ui.button("Load").clicked() {
+                        match Layout::read_file(&self.layout_path) {
+                            Ok(x) => self.layout_input = x,
+                            Err(x) => self.layout_status = Some(Err(x.to_string())),
+                        }
+                    }
+                });
+
+                ui.separator();
+                ui.heading("Import");
+                ui.add(egui::TextEdit::multiline(&mut self.layout_input)
+                    .hint_text("Paste a layout code here")
+                    .desired_rows(3));
+
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.import_mode, ImportMode::Merge, "Merge")
+                        .on_hover_text("Add new groups and monitors, keeping what's already here");
+                    ui.radio_value(&mut self.import_mode, ImportMode::Replace, "Replace")
+                        .on_hover_text("Replace all groups in this profile");
+                });
+
+                if self.layout_input.trim().is_empty() {
+                    return;
+                }
+
+                let imported = match self.imported_layout() {
+                    Ok(x) => x,
+                    Err(x) => {
+                        ui.colored_label(egui::Color32::RED, x.to_string());
+                        return;
+                    }
+                };
+
+                // Preview what's about to happen
+                let preview = layout::preview(self.profile(), &imported, self.import_mode);
+                ui.label(format!("From profile \"{}\":", imported.name));
+
+                if !preview.removed_groups.is_empty() {
+                    ui.label(format!("Remove groups: {}", preview.removed_groups.join(", ")));
+                }
+                if !preview.new_groups.is_empty() {
+                    ui.label(format!("New groups: {}", preview.new_groups.join(", ")));
+                }
+
+                egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
+                    for (group, monitor) in &preview.added {
+                        ui.label(format!("+ {} monitor {} → {}", monitor, monitor.target_label(), group));
+                    }
+                });
+
+                if preview.duplicates > 0 {
+                    ui.label(format!("{} monitor(s) already here will be skipped", preview.duplicates));
+                }
+
+                if ui.button("Import").clicked() {
+                    let mode = self.import_mode;
+                    layout::apply(self.profile_mut(), &imported, mode);
+
+                    self.layout_input.clear();
+                    self.layout_status = Some(Ok(format!("Imported {} monitor(s)", preview.added.len())));
+                }
+            });
+
+        if let Some(status) = &self.layout_status && open {
+            egui::TopBottomPanel::bottom("layout_status").show(ctx, |ui| match status {
+                Ok(x) => ui.label(x),
+                Err(x) => ui.colored_label(egui::Color32::RED, x),
+            });
+        }
+
+        if !open {
+            self.layout_status = None;
+            self.layout_parsed = None;
+        }
+
+        self.layout_open = open;
+    }
+
+    /// Window for adding lots of targets to a group at once
+    fn bulk_window(&mut self, ctx: &egui::Context) {
+        let Some(group) = self.bulk_group.filter(|x| *x < self.profile().groups.len()) else {
+            self.bulk_group = None;
+            return;
+        };
+
+        let mut open = true;
+        let mut added = false;
+
+        egui::Window::new("Bulk add")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!("Adding to \"{}\"", self.profile().groups[group].name));
+                ui.label("Paste ids, profile links or names, separated by commas or new lines. \
+                    Use faction:<id> or a faction link to add a whole faction.");
+
+                ui.add(egui::TextEdit::multiline(&mut self.bulk_input)
+                    .hint_text("1234, 5678\nhttps://www.torn.com/profiles.php?XID=4321\nfaction:9876")
+                    .desired_rows(5));
+
+                let entries = bulk::parse(&self.bulk_input);
+
+                // Faction members are only fetched when asked for, as big
+                // factions are a lot of ids to add by accident
+                let missing = bulk::factions(&entries).into_iter()
+                    .filter(|x| !self.bulk_factions.contains_key(x))
+                    .collect::<Vec<_>>();
+
+                if !missing.is_empty() && ui.button(format!("Look up {} faction(s)", missing.len())).clicked() {
+                    self.bulk_error = None;
+
+                    for id in missing {
+                        match executor::block_on(faction_members(&self.apikey, id)) {
+                            Ok(x) => {
+                                let members = x.iter().map(|x| User::from_member(id, x)).collect();
+                                self.bulk_factions.insert(id, members);
+                            }
+                            Err(x) => self.bulk_error = Some(format!("Faction {}: {}", id, x)),
+                        }
+                    }
+                }
+
+                if let Some(x) = &self.bulk_error {
+                    ui.colored_label(egui::Color32::RED, x);
+                }
+
+                // Names can be looked up among faction members fetched above,
+                // and anyone already fetched for a monitor
+                let known = self.store.users();
+
+                let result = bulk::resolve(&entries, &self.bulk_factions, &known);
+
+                let existing = &self.profile().groups[group].monitors;
+                let new = result.ids.iter()
+                    .map(|x| MonitorList::Simple(SimpleHospMonitor::new(*x)))
+                    .filter(|x| !existing.iter().any(|y| y.same_target(x)))
+                    .collect::<Vec<_>>();
+
+                ui.separator();
+                ui.label(format!("{} new target(s)", new.len()));
+
+                let already = result.ids.len() - new.len();
+                if already > 0 {
+                    ui.label(format!("{} already in this group", already));
+                }
+                if result.duplicates > 0 {
+                    ui.label(format!("{} listed more than once", result.duplicates));
+                }
+                if !result.unresolved.is_empty() {
+                    ui.colored_label(egui::Color32::YELLOW, format!("Couldn't find: {}", result.unresolved.join(", ")))
+                        .on_hover_text("Names are looked up among members of the factions listed, \
+                            and targets already being monitored");
+                }
+                if !result.invalid.is_empty() {
+                    ui.colored_label(egui::Color32::RED, format!("Not recognised: {}", result.invalid.join(", ")));
+                }
+
+                if ui.add_enabled(!new.is_empty(), egui::Button::new("Add")).clicked() {
+                    self.profile_mut().groups[group].monitors.extend(new);
+                    added = true;
+                }
+            });
+
+        if added || !open {
+            self.bulk_group = None;
+            self.bulk_input.clear();
+            self.bulk_error = None;
+        }
+    }
+
+    /// Contents of one monitor group. Returns whether it was removed.
+    fn group_ui(&mut self, ui: &mut egui::Ui, ctx: &egui::Context, group: usize) -> bool {
+        let remove = ui.horizontal(|ui| {
+            let mut selected = MonitorList::None;
+
+            egui::ComboBox::from_id_salt(("add_monitor", group))
+                .selected_text("Add monitor")
+                .show_ui(ui, |ui| {
+                    // Monitors the key can't serve are shown, but can't be picked
+                    for i in MonitorList::kinds() {
+                        let servable = self.key_caps.as_ref().is_none_or(|x| i.servable_by(x));
+                        let label = format!("{} monitor", i);
+
+                        ui.add_enabled_ui(servable, |ui| {
+                            ui.selectable_value(&mut selected, i, label);
+                        }).response.on_disabled_hover_text("The current API key can't access the data this monitor needs");
+                    }
+                }
+                );
+
+            if selected != MonitorList::None{
+                self.profile_mut().groups[group].monitors.push(selected);
+            }
+
+            if ui.button("Bulk add…").clicked() {
+                self.bulk_group = Some(group);
+            }
+
+            ui.button("Remove group").clicked()
+        }).inner;
+
+        if remove {
+            self.profile_mut().groups.remove(group);
+            // The bulk add dialog's group index would be stale
+            self.bulk_group = None;
+            return true;
+        }
+
+        let monitors = self.profile().groups[group].monitors.clone();
+
+        let mut closed = None;
+
+        egui::Grid::new(("monitors", group))
+            .spacing(egui::Vec2::new(10.0, 2.0))
+            .striped(false)
+            .show(ui, |ui| {
+            let clock = self.clock.clone();
+            let store = self.store.clone();
+            for (index, mut i) in monitors.into_iter().enumerate(){
+                // Badge monitors the key can't serve
+                let missing = self.key_caps.as_ref()
+                    .map(|x| x.missing(i.required_selections()))
+                    .unwrap_or_default();
+
+                if missing.is_empty() {
+                    ui.label("");
+                }
+                else {
+                    let missing = missing.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+                    ui.colored_label(egui::Color32::YELLOW, "⚠")
+                        .on_hover_text(format!("API key can't access: {}", missing.join(", ")));
+                }
+
+                i.update(self, ui, ctx, store.as_ref(), clock.as_ref(), |_: &mut Self| closed = Some(index));
+                self.profile_mut().groups[group].monitors[index] = i;
+                ui.end_row();
+            }
+        });
+
+        if let Some(index) = closed {
+            self.profile_mut().groups[group].monitors.remove(index);
+        }
+
+        false
+    }
+
     fn init(&mut self){
-        executor::block_on(async {
-            match self.update_torn().await{
-                Ok(_) => (),
-                Err(_) => {self.errmodal_open = true;}
+        let job = self.refresh_job();
+        self.refresher.start(job, true, self.clock.now());
+    }
+
+    /// What a refresh of the open profile needs
+    fn refresh_job(&self) -> RefreshJob {
+        RefreshJob {
+            apikey: self.apikey.clone(),
+            ids: self.profile().monitors().flat_map(|x| x.users()).collect(),
+            store: self.store.clone(),
+            clock: self.clock.clone(),
+            history: self.history.clone(),
+            watch: self.history_settings.watch,
+        }
+    }
+
+    /// Fetch everything the open profile's monitors show into the store,
+    /// waiting until it's done
+    fn refresh(&mut self) -> Result<(), GetInfoError> {
+        let outcome = executor::block_on(self.refresh_job().run());
+        self.refreshed(outcome)
+    }
+
+    /// Take in what a refresh came back with
+    fn refreshed(&mut self, outcome: RefreshOutcome) -> Result<(), GetInfoError> {
+        self.key_caps = outcome.key_caps;
+
+        if let Some(x) = outcome.history {
+            self.history_error = x.err().map(|x| x.to_string());
+        }
+
+        if let Some(x) = &mut self.notifier {
+            x.refreshed(&outcome.result, self.clock.now());
+        }
+
+        outcome.result
+    }
+
+    /// Load the API key from the key store. Keys found in plain text in the
+    /// persistence file are moved into the store straight away if possible.
+    fn load_key(&mut self) {
+        let migrating = self.keys.legacy_key().is_some();
+
+        match self.keys.load() {
+            Ok(x) => {
+                self.apikey = x.unwrap_or_default();
+                self.store_key();
+            }
+            Err(PersistenceError::NeedsPassphrase) => self.passphrase_open = true,
+            Err(x) => self.key_store_error = Some(x.to_string()),
+        }
+
+        // Scrub the plain text key from the persistence file once it's safe
+        if migrating && self.keys.legacy_key().is_none()
+            && let Err(x) = PersistedData::from(&*self).save(&self.paths.persistence) {
+            self.key_store_error = Some(x.to_string());
+        }
+    }
+
+    /// Save settings to the config directory and move the file from before
+    /// they lived there out of the way, once the key in it (if any) is safely
+    /// in the key store
+    fn retire_legacy(&mut self) {
+        if self.paths.legacy().is_none() || self.keys.legacy_key().is_some() || self.load_notice.is_some() {
+            return;
+        }
+
+        let result = PersistedData::from(&*self).save(&self.paths.persistence)
+            .and_then(|_| self.paths.retire_legacy());
+
+        if let Err(x) = result {
+            self.key_store_error = Some(x.to_string());
+        }
+    }
+
+    /// Put the API key in the key store, if it's changed
+    fn store_key(&mut self) {
+        match self.keys.sync(&self.apikey) {
+            Ok(_) => self.key_store_error = None,
+            Err(PersistenceError::NeedsPassphrase) => {
+                self.passphrase_open = !self.passphrase_skipped;
+            }
+            Err(x) => self.key_store_error = Some(x.to_string()),
+        }
+    }
+
+    /// Prompt for the passphrase protecting the API key, when there's no OS
+    /// keyring to use
+    fn passphrase_modal(&mut self, ctx: &egui::Context) {
+        if !self.passphrase_open {
+            return;
+        }
+
+        let unlocking = self.keys.has_encrypted_key();
+
+        let modal = egui::Modal::new(egui::Id::new("passphrase")).show(ctx, |ui| {
+            if unlocking {
+                ui.heading("Unlock API key");
+                ui.label("Enter the passphrase your API key was saved with.");
+            }
+            else {
+                ui.heading("Protect API key");
+                ui.label("No system keyring is available, so your API key will be \
+                    saved encrypted with a passphrase. You'll need it each time \
+                    torndkt starts.");
             }
+
+            let input = ui.add(egui::TextEdit::singleline(&mut self.passphrase_buf).password(true));
+
+            if let Some(x) = &self.key_store_error {
+                ui.colored_label(egui::Color32::RED, x);
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                let submit = ui.button(if unlocking {"Unlock"} else {"Save"}).clicked()
+                    || (input.lost_focus() && ui.input(|x| x.key_pressed(egui::Key::Enter)));
+
+                (submit, ui.button("Skip").clicked())
+            }).inner
+        });
+
+        let (submit, skip) = modal.inner;
+
+        if submit {
+            self.submit_passphrase();
+        }
+
+        if skip || modal.should_close() {
+            self.skip_passphrase();
+        }
+    }
+
+    /// Unlock or protect the key with the passphrase that's been typed in
+    fn submit_passphrase(&mut self) {
+        if self.passphrase_buf.is_empty() {
+            return;
+        }
+
+        match self.keys.unlock(&self.passphrase_buf) {
+            Ok(key) => {
+                if let Some(x) = key {
+                    self.apikey = x;
+                }
+
+                self.passphrase_open = false;
+                self.passphrase_buf.clear();
+                self.store_key();
+            }
+            Err(x) => self.key_store_error = Some(x.to_string()),
+        }
+    }
+
+    /// Carry on without the passphrase, and stop asking for it
+    fn skip_passphrase(&mut self) {
+        self.passphrase_open = false;
+        self.passphrase_skipped = true;
+        self.passphrase_buf.clear();
+    }
+
+    /// Look up what the current key can do. The API layer caches this, so it's
+    /// cheap to call repeatedly.
+    fn refresh_key_caps(&mut self) {
+        if self.apikey.is_empty() {
+            self.key_caps = None;
+            return;
+        }
+
+        self.key_caps = executor::block_on(key_capabilities(&self.apikey)).ok();
+    }
+
+    /// Show the key owner's chain and war score in the status bar
+    fn faction_status(&self, ui: &mut egui::Ui, faction: u32) {
+        if let Some(chain) = self.store.chain(faction)
+            && let Some(breaks_at) = chain.breaks_at {
+            let secs = (breaks_at - self.clock.now()).num_seconds().max(0);
+            ui.label(format!("chain: {}/{} ({}:{:02} left)", chain.current, chain.max, secs / 60, secs % 60));
+        }
+
+        if let Some(Some(war)) = self.store.war(faction)
+            && let Some((ours, theirs)) = war.sides(faction) {
+            ui.label(format!("war: {} {} - {} {}", ours.name, ours.score, theirs.score, theirs.name))
+                .on_hover_text(format!("Lead of {} needed to win", war.target));
+        }
+    }
+
+    /// Hand what's on screen to the local server and the webhooks, if
+    /// either is running
+    fn publish(&mut self) {
+        if self.live.is_none() && self.notifier.is_none() {
+            return;
+        }
+
+        let faction = self.key_caps.as_ref().and_then(|x| x.faction_id);
+        let snapshot = Snapshot::new(self.profile(), faction, &self.store, self.clock.as_ref());
+
+        if let Some(x) = &mut self.notifier {
+            x.observe(&snapshot, self.clock.now());
+        }
+
+        if let Some((live, _)) = &self.live {
+            live.publish(snapshot);
+        }
+    }
+
+    /// Show the error modal, if there's an error to show
+    fn error_modal(&mut self, ctx: &egui::Context) {
+        let Some(err) = self.api_error.clone() else { return };
+
+        let modal = egui::Modal::new(egui::Id::new("api_error")).show(ctx, |ui| {
+            ui.heading(if err.is_key_error() { "API key error" } else { "API error" });
+            ui.label(format!("{}", err));
+            ui.label(err.help_text());
+
+            ui.separator();
+            ui.button("Ok").clicked()
         });
+
+        if modal.inner || modal.should_close() {
+            self.api_error = None;
+        }
     }
 
-    async fn update_torn(&mut self) -> Result<(),GetInfoError> {
-        for i in self.ids.clone() {
-            let resp = self.update_hosp_time(i).await?;
+    /// Tell the user if their settings couldn't be loaded
+    fn load_notice_modal(&mut self, ctx: &egui::Context) {
+        let Some(notice) = &self.load_notice else { return };
+
+        let modal = egui::Modal::new(egui::Id::new("load_notice")).show(ctx, |ui| {
+            ui.heading("Settings not loaded");
+            ui.label(notice);
+            ui.label("Default settings are being used instead.");
 
-            let hosp_datetime = DateTime::from_timestamp(
-                    resp.states["hospital_timestamp"],
-                    0
-                ).unwrap();
+            ui.separator();
+            ui.button("Ok").clicked()
+        });
 
-            self.hosp_map.insert(resp.name, hosp_datetime);
+        if modal.inner || modal.should_close() {
+            self.load_notice = None;
         }
+    }
+
+    /// Offer to create the profile asked for on the command line, if there
+    /// wasn't one by that name. It's not created without asking, so a typo
+    /// doesn't leave an empty profile behind.
+    fn missing_profile_modal(&mut self, ctx: &egui::Context) {
+        let Some(name) = self.missing_profile.clone() else { return };
 
-        Ok(())
+        let modal = egui::Modal::new(egui::Id::new("missing_profile")).show(ctx, |ui| {
+            ui.heading("Profile not found");
+            ui.label(format!("There's no profile called \"{}\", so \"{}\" was opened instead.", name, self.profile().name));
+
+            ui.separator();
+            ui.horizontal(|ui| (ui.button(format!("Create \"{}\"", name)).clicked(), ui.button("Ok").clicked())).inner
+        });
+
+        let (create, ok) = modal.inner;
+
+        if create {
+            self.profiles.push(Profile::new(&name));
+            self.switch_profile(self.profiles.len() - 1);
+        }
+
+        if create || ok || modal.should_close() {
+            self.missing_profile = None;
+        }
+    }
+
+    /// Show the results of a key test, if one has just been run
+    fn key_report_modal(&mut self, ctx: &egui::Context) {
+        let Some(report) = &self.key_report else { return };
+
+        let modal = egui::Modal::new(egui::Id::new("key_report")).show(ctx, |ui| {
+            ui.heading("API key ok");
+            ui.label(format!("Owner: {} [{}]", report.owner_name, report.owner_id));
+            ui.label(format!("Access level: {} ({})", report.capabilities.access, report.capabilities.access_type));
+
+            ui.separator();
+            ui.label("Monitors:");
+            for i in MonitorList::kinds() {
+                let missing = report.capabilities.missing(i.required_selections());
+
+                if missing.is_empty() {
+                    ui.label(format!("✔ {}", i));
+                }
+                else {
+                    let missing = missing.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+                    ui.label(format!("✘ {} (needs {})", i, missing.join(", ")));
+                }
+            }
+
+            ui.separator();
+            ui.button("Ok").clicked()
+        });
+
+        if modal.inner || modal.should_close() {
+            self.key_report = None;
+        }
     }
 }
 
 impl eframe::App for ExampleApp {
     fn save(&mut self, _storage: &mut dyn Storage) {
+        self.store_key();
+
         let data = PersistedData::from(&*self);
-        data.save("persistence.json").unwrap();
+        self.save_error = data.save(&self.paths.persistence)
+            .err()
+            .map(|x| x.to_string());
     }
 
     #[tokio::main]
-    async fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
-        ctx.set_pixels_per_point(self.uiscale);
+    async fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        ctx.set_pixels_per_point(self.profile().uiscale);
 
         if self.first_update {
             self.init();
         }
 
+        // Keep refreshing without being asked, but not while the key might
+        // be about to change. Timed by the app's clock, so replays refresh
+        // as often as they would have at the time.
+        let now = self.clock.now();
+        if self.refresher.due(now) && !self.passphrase_open {
+            let job = self.refresh_job();
+            self.refresher.start(job, false, now);
+        }
+
+        // Errors from refreshes nobody asked for only go in the status bar
+        if let Some((outcome, asked)) = self.refresher.finished()
+            && let Err(x) = self.refreshed(outcome)
+            && asked {
+            match x {
+                GetInfoError::WrongKey | GetInfoError::Other(_)
+                | GetInfoError::Decode(_) | GetInfoError::BadRequest => self.api_error = Some(x),
+                GetInfoError::InvalidId => {},
+                // Shown in the status bar
+                GetInfoError::Network | GetInfoError::Paused => {},
+            }
+        }
+
         self.first_update = false;
-        let _ = egui::Window::new("API key error")
-            .open(&mut self.errmodal_open)
-            .collapsible(false)
-            .resizable(false);
-        
+        self.publish();
+
+        self.error_modal(ctx);
+        self.key_report_modal(ctx);
+        self.passphrase_modal(ctx);
+        self.load_notice_modal(ctx);
+        self.missing_profile_modal(ctx);
+        self.layout_window(ctx);
+
+        let faction = self.key_caps.as_ref().and_then(|x| x.faction_id);
+        self.war_window.show(ctx, self.history.as_deref(), faction, self.clock.now());
+        self.bulk_window(ctx);
+
+        egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                let title = format!("Profile: {}", self.profile().name);
+                ui.menu_button(title, |ui| self.profile_menu(ui));
+            });
+        });
+
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            let offset = server_offset();
+
+            if let Some(replay) = client().replay() {
+                ui.colored_label(egui::Color32::LIGHT_BLUE, format!("replaying at {}x", replay.clock.speed()))
+                    .on_hover_text(format!("Recording from {}", replay.recording.start().format("%Y-%m-%d %H:%M:%S TCT")));
+            }
+            else if offset.is_synced() {
+                let secs = offset.get().num_milliseconds() as f64 / 1000.0;
+                ui.label(format!("clock offset: {:+.0}s", secs))
+                    .on_hover_text("Difference between this computer's clock and Torn's");
+            }
+            else {
+                ui.label("clock offset: not synced");
+            }
+
+            match client().health() {
+                ApiHealth::Ok => {}
+                ApiHealth::Degraded(x) => {
+                    ui.colored_label(egui::Color32::YELLOW, "API degraded")
+                        .on_hover_text(format!("{} request(s) in a row have failed", x));
+                }
+                ApiHealth::Paused(until) => {
+                    let secs = (until - Utc::now()).num_seconds().max(0);
+                    ui.colored_label(egui::Color32::RED, format!("API degraded, paused for {}s", secs))
+                        .on_hover_text(GetInfoError::Paused.help_text());
+                }
+            }
+
+            if let Some(faction) = self.key_caps.as_ref().and_then(|x| x.faction_id) {
+                self.faction_status(ui, faction);
+            }
+
+            let stats = entity_cache().stats();
+            ui.label(format!("cache: {:.0}% hits", stats.hit_rate() * 100.0))
+                .on_hover_text(format!("{} from cache, {} combined with another request, {} fetched",
+                    stats.hits, stats.coalesced, stats.misses));
+
+            if let Some(x) = &self.save_error {
+                ui.colored_label(egui::Color32::RED, format!("couldn't save settings: {}", x))
+                    .on_hover_text(self.paths.persistence.display().to_string());
+            }
+
+            if let Some(x) = &self.webhook_error {
+                ui.colored_label(egui::Color32::RED, format!("webhooks: {}", x));
+            }
+
+            if let Some(x) = self.notifier.as_ref().and_then(|x| x.problem()) {
+                ui.colored_label(egui::Color32::YELLOW, format!("webhooks: {}", x));
+            }
+
+            if let Some(x) = &self.history_error {
+                ui.colored_label(egui::Color32::RED, format!("history: {}", x))
+                    .on_hover_text(self.paths.history.display().to_string());
+            }
+
+            if let Some(x) = &self.key_store_error {
+                ui.colored_label(egui::Color32::YELLOW, format!("key storage: {}", x));
+            }
+
+            if let Some((_, addr)) = &self.live {
+                ui.label(format!("serving on {}", addr))
+                    .on_hover_text("Other tools can read monitors, war and chain from here");
+            }
+        });
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.with_layout(egui::Layout::left_to_right(egui::Align::LEFT), |ui| {
                 ui.label("API key:");
 
-                ui.add(egui::TextEdit::singleline(&mut self.apikey));
+                ui.add(egui::TextEdit::singleline(&mut self.apikey).password(!self.show_key));
+                ui.checkbox(&mut self.show_key, "Show");
+
+                if ui.button("Test key").clicked() {
+                    match executor::block_on(test_key(&self.apikey)) {
+                        Ok(x) => {
+                            self.key_caps = Some(x.capabilities.clone());
+                            self.key_report = Some(x);
+                        },
+                        Err(x) => self.api_error = Some(x),
+                    }
+                }
             });
 
             ui.with_layout(egui::Layout::left_to_right(egui::Align::LEFT), |ui| {
                 // Reload button
-                if ui.button("Reload").clicked() {
-                    executor::block_on((async || {
-                        match self.update_torn().await{
-                            Ok(_) => (),
-                            Err(x) => {match x{
-                                GetInfoError::WrongKey => self.errmodal_open = true,
-                                GetInfoError::InvalidId => {},
-                                GetInfoError::Other(x) =>  println!("Error: {:?}", x),
-                            }}
-                        }
-                    })());
+                let reload = ui.add_enabled(!self.refresher.running(), egui::Button::new("Reload"))
+                    .on_disabled_hover_text("Refreshing…");
+                if reload.clicked() {
+                    let job = self.refresh_job();
+                    self.refresher.start(job, true, self.clock.now());
+                }
 
-                    for i in &mut self.monitors{
-                        i.update_torn(&self.apikey);
-                    }
-                };
+                if self.refresher.running() {
+                    ui.spinner();
+                }
 
-                let mut selected = MonitorList::None;
+                if ui.button("War history").clicked() {
+                    self.war_window.show_window();
+                }
 
-                egui::ComboBox::from_label("")
-                    .selected_text(format!("{:?}", selected))
-                    .show_ui(ui, |ui| {
-                        ui.selectable_value(&mut selected, MonitorList::None, " ");
-                        ui.selectable_value(&mut selected, MonitorList::Simple(SimpleHospMonitor::default()), "Simple monitor");
-                    }
-                    );
+                ui.separator();
 
-                if selected !=  MonitorList::None{
-                    match selected {
-                        MonitorList::None => {}
-                        MonitorList::Simple(x) => {self.monitors.push(MonitorList::Simple(x))},
-                    }
+                ui.add(egui::TextEdit::singleline(&mut self.group_name_buf)
+                    .hint_text("Group name")
+                    .desired_width(100.0));
+
+                let name = self.group_name_buf.trim().to_string();
+                if ui.add_enabled(!name.is_empty(), egui::Button::new("Add group")).clicked() {
+                    self.profile_mut().groups.push(MonitorGroup::new(&name));
+                    self.group_name_buf.clear();
                 }
             });
 
             ui.separator();
 
-            egui::Grid::new("detrep_month_det")
-                .spacing(egui::Vec2::new(10.0, 2.0))
-                .striped(false)
-                .show(ui, |ui| {
-                for mut i in self.monitors.clone(){
-                    i.update(self, ui, ctx, |x: &mut Self| println!("test (key): {}", x.apikey));
-                    ui.end_row();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                let mut group = 0;
+                while group < self.profile().groups.len() {
+                    let name = self.profile().groups[group].name.clone();
+
+                    let removed = egui::CollapsingHeader::new(name)
+                        .id_salt(("group", self.active, group))
+                        .default_open(true)
+                        .show(ui, |ui| self.group_ui(ui, ctx, group))
+                        .body_returned
+                        .unwrap_or(false);
+
+                    // The next group has moved into this one's place
+                    if !removed {
+                        group += 1;
+                    }
                 }
             });
         });
+        // Countdowns tick every frame, and refreshes are checked for here
         ctx.request_repaint();
     }
 }
 
+/// Windows release builds are windowed programs with no console of their
+/// own, so subcommands, `--tui`, `--help` and usage errors would go nowhere.
+/// Borrow the console torndkt was started from, if there is one and output
+/// isn't already redirected. Started from Explorer, there's none to borrow.
+#[cfg(windows)]
+fn attach_console() {
+    unsafe extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+        fn GetStdHandle(std_handle: u32) -> *mut std::ffi::c_void;
+    }
+
+    /// `ATTACH_PARENT_PROCESS`
+    const PARENT_PROCESS: u32 = u32::MAX;
+
+    /// `STD_OUTPUT_HANDLE`
+    const STD_OUTPUT: u32 = -11i32 as u32;
+
+    // Safe to call in any state: both just fail if there's nothing to do
+    unsafe {
+        if GetStdHandle(STD_OUTPUT).is_null() {
+            AttachConsole(PARENT_PROCESS);
+        }
+    }
+}
+
+/// Other platforms always have their terminal
+#[cfg(not(windows))]
+fn attach_console() {}
+
 fn main() -> eframe::Result<()> {
-    let result = PersistedData::load("persistence.json");
-    let mut app = ExampleApp::default();
+    attach_console();
+
+    let args = cli::Args::parse();
+    let paths = ConfigPaths::resolve(args.config);
+
+    let mut client = ApiClient::new(ApiConfig {
+        base_url: args.api_url,
+        timeout: Duration::from_secs_f64(args.api_timeout),
+        retries: args.api_retries,
+        key_in_header: args.key_in_header,
+        ..ApiConfig::default()
+    });
+
+    if let Some(path) = &args.record {
+        match Recorder::create(path) {
+            Ok(x) => client = client.with_recorder(x),
+            Err(x) => eprintln!("Can't record to {}: {}", path.display(), x),
+        }
+    }
+
+    let mut replay_clock = None;
+    if let Some(path) = &args.replay {
+        let recording = match Recording::load(path) {
+            Ok(x) => x,
+            Err(x) => {
+                eprintln!("Can't replay {}: {}", path.display(), x);
+                std::process::exit(1);
+            }
+        };
+
+        let replay = Replay::new(recording, args.replay_speed, Arc::new(SystemClock));
+        replay_clock = Some(replay.clock.clone());
+        client = client.with_replay(replay);
+    }
+
+    install(client);
+
+    let result = PersistedData::load(paths.load_path());
+    let mut app = ExampleApp{
+        keys: KeyManager::new(KeyBackend::default(), &paths.key_file, ""),
+        ..ExampleApp::default()
+    };
+
+    // A missing file just means first launch. Anything else means the file is
+    // unreadable, so keep a copy of it before it gets overwritten with defaults.
+    match result{
+        Ok(x) => {
+            app.keys = KeyManager::new(x.key_backend, &paths.key_file, &x.api_key);
+
+            if !x.profiles.is_empty() {
+                app.active = x.profiles.iter()
+                    .position(|p| p.name == x.active_profile)
+                    .unwrap_or(0);
+                app.profiles = x.profiles;
+            }
+
+            app.history_settings = x.history;
+        }
+        Err(x) if x.is_not_found() => {}
+        Err(x) => {
+            let backup = match PersistedData::backup(paths.load_path()) {
+                Ok(path) => format!("A copy of the old file was saved to {}.", path.display()),
+                Err(err) => format!("The old file couldn't be backed up either ({}).", err),
+            };
+
+            app.load_notice = Some(format!("Saved settings couldn't be loaded: {}. {}", x, backup));
+        }
+    }
+
+    if let Some(name) = args.profile {
+        match app.profiles.iter().position(|x| x.name == name) {
+            Some(x) => app.active = x,
+            // The window asks, but there's no one to ask in a script
+            None if args.command.is_some() || args.tui => {
+                let names = app.profiles.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+                eprintln!("No profile called \"{}\". Profiles: {}", name, names.join(", "));
+                std::process::exit(1);
+            }
+            None => app.missing_profile = Some(name),
+        }
+    }
+
+    if let Some(clock) = replay_clock {
+        app.clock = clock;
+    }
+
+    app.paths = paths;
+    app.keys.select(&app.profile().key_slot.clone());
+    app.key_slot_buf = app.profile().key_slot.clone();
+    app.load_key();
+    app.retire_legacy();
+
+    // Replays are of things that already happened, which are already in the
+    // history if they were recorded at the time
+    if app.history_settings.enabled && args.replay.is_none() {
+        match History::open(&app.paths.history, &app.history_settings) {
+            Ok(x) => app.history = Some(Arc::new(x)),
+            Err(x) => app.history_error = Some(x.to_string()),
+        }
+    }
+
+    if let Some(command) = args.command {
+        let runtime = tokio::runtime::Runtime::new().expect("Couldn't start async runtime");
+
+        if let Err(x) = runtime.block_on(commands::run(&mut app, command, args.json)) {
+            eprintln!("{}", x);
+            std::process::exit(1);
+        }
+
+        return Ok(());
+    }
+
+    if let Some(port) = args.serve {
+        let live = Arc::new(LiveState::new(app.clock.clone()));
+
+        match live::spawn(port, live.clone()) {
+            Ok(addr) => app.live = Some((live, addr)),
+            Err(x) => eprintln!("Can't serve on port {}: {}", port, x),
+        }
+    }
+
+    app.start_notifier();
+    if let Some(x) = &app.webhook_error {
+        eprintln!("Can't start webhooks: {}", x);
+    }
+
+    if args.tui {
+        if let Err(x) = tui::run(app) {
+            eprintln!("Terminal error: {}", x);
+            std::process::exit(1);
+        }
 
-    // If error, do nothing. Otherwise, actually use the data
-    if let Ok(x) = result{
-        app.apikey = x.api_key;
-        app.monitors = x.monitors;
+        return Ok(());
     }
 
     let native_options = eframe::NativeOptions {
@@ -1,63 +1,46 @@
 #![warn(missing_docs)]
-use std::collections::HashMap;
-use chrono::{DateTime, Utc};
 use eframe::egui;
 use eframe::egui::{Color32, Ui};
 use egui_extras::{Size, StripBuilder};
-use futures::executor;
 use serde::{Deserialize, Serialize};
-use torn_api::request::IntoRequest;
-use torn_api::request::models::{UserRequest};
-use crate::api::api::{run_request, GetInfoError};
-use crate::monitors::core::Monitor;
+use crate::api::api::GetInfoError;
+use crate::api::key::Selection;
+use crate::clock::Clock;
+use crate::monitors::core::{Monitor, MonitorState, MonitorSummary};
+use crate::store::EntityStore;
 use crate::util::to_hms;
 
 /// A simple hospitalisation monitor, that just shows how long it will last and
 /// the name of the user, when given an id.
-#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct SimpleHospMonitor{
     /// User ID to track
     pub id: u32,
-
-    /// Hospital timestamp, as given by tornapi
-    #[serde(skip_serializing, skip_deserializing)]
-    hosp_timestamp:  DateTime<Utc>,
-
-    /// Internal flag for api errors
-    #[serde(skip_serializing, skip_deserializing)]
-    id_error: bool,
-
-    /// Torn API key. Stored internally for error messages
-    #[serde(skip_serializing, skip_deserializing)]
-    apikey: String,
-
-    /// Internally stored username, as given by the torn api
-    #[serde(skip_serializing, skip_deserializing)]
-    name: String
 }
 
-impl Default for SimpleHospMonitor{
-    fn default() -> SimpleHospMonitor{
-        SimpleHospMonitor{
-            id: 0,
-            hosp_timestamp:  Utc::now(),
-            id_error: false,
-            apikey: String::new(),
-            name: String::new()
-        }
+impl SimpleHospMonitor{
+    /// API selections needed to run the monitor
+    pub const REQUIRED_SELECTIONS: &'static [Selection] = &[Selection::new("user", "profile")];
+
+    /// A monitor for the given user id
+    pub fn new(id: u32) -> SimpleHospMonitor {
+        SimpleHospMonitor { id }
     }
-}
 
-#[derive(Deserialize, Serialize, Debug,  PartialEq, Clone)]
-struct ApiResponse {
-    pub name: String,
+    /// Seconds left until the user leaves hospital, according to `clock`.
+    /// Never negative, and zero if the user hasn't been fetched.
+    pub fn time_left(&self, store: &EntityStore, clock: &dyn Clock) -> i64{
+        let Some(Ok(user)) = store.user(self.id) else { return 0 };
 
-    /// Hospital timestamp, jail timestamp
-    pub states: HashMap<String, i64>,
+        (user.hospital_until - clock.now())
+            .as_seconds_f32()
+            .ceil()
+            .clamp(0.0, f32::MAX) as i64
+    }
 }
 
 impl Monitor for SimpleHospMonitor{
-    fn update<F, C>(&mut self, caller_ref: &mut C, container: &mut Ui, _ctx: &egui::Context, close_cb: F)
+    fn update<F, C>(&mut self, caller_ref: &mut C, container: &mut Ui, _ctx: &egui::Context, store: &EntityStore, clock: &dyn Clock, close_cb: F)
         where F:  FnOnce(&mut C)
     {
         // Strip for layouting
@@ -68,16 +51,18 @@ impl Monitor for SimpleHospMonitor{
             .size(Size::exact(30.0)) // Col 4: close button
             .horizontal(|mut strip| {
                 let mut input = self.id.to_string();
+                let user = store.user(self.id);
+                let id_error = matches!(user, Some(Err(GetInfoError::InvalidId)));
 
                 // UI edittext
                 strip.cell(|ui| {
-                    if self.id_error{
+                    if id_error{
                         ui.style_mut().visuals.extreme_bg_color =  Color32::from_rgb(255, 0, 0);
                     }
 
                     let textedit = ui.text_edit_singleline(&mut input);
 
-                    textedit.on_hover_text(if self.id_error {"ID doesn't exist"} else {"ID to query"});
+                    textedit.on_hover_text(if id_error {"ID doesn't exist"} else {"ID to query"});
                 });
 
                 input = input.chars()
@@ -95,20 +80,19 @@ impl Monitor for SimpleHospMonitor{
                 };
 
                 // Get time left in hospital
-                let time_diff = (self.hosp_timestamp - Utc::now())
-                    .as_seconds_f32()
-                    .ceil()
-                    .clamp(0.0, f32::MAX);
+                let time_diff = self.time_left(store, clock);
 
                 // Col 2: Time left in hospital
                 strip.cell(|ui| {
-                    let lbl = ui.label(format!("ETA: {}", to_hms(time_diff.ceil() as i64)));
+                    let lbl = ui.label(format!("ETA: {}", to_hms(time_diff)));
                     lbl.on_hover_text("Time to leave hospital");
                 });
 
                 // Col 3: Username
                 strip.cell(|ui| {
-                    ui.label(self.name.clone());
+                    if let Some(Ok(x)) = &user {
+                        ui.label(&x.name);
+                    }
                 });
 
                 // Col 4: close button
@@ -120,38 +104,29 @@ impl Monitor for SimpleHospMonitor{
             });
     }
 
-    fn update_torn(&mut self, apikey: &str) -> Result<(),GetInfoError>{
-        self.apikey = apikey.to_string();
-
-        let built = UserRequest::builder()
-            .id(self.id.to_string())
-            .api_key_public(apikey)
-            .build()
-            .into_request().1;
-
-        let resp: Result<ApiResponse, _> = executor::block_on(run_request(&built));
-
-        match resp {
-            Ok(resp) => {
-                let hosp_datetime = DateTime::from_timestamp(
-                    resp.states["hospital_timestamp"],
-                    0
-                ).unwrap();
-
-                self.hosp_timestamp = hosp_datetime;
-                self.name = resp.name;
-
-                Ok(())
-            }
-            Err(x) => {
-                match x {
-                    GetInfoError::InvalidId => {self.id_error = true;}
-                    GetInfoError::WrongKey => {}
-                    GetInfoError::Other(_) => {}
-                }
+    fn users(&self) -> Vec<u32> {
+        vec![self.id]
+    }
 
-                Err(x)
-            }
+    fn summary(&self, store: &EntityStore, clock: &dyn Clock) -> MonitorSummary {
+        let user = store.user(self.id);
+        let time_left = self.time_left(store, clock);
+
+        let state = match &user {
+            None => MonitorState::Unknown,
+            Some(Err(x)) => MonitorState::Error(x.clone()),
+            Some(Ok(_)) if time_left > 0 => MonitorState::Waiting,
+            Some(Ok(_)) => MonitorState::Ready,
+        };
+
+        let user = user.and_then(|x| x.ok());
+
+        MonitorSummary {
+            target: format!("[{}]", self.id),
+            name: user.as_ref().map(|x| x.name.clone()).unwrap_or_default(),
+            ends_at: user.filter(|_| state == MonitorState::Waiting).map(|x| x.hospital_until),
+            state,
+            time_left,
         }
     }
 }
\ No newline at end of file
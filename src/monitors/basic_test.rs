@@ -0,0 +1,60 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use crate::api::api::GetInfoError;
+use crate::clock::FixedClock;
+use crate::monitors::basic::SimpleHospMonitor;
+use crate::monitors::core::{Monitor, MonitorState, MonitorSummary};
+use crate::store::{EntityStore, User};
+
+/// A store holding one user, in hospital until `until`
+fn store(id: u32, until: DateTime<Utc>) -> EntityStore {
+    let store = EntityStore::default();
+    store.insert_user(id, Ok(User {
+        id,
+        name: "Test".to_string(),
+        hospital_until: until,
+        faction_id: None,
+    }));
+
+    store
+}
+
+#[test]
+fn time_left_counts_down() {
+    let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let monitor = SimpleHospMonitor::new(1);
+    let store = store(1, now + TimeDelta::seconds(90));
+
+    assert_eq!(monitor.time_left(&store, &FixedClock(now)), 90);
+    assert_eq!(monitor.time_left(&store, &FixedClock(now + TimeDelta::seconds(30))), 60);
+    assert_eq!(monitor.time_left(&store, &FixedClock(now + TimeDelta::milliseconds(500))), 90);
+}
+
+#[test]
+fn time_left_never_negative() {
+    let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let store = store(1, now);
+
+    assert_eq!(SimpleHospMonitor::new(1).time_left(&store, &FixedClock(now + TimeDelta::hours(1))), 0);
+    assert_eq!(SimpleHospMonitor::new(2).time_left(&store, &FixedClock(now)), 0);
+}
+
+#[test]
+fn summary_states() {
+    let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let store = store(1, now + TimeDelta::seconds(90));
+    store.insert_user(2, Err(GetInfoError::InvalidId));
+
+    let summary = SimpleHospMonitor::new(1).summary(&store, &FixedClock(now));
+    assert_eq!(summary, MonitorSummary {
+        target: "[1]".to_string(),
+        name: "Test".to_string(),
+        state: MonitorState::Waiting,
+        time_left: 90,
+        ends_at: Some(now + TimeDelta::seconds(90)),
+    });
+
+    let later = FixedClock(now + TimeDelta::seconds(90));
+    assert_eq!(SimpleHospMonitor::new(1).summary(&store, &later).state, MonitorState::Ready);
+    assert_eq!(SimpleHospMonitor::new(2).summary(&store, &later).state, MonitorState::Error(GetInfoError::InvalidId));
+    assert_eq!(SimpleHospMonitor::new(3).summary(&store, &later).state, MonitorState::Unknown);
+}
\ No newline at end of file
@@ -1,16 +1,63 @@
+use chrono::{DateTime, Utc};
 use eframe::egui;
 use eframe::egui::Ui;
 use serde::Serialize;
 use serde::de::DeserializeOwned;
 use crate::api::api::GetInfoError;
+use crate::clock::Clock;
+use crate::store::EntityStore;
+
+/// How whatever a monitor watches is doing
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub enum MonitorState {
+    /// Not fetched yet
+    Unknown,
+
+    /// Fetching it failed
+    Error(GetInfoError),
+
+    /// Its timer is still running
+    Waiting,
+
+    /// Its timer has run out
+    Ready,
+
+    /// It has no timer, just figures to show
+    Info,
+}
+
+/// What a monitor shows, for front ends other than egui
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MonitorSummary {
+    /// What's being watched, e.g. a user id
+    pub target: String,
+
+    /// Name of what's being watched, if it's known
+    pub name: String,
+
+    /// How it's doing
+    pub state: MonitorState,
+
+    /// Seconds left on its timer
+    pub time_left: i64,
+
+    /// When its timer runs out, if it has one running
+    pub ends_at: Option<DateTime<Utc>>,
+}
 
 /// The monitor trait. All monitors should implement this, but there's not really
 /// anything to enforce it.
 pub trait Monitor: Serialize + DeserializeOwned{
-    /// Run on each egui update
-    fn update<F, C>(&mut self, caller_ref: &mut C, container: &mut Ui, ctx: &egui::Context, close_cb: F)
+    /// Run on each egui update. API data should be read from `store`, and
+    /// anything time-dependent should use `clock` rather than reading the
+    /// system time.
+    fn update<F, C>(&mut self, caller_ref: &mut C, container: &mut Ui, ctx: &egui::Context, store: &EntityStore, clock: &dyn Clock, close_cb: F)
         where F: FnOnce(&mut C);
 
-    /// Update tornapi data
-    fn update_torn(&mut self, apikey: &str) -> Result<(), GetInfoError>;
+    /// User ids the monitor shows. These are fetched into the store on
+    /// refresh, once each however many monitors show them.
+    fn users(&self) -> Vec<u32>;
+
+    /// What the monitor would show right now, without drawing it
+    fn summary(&self, store: &EntityStore, clock: &dyn Clock) -> MonitorSummary;
 }
\ No newline at end of file
@@ -0,0 +1,100 @@
+#![warn(missing_docs)]
+use eframe::egui;
+use eframe::egui::Ui;
+use serde::{Deserialize, Serialize};
+use crate::api::cache::{FACTION_ATTACKS, FACTION_CHAIN, FACTION_WARS};
+use crate::api::key::Selection;
+use crate::clock::Clock;
+use crate::leaderboard::{Column, MemberStats};
+use crate::monitors::core::{Monitor, MonitorState, MonitorSummary};
+use crate::store::EntityStore;
+
+/// The members of our faction who've done the most in the latest ranked war,
+/// from the leaderboard worked out from history on each refresh
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct LeaderboardMonitor {
+    /// How many members to show
+    pub top: u32,
+
+    /// What they're ranked by, biggest first
+    pub sort: Column,
+}
+
+impl Default for LeaderboardMonitor {
+    fn default() -> Self {
+        LeaderboardMonitor { top: 5, sort: Column::Respect }
+    }
+}
+
+impl LeaderboardMonitor {
+    /// API selections needed to run the monitor. Attacks are only recorded
+    /// for the faction whose chain and war are being fetched.
+    pub const REQUIRED_SELECTIONS: &'static [Selection] = &[FACTION_CHAIN, FACTION_WARS, FACTION_ATTACKS];
+
+    /// Columns offered to rank by. Ranking by name isn't useful here.
+    const SORTABLE: [Column; 6] = [Column::Hits, Column::Respect, Column::Assists, Column::Losses, Column::FairFight, Column::OutsideHits];
+
+    /// The members to show, best first. `None` if there's no leaderboard yet.
+    pub fn leaders(&self, store: &EntityStore) -> Option<Vec<MemberStats>> {
+        let mut leaderboard = store.leaderboard()?;
+        leaderboard.sort(self.sort, true);
+        leaderboard.members.truncate(self.top as usize);
+
+        Some(leaderboard.members)
+    }
+}
+
+impl Monitor for LeaderboardMonitor {
+    fn update<F, C>(&mut self, caller_ref: &mut C, container: &mut Ui, _ctx: &egui::Context, store: &EntityStore, _clock: &dyn Clock, close_cb: F)
+        where F: FnOnce(&mut C)
+    {
+        container.vertical(|ui| {
+            ui.horizontal(|ui| {
+                ui.label("Top");
+                ui.add(egui::DragValue::new(&mut self.top).range(1..=50));
+
+                egui::ComboBox::from_id_salt(ui.id().with("leaderboard_sort"))
+                    .selected_text(format!("by {}", self.sort.title()))
+                    .show_ui(ui, |ui| {
+                        for i in Self::SORTABLE {
+                            ui.selectable_value(&mut self.sort, i, i.title());
+                        }
+                    });
+
+                if ui.button("x").clicked() {
+                    close_cb(caller_ref);
+                }
+            });
+
+            match self.leaders(store) {
+                None => { ui.weak("No war recorded yet"); }
+                Some(x) if x.is_empty() => { ui.weak("No hits yet"); }
+                Some(x) => {
+                    for (rank, member) in x.iter().enumerate() {
+                        ui.label(format!("{}. {}: {}", rank + 1, member.name, self.sort.value(member)));
+                    }
+                }
+            }
+        });
+    }
+
+    fn users(&self) -> Vec<u32> {
+        vec![]
+    }
+
+    fn summary(&self, store: &EntityStore, _clock: &dyn Clock) -> MonitorSummary {
+        let leaders = self.leaders(store);
+
+        MonitorSummary {
+            target: format!("[top {}]", self.top),
+            name: leaders.iter()
+                .flatten()
+                .map(|x| format!("{} ({})", x.name, self.sort.value(x)))
+                .collect::<Vec<_>>()
+                .join(", "),
+            state: if leaders.is_some() { MonitorState::Info } else { MonitorState::Unknown },
+            time_left: 0,
+            ends_at: None,
+        }
+    }
+}
\ No newline at end of file
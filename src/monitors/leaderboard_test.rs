@@ -0,0 +1,35 @@
+use chrono::DateTime;
+use crate::clock::FixedClock;
+use crate::leaderboard::{Column, Leaderboard, MemberStats};
+use crate::monitors::core::{Monitor, MonitorState};
+use crate::monitors::leaderboard::LeaderboardMonitor;
+use crate::store::EntityStore;
+
+/// A member with the given hits and respect
+fn member(id: u32, name: &str, hits: u32, respect: f64) -> MemberStats {
+    MemberStats { id, name: name.to_string(), hits, respect, ..MemberStats::default() }
+}
+
+#[test]
+fn shows_the_top_members() {
+    let clock = FixedClock(DateTime::from_timestamp(1_700_000_000, 0).unwrap());
+    let store = EntityStore::default();
+    let monitor = LeaderboardMonitor { top: 2, sort: Column::Hits };
+
+    assert_eq!(monitor.leaders(&store), None);
+    assert_eq!(monitor.summary(&store, &clock).state, MonitorState::Unknown);
+
+    store.set_leaderboard(Leaderboard {
+        war_id: 1,
+        members: vec![member(1, "Alice", 3, 10.0), member(2, "Bob", 5, 8.0), member(3, "Carol", 1, 2.0)],
+    });
+
+    let leaders = monitor.leaders(&store).unwrap();
+    assert_eq!(leaders.iter().map(|x| x.id).collect::<Vec<_>>(), vec![2, 1]);
+
+    let summary = monitor.summary(&store, &clock);
+    assert_eq!(summary.state, MonitorState::Info);
+    assert_eq!(summary.target, "[top 2]");
+    assert_eq!(summary.name, "Bob (5), Alice (3)");
+    assert!(monitor.users().is_empty());
+}
\ No newline at end of file
@@ -7,5 +7,14 @@ pub mod core;
 /// Simple monitor
 pub mod basic;
 
+/// Top of the war leaderboard
+pub mod leaderboard;
+
 /// Item selection
-pub mod selection;
\ No newline at end of file
+pub mod selection;
+
+#[cfg(test)]
+mod basic_test;
+
+#[cfg(test)]
+mod leaderboard_test;
\ No newline at end of file
@@ -1,10 +1,12 @@
 use std::fmt::Display;
-use eframe::egui;
 use eframe::egui::{Context, Ui};
 use serde::{Deserialize, Serialize};
-use crate::api::api::GetInfoError;
+use crate::api::key::{KeyCapabilities, Selection};
+use crate::clock::Clock;
 use crate::monitors::basic::SimpleHospMonitor;
-use crate::monitors::core::Monitor;
+use crate::monitors::leaderboard::LeaderboardMonitor;
+use crate::monitors::core::{Monitor, MonitorState, MonitorSummary};
+use crate::store::EntityStore;
 
 
 /// Enum to encode all monitor types
@@ -16,12 +18,57 @@ pub enum MonitorList {
 
     /// Simple monitor with just hospitalisation time and name
     Simple(SimpleHospMonitor),
+
+    /// Top members of the latest ranked war's leaderboard
+    Leaderboard(LeaderboardMonitor),
+}
+
+impl MonitorList {
+    /// One of each kind of monitor, in the order they're offered to the user
+    pub fn kinds() -> Vec<MonitorList> {
+        vec![MonitorList::Simple(SimpleHospMonitor::default()), MonitorList::Leaderboard(LeaderboardMonitor::default())]
+    }
+
+    /// Short description of what the monitor watches, e.g. a user id
+    pub fn target_label(&self) -> String {
+        match self {
+            MonitorList::Simple(x) => format!("[{}]", x.id),
+            MonitorList::Leaderboard(x) => format!("[top {}]", x.top),
+            MonitorList::None => String::new(),
+        }
+    }
+
+    /// Whether two monitors are the same kind and watch the same thing, ignoring
+    /// any data they've fetched
+    pub fn same_target(&self, other: &MonitorList) -> bool {
+        match (self, other) {
+            (MonitorList::Simple(a), MonitorList::Simple(b)) => a.id == b.id,
+            (MonitorList::Leaderboard(a), MonitorList::Leaderboard(b)) => a == b,
+            (MonitorList::None, MonitorList::None) => true,
+            _ => false,
+        }
+    }
+
+    /// API selections the monitor needs the key to have access to
+    pub fn required_selections(&self) -> &'static [Selection] {
+        match self {
+            MonitorList::Simple(_) => SimpleHospMonitor::REQUIRED_SELECTIONS,
+            MonitorList::Leaderboard(_) => LeaderboardMonitor::REQUIRED_SELECTIONS,
+            MonitorList::None => &[],
+        }
+    }
+
+    /// Whether a key with the given capabilities can run this monitor
+    pub fn servable_by(&self, caps: &KeyCapabilities) -> bool {
+        caps.missing(self.required_selections()).is_empty()
+    }
 }
 
 impl Display for MonitorList {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let str = match self {
             MonitorList::Simple(_) => "Simple".to_string(),
+            MonitorList::Leaderboard(_) => "Leaderboard".to_string(),
             MonitorList::None => "".to_string(),
         };
         write!(f, "{}", str)
@@ -29,19 +76,35 @@ impl Display for MonitorList {
 }
 
 impl Monitor for MonitorList{
-    fn update<F,C>(&mut self, caller_ref: &mut C, container: &mut Ui, ctx: &Context, close_cb: F)
+    fn update<F,C>(&mut self, caller_ref: &mut C, container: &mut Ui, ctx: &Context, store: &EntityStore, clock: &dyn Clock, close_cb: F)
         where F: FnOnce(&mut C)
     {
         match self{
-            MonitorList::Simple(x) => {x.update(caller_ref, container, ctx, close_cb);},
+            MonitorList::Simple(x) => {x.update(caller_ref, container, ctx, store, clock, close_cb);},
+            MonitorList::Leaderboard(x) => {x.update(caller_ref, container, ctx, store, clock, close_cb);},
             MonitorList::None => {}
         }
     }
 
-    fn update_torn(&mut self, apikey: &str) -> Result<(), GetInfoError> {
+    fn users(&self) -> Vec<u32> {
+        match self{
+            MonitorList::Simple(x) => x.users(),
+            MonitorList::Leaderboard(x) => x.users(),
+            MonitorList::None => vec![]
+        }
+    }
+
+    fn summary(&self, store: &EntityStore, clock: &dyn Clock) -> MonitorSummary {
         match self{
-            MonitorList::Simple(x) => x.update_torn(apikey),
-            MonitorList::None => Ok(())
+            MonitorList::Simple(x) => x.summary(store, clock),
+            MonitorList::Leaderboard(x) => x.summary(store, clock),
+            MonitorList::None => MonitorSummary {
+                target: String::new(),
+                name: String::new(),
+                state: MonitorState::Unknown,
+                time_left: 0,
+                ends_at: None,
+            }
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,162 @@
+//! Where persistence files live, and how they get written
+#![warn(missing_docs)]
+
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use directories::ProjectDirs;
+use crate::persistence::PersistenceError;
+
+/// Name of the main persistence file
+const PERSISTENCE_FILE: &str = "persistence.json";
+
+/// Name of the encrypted API key file
+const KEY_FILE: &str = "apikey.enc";
+
+/// Name of the history database
+const HISTORY_FILE: &str = "history.sqlite";
+
+/// How many old copies of a file to keep when overwriting it
+pub const BACKUP_COUNT: usize = 3;
+
+/// Paths of all the files torndkt saves
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ConfigPaths {
+    /// Main persistence file
+    pub persistence: PathBuf,
+
+    /// Encrypted API key, for when there's no keyring
+    pub key_file: PathBuf,
+
+    /// History database
+    pub history: PathBuf,
+
+    /// Persistence file from before settings moved to the config directory, to
+    /// load from if there's nothing in the config directory yet
+    pub legacy_persistence: Option<PathBuf>,
+}
+
+impl ConfigPaths {
+    /// Work out where files go. `config` overrides the persistence file path,
+    /// with the other files going next to it. Otherwise, the platform's config
+    /// directory is used (`$XDG_CONFIG_HOME/torndkt` on Linux, `%APPDATA%` on
+    /// Windows), falling back to the working directory if there isn't one.
+    pub fn resolve(config: Option<PathBuf>) -> ConfigPaths {
+        if let Some(config) = config {
+            return ConfigPaths {
+                key_file: sibling(&config, KEY_FILE),
+                history: sibling(&config, HISTORY_FILE),
+                persistence: config,
+                legacy_persistence: None,
+            };
+        }
+
+        let dir = ProjectDirs::from("", "", "torndkt")
+            .map(|x| x.config_dir().to_path_buf())
+            .unwrap_or_default();
+
+        ConfigPaths {
+            persistence: dir.join(PERSISTENCE_FILE),
+            key_file: dir.join(KEY_FILE),
+            history: dir.join(HISTORY_FILE),
+            legacy_persistence: Some(PathBuf::from(PERSISTENCE_FILE)),
+        }
+    }
+
+    /// The persistence file to load from. That's normally just the persistence
+    /// file, unless this is the first launch since it moved, in which case it's
+    /// the old one in the working directory.
+    pub fn load_path(&self) -> &Path {
+        match &self.legacy_persistence {
+            Some(legacy) if !self.persistence.exists() && legacy.exists() => legacy,
+            _ => &self.persistence,
+        }
+    }
+
+    /// The persistence file from before settings moved to the config
+    /// directory, if it's still there
+    pub fn legacy(&self) -> Option<&Path> {
+        self.legacy_persistence.as_deref().filter(|x| *x != self.persistence && x.exists())
+    }
+
+    /// Once settings have been saved to the persistence file, move the old
+    /// one out of the way as `persistence.json.bak`, without the plain text
+    /// API key old versions kept in it. Returns where it went, or `None` if
+    /// there was nothing to do.
+    pub fn retire_legacy(&self) -> Result<Option<PathBuf>, PersistenceError> {
+        let Some(legacy) = self.legacy().filter(|_| self.persistence.exists()) else { return Ok(None) };
+
+        let mut data: serde_json::Value = serde_json::from_slice(&std::fs::read(legacy)?)?;
+        if let Some(x) = data.as_object_mut() {
+            x.remove("api_key");
+        }
+
+        let backup = with_suffix(legacy, "bak");
+        write_atomic(&backup, serde_json::to_string(&data)?.as_bytes(), 0)?;
+        std::fs::remove_file(legacy)?;
+
+        Ok(Some(backup))
+    }
+}
+
+/// A file in the same directory as `path`
+fn sibling(path: &Path, name: &str) -> PathBuf {
+    path.parent()
+        .map(|x| x.join(name))
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+/// `path` with an extra extension tacked on, e.g. `persistence.json.tmp`
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name()
+        .map(|x| x.to_os_string())
+        .unwrap_or_else(|| OsString::from(PERSISTENCE_FILE));
+    name.push(".");
+    name.push(suffix);
+
+    path.with_file_name(name)
+}
+
+/// Shift `path.1`..`path.N` along by one, and copy `path` to `path.1`. The
+/// oldest copy falls off the end.
+fn rotate_backups(path: &Path, count: usize) -> Result<(), PersistenceError> {
+    if count == 0 || !path.exists() {
+        return Ok(());
+    }
+
+    for i in (1..count).rev() {
+        let from = with_suffix(path, &i.to_string());
+
+        if from.exists() {
+            std::fs::rename(&from, with_suffix(path, &(i + 1).to_string()))?;
+        }
+    }
+
+    std::fs::copy(path, with_suffix(path, "1"))?;
+    Ok(())
+}
+
+/// Write a file so that a crash part way through can't leave it truncated:
+/// the data goes to a temporary file which then replaces the real one. The
+/// previous `backups` versions are kept alongside it. Does nothing if the
+/// contents haven't changed, so unchanged saves don't push out backups.
+pub fn write_atomic(path: &Path, contents: &[u8], backups: usize) -> Result<(), PersistenceError> {
+    if std::fs::read(path).is_ok_and(|x| x == contents) {
+        return Ok(());
+    }
+
+    if let Some(dir) = path.parent().filter(|x| !x.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let tmp = with_suffix(path, "tmp");
+    {
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+    }
+
+    rotate_backups(path, backups)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+use crate::persistence::files::{with_suffix, write_atomic, ConfigPaths};
+
+/// A fresh directory for a test
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("torndkt_test_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn config_override() {
+    let paths = ConfigPaths::resolve(Some(PathBuf::from("/some/dir/war.json")));
+
+    assert_eq!(paths.persistence, PathBuf::from("/some/dir/war.json"));
+    assert_eq!(paths.key_file, PathBuf::from("/some/dir/apikey.enc"));
+    assert_eq!(paths.load_path(), paths.persistence);
+}
+
+#[test]
+fn atomic_write_and_backups() {
+    let dir = test_dir("atomic");
+    let path = dir.join("persistence.json");
+
+    // Creates missing directories
+    write_atomic(&path, b"1", 2).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"1");
+    assert!(!with_suffix(&path, "tmp").exists());
+
+    write_atomic(&path, b"2", 2).unwrap();
+    write_atomic(&path, b"3", 2).unwrap();
+    write_atomic(&path, b"4", 2).unwrap();
+
+    assert_eq!(std::fs::read(&path).unwrap(), b"4");
+    assert_eq!(std::fs::read(with_suffix(&path, "1")).unwrap(), b"3");
+    assert_eq!(std::fs::read(with_suffix(&path, "2")).unwrap(), b"2");
+    assert!(!with_suffix(&path, "3").exists());
+
+    // Rewriting the same contents doesn't push out backups
+    write_atomic(&path, b"4", 2).unwrap();
+    assert_eq!(std::fs::read(with_suffix(&path, "1")).unwrap(), b"3");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn legacy_file_is_retired_without_the_key() {
+    let dir = test_dir("legacy");
+    let paths = ConfigPaths {
+        persistence: dir.join("config").join("persistence.json"),
+        key_file: dir.join("config").join("apikey.enc"),
+        history: dir.join("config").join("history.sqlite"),
+        legacy_persistence: Some(dir.join("persistence.json")),
+    };
+
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("persistence.json"), r#"{"api_key": "secret", "monitors": []}"#).unwrap();
+    assert_eq!(paths.load_path(), dir.join("persistence.json"));
+
+    // Kept until the new file has been saved
+    assert_eq!(paths.retire_legacy().unwrap(), None);
+    assert!(paths.legacy().is_some());
+
+    write_atomic(&paths.persistence, b"{}", 0).unwrap();
+    let backup = paths.retire_legacy().unwrap().unwrap();
+
+    assert!(paths.legacy().is_none());
+    assert_eq!(backup, dir.join("persistence.json.bak"));
+    assert_eq!(std::fs::read_to_string(&backup).unwrap(), r#"{"monitors":[]}"#);
+    assert_eq!(paths.retire_legacy().unwrap(), None);
+
+    // Never removes the file in use, when both are the same
+    let same = ConfigPaths { legacy_persistence: Some(paths.persistence.clone()), ..paths.clone() };
+    assert_eq!(same.retire_legacy().unwrap(), None);
+    assert!(paths.persistence.exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
\ No newline at end of file
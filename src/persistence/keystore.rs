@@ -0,0 +1,342 @@
+//! Secure storage for the API key, so it doesn't sit in `persistence.json` in
+//! plain text
+#![warn(missing_docs)]
+
+use std::io::Read;
+use std::path::PathBuf;
+use argon2::Argon2;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::{AeadCore, ChaCha20Poly1305, KeyInit, Nonce};
+use chacha20poly1305::aead::{Aead, OsRng};
+use chacha20poly1305::aead::rand_core::RngCore;
+use serde::{Deserialize, Serialize};
+use crate::persistence::PersistenceError;
+use crate::persistence::files::write_atomic;
+use crate::profile::{valid_key_slot, DEFAULT_KEY_SLOT};
+
+/// Service name used for keyring entries
+const KEYRING_SERVICE: &str = "torndkt";
+
+/// Somewhere the API key can be kept
+pub trait KeyStore {
+    /// Get the stored key. `Ok(None)` means nothing has been stored yet.
+    fn load(&self) -> Result<Option<String>, PersistenceError>;
+
+    /// Store a key, replacing whatever was there before
+    fn store(&self, key: &str) -> Result<(), PersistenceError>;
+
+    /// Remove the stored key, if there is one
+    fn clear(&self) -> Result<(), PersistenceError>;
+}
+
+/// Which key store to use. Saved in the persistence file.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum KeyBackend {
+    /// The OS keyring (Windows credential manager, macOS keychain, Secret
+    /// Service on Linux)
+    #[default]
+    Keyring,
+
+    /// A passphrase encrypted file next to the persistence file, for when
+    /// there's no keyring available
+    EncryptedFile,
+}
+
+/// Key store backed by the OS keyring
+pub struct KeyringStore {
+    entry: keyring::Entry,
+}
+
+impl KeyringStore {
+    /// Open the keyring entry with the given name
+    pub fn new(name: &str) -> Result<KeyringStore, PersistenceError> {
+        Ok(KeyringStore {
+            entry: keyring::Entry::new(KEYRING_SERVICE, name)?,
+        })
+    }
+}
+
+impl KeyStore for KeyringStore {
+    fn load(&self) -> Result<Option<String>, PersistenceError> {
+        match self.entry.get_password() {
+            Ok(x) => Ok(Some(x)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(x) => Err(x.into()),
+        }
+    }
+
+    fn store(&self, key: &str) -> Result<(), PersistenceError> {
+        Ok(self.entry.set_password(key)?)
+    }
+
+    fn clear(&self) -> Result<(), PersistenceError> {
+        match self.entry.delete_credential() {
+            Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(x) => Err(x.into()),
+        }
+    }
+}
+
+/// On-disk format for [`EncryptedFileStore`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct EncryptedKeyFile {
+    /// Salt for the passphrase KDF, base64
+    salt: String,
+
+    /// Cipher nonce, base64
+    nonce: String,
+
+    /// Encrypted key, base64
+    ciphertext: String,
+}
+
+/// Key store that keeps the key in a file, encrypted with a key derived from a
+/// passphrase (Argon2id + ChaCha20-Poly1305)
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    passphrase: String,
+}
+
+impl EncryptedFileStore {
+    /// Use the file at `path`, unlocked with `passphrase`
+    pub fn new(path: impl Into<PathBuf>, passphrase: &str) -> EncryptedFileStore {
+        EncryptedFileStore {
+            path: path.into(),
+            passphrase: passphrase.to_string(),
+        }
+    }
+
+    /// Whether there's an encrypted key on disk to unlock
+    pub fn exists(path: impl Into<PathBuf>) -> bool {
+        path.into().exists()
+    }
+
+    /// Derive the cipher from the passphrase and a salt
+    fn cipher(&self, salt: &[u8]) -> Result<ChaCha20Poly1305, PersistenceError> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(self.passphrase.as_bytes(), salt, &mut key)
+            .map_err(|_| PersistenceError::CryptoError)?;
+
+        Ok(ChaCha20Poly1305::new(&key.into()))
+    }
+}
+
+impl KeyStore for EncryptedFileStore {
+    fn load(&self) -> Result<Option<String>, PersistenceError> {
+        let mut file = match std::fs::File::open(&self.path) {
+            Ok(x) => x,
+            Err(x) if x.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(x) => return Err(x.into()),
+        };
+
+        let mut buf = String::new();
+        file.read_to_string(&mut buf)?;
+        let stored = serde_json::from_str::<EncryptedKeyFile>(&buf)?;
+
+        let decode = |x: &str| BASE64.decode(x).map_err(|_| PersistenceError::CryptoError);
+        let salt = decode(&stored.salt)?;
+        let nonce = decode(&stored.nonce)?;
+        let ciphertext = decode(&stored.ciphertext)?;
+
+        if nonce.len() != 12 {
+            return Err(PersistenceError::CryptoError);
+        }
+
+        // Decryption only fails if the passphrase is wrong or the file has
+        // been tampered with, and there's no way to tell those apart
+        let plaintext = self.cipher(&salt)?
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| PersistenceError::WrongPassphrase)?;
+
+        String::from_utf8(plaintext)
+            .map(Some)
+            .map_err(|_| PersistenceError::CryptoError)
+    }
+
+    fn store(&self, key: &str) -> Result<(), PersistenceError> {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+        let ciphertext = self.cipher(&salt)?
+            .encrypt(&nonce, key.as_bytes())
+            .map_err(|_| PersistenceError::CryptoError)?;
+
+        let stored = EncryptedKeyFile {
+            salt: BASE64.encode(salt),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        };
+
+        // No backups, as old copies of the key are exactly what shouldn't be
+        // left lying around
+        write_atomic(&self.path, serde_json::to_string(&stored)?.as_bytes(), 0)
+    }
+
+    fn clear(&self) -> Result<(), PersistenceError> {
+        match std::fs::remove_file(&self.path) {
+            Ok(_) => Ok(()),
+            Err(x) if x.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(x) => Err(x.into()),
+        }
+    }
+}
+
+/// Keeps track of where the API key lives, and stores it when it changes
+pub struct KeyManager {
+    /// Which store to use
+    pub backend: KeyBackend,
+
+    /// Path of the encrypted key file for the default slot, for
+    /// [`KeyBackend::EncryptedFile`]. Other slots go next to it.
+    key_file: PathBuf,
+
+    /// Which key is being managed. Each slot is a separate keyring entry or
+    /// encrypted file.
+    slot: String,
+
+    /// Passphrase for the encrypted key file, once unlocked or chosen
+    passphrase: Option<String>,
+
+    /// Key as last stored, so unchanged keys aren't rewritten
+    stored: Option<String>,
+
+    /// Key found in plain text in the persistence file, which stays there until
+    /// it's been moved into the secure store. Always belongs to the default slot.
+    legacy: Option<String>,
+}
+
+impl KeyManager {
+    /// Set up with the given backend. If `legacy_key` isn't empty, it's a key
+    /// from the old plain text format that needs migrating.
+    pub fn new(backend: KeyBackend, key_file: impl Into<PathBuf>, legacy_key: &str) -> KeyManager {
+        KeyManager {
+            backend,
+            key_file: key_file.into(),
+            slot: DEFAULT_KEY_SLOT.to_string(),
+            passphrase: None,
+            stored: None,
+            legacy: (!legacy_key.is_empty()).then(|| legacy_key.to_string()),
+        }
+    }
+
+    /// Switch to a different key slot. The key for it needs loading with
+    /// [`KeyManager::load`] afterwards.
+    pub fn select(&mut self, slot: &str) {
+        self.slot = slot.to_string();
+        self.stored = None;
+    }
+
+    /// Encrypted key file for the current slot. Slot names that could
+    /// point outside the config directory are refused.
+    fn slot_file(&self) -> Result<PathBuf, PersistenceError> {
+        if self.slot == DEFAULT_KEY_SLOT {
+            Ok(self.key_file.clone())
+        }
+        else if valid_key_slot(&self.slot) {
+            Ok(self.key_file.with_file_name(format!("apikey-{}.enc", self.slot)))
+        }
+        else {
+            Err(PersistenceError::InvalidKeySlot(self.slot.clone()))
+        }
+    }
+
+    /// The legacy key, if it belongs to the current slot
+    fn slot_legacy(&self) -> Option<String> {
+        self.legacy.clone().filter(|_| self.slot == DEFAULT_KEY_SLOT)
+    }
+
+    /// The store for the current backend, if it can be used right now
+    fn store(&self) -> Result<Box<dyn KeyStore>, PersistenceError> {
+        match self.backend {
+            KeyBackend::Keyring => Ok(Box::new(KeyringStore::new(&self.slot)?)),
+            KeyBackend::EncryptedFile => match &self.passphrase {
+                Some(x) => Ok(Box::new(EncryptedFileStore::new(self.slot_file()?, x))),
+                None => Err(PersistenceError::NeedsPassphrase),
+            },
+        }
+    }
+
+    /// Load the key for the current slot. Falls back from the keyring to the
+    /// encrypted file if there's no keyring on this machine. Returns
+    /// [`PersistenceError::NeedsPassphrase`] if the key is in an encrypted file
+    /// that needs unlocking first.
+    pub fn load(&mut self) -> Result<Option<String>, PersistenceError> {
+        if self.backend == KeyBackend::Keyring {
+            match self.store().and_then(|x| x.load()) {
+                Ok(x) => {
+                    self.stored = x.clone();
+                    return Ok(x.or(self.slot_legacy()));
+                }
+                Err(_) => self.backend = KeyBackend::EncryptedFile,
+            }
+        }
+
+        if self.passphrase.is_some() {
+            let key = self.store()?.load()?;
+            self.stored = key.clone();
+            return Ok(key.or(self.slot_legacy()));
+        }
+
+        if self.has_encrypted_key() {
+            return Err(PersistenceError::NeedsPassphrase);
+        }
+
+        Ok(self.slot_legacy())
+    }
+
+    /// Whether there's an encrypted key file waiting to be unlocked, as
+    /// opposed to a new passphrase needing to be chosen
+    pub fn has_encrypted_key(&self) -> bool {
+        self.slot_file().is_ok_and(EncryptedFileStore::exists)
+    }
+
+    /// Unlock the encrypted key file, or set the passphrase for a new one
+    pub fn unlock(&mut self, passphrase: &str) -> Result<Option<String>, PersistenceError> {
+        let store = EncryptedFileStore::new(self.slot_file()?, passphrase);
+        let key = store.load()?;
+
+        self.passphrase = Some(passphrase.to_string());
+        self.stored = key.clone();
+        Ok(key.or(self.slot_legacy()))
+    }
+
+    /// Store the key for the current slot if it's changed since it was last
+    /// stored. Once this succeeds for the default slot, any legacy plain text
+    /// key is dropped.
+    pub fn sync(&mut self, key: &str) -> Result<(), PersistenceError> {
+        if self.stored.as_deref() == Some(key) || (self.stored.is_none() && key.is_empty()) {
+            self.drop_legacy();
+            return Ok(());
+        }
+
+        let store = self.store()?;
+
+        if key.is_empty() {
+            store.clear()?;
+            self.stored = None;
+        }
+        else {
+            store.store(key)?;
+            self.stored = Some(key.to_string());
+        }
+
+        self.drop_legacy();
+        Ok(())
+    }
+
+    /// Forget the legacy key once the default slot has been stored
+    fn drop_legacy(&mut self) {
+        if self.slot == DEFAULT_KEY_SLOT {
+            self.legacy = None;
+        }
+    }
+
+    /// Plain text key that still needs to stay in the persistence file, because
+    /// it hasn't been moved into the secure store yet
+    pub fn legacy_key(&self) -> Option<&str> {
+        self.legacy.as_deref()
+    }
+}
@@ -0,0 +1,100 @@
+use std::path::PathBuf;
+use crate::persistence::PersistenceError;
+use crate::persistence::keystore::{EncryptedFileStore, KeyBackend, KeyManager, KeyStore};
+use crate::profile::valid_key_slot;
+
+/// Path for a test's key file, cleaned up before use
+fn key_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("torndkt_test_{}_{}.enc", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn encrypted_round_trip() {
+    let path = key_file("round_trip");
+    let store = EncryptedFileStore::new(&path, "hunter2");
+
+    assert_eq!(store.load().unwrap(), None);
+    store.store("abcdefghijklmnop").unwrap();
+    assert_eq!(store.load().unwrap(), Some("abcdefghijklmnop".to_string()));
+
+    // The key shouldn't be on disk in plain text
+    let raw = std::fs::read_to_string(&path).unwrap();
+    assert!(!raw.contains("abcdefghijklmnop"));
+
+    store.clear().unwrap();
+    assert_eq!(store.load().unwrap(), None);
+}
+
+#[test]
+fn encrypted_wrong_passphrase() {
+    let path = key_file("wrong_passphrase");
+    EncryptedFileStore::new(&path, "hunter2").store("abcdefghijklmnop").unwrap();
+
+    let result = EncryptedFileStore::new(&path, "hunter3").load();
+    assert!(matches!(result, Err(PersistenceError::WrongPassphrase)));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn legacy_key_migration() {
+    let path = key_file("migration");
+    let mut keys = KeyManager::new(KeyBackend::EncryptedFile, &path, "abcdefghijklmnop");
+
+    // Nowhere to put it yet, so the plain text key has to stay put
+    assert_eq!(keys.load().unwrap(), Some("abcdefghijklmnop".to_string()));
+    assert!(matches!(keys.sync("abcdefghijklmnop"), Err(PersistenceError::NeedsPassphrase)));
+    assert_eq!(keys.legacy_key(), Some("abcdefghijklmnop"));
+
+    // Once there's a passphrase it moves into the encrypted file
+    keys.unlock("hunter2").unwrap();
+    keys.sync("abcdefghijklmnop").unwrap();
+    assert_eq!(keys.legacy_key(), None);
+
+    // And can be unlocked next time
+    let mut keys = KeyManager::new(KeyBackend::EncryptedFile, &path, "");
+    assert!(matches!(keys.load(), Err(PersistenceError::NeedsPassphrase)));
+    assert_eq!(keys.unlock("hunter2").unwrap(), Some("abcdefghijklmnop".to_string()));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+
+#[test]
+fn key_slots() {
+    let path = key_file("slots");
+    let slot_path = path.with_file_name("apikey-war.enc");
+    let _ = std::fs::remove_file(&slot_path);
+
+    let mut keys = KeyManager::new(KeyBackend::EncryptedFile, &path, "");
+    keys.unlock("hunter2").unwrap();
+    keys.sync("abcdefghijklmnop").unwrap();
+
+    keys.select("war");
+    assert_eq!(keys.load().unwrap(), None);
+    keys.sync("ponmlkjihgfedcba").unwrap();
+
+    keys.select("api_key");
+    assert_eq!(keys.load().unwrap(), Some("abcdefghijklmnop".to_string()));
+
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&slot_path).unwrap();
+}
+
+#[test]
+fn unsafe_key_slots_are_refused() {
+    let path = key_file("unsafe_slots");
+
+    let mut keys = KeyManager::new(KeyBackend::EncryptedFile, &path, "");
+    keys.unlock("hunter2").unwrap();
+
+    for slot in ["../../x", "a/b", "a\\b", "", "this_slot_name_is_far_too_long_to_use"] {
+        keys.select(slot);
+        assert!(matches!(keys.sync("abcdefghijklmnop"), Err(PersistenceError::InvalidKeySlot(_))), "{:?}", slot);
+        assert!(!keys.has_encrypted_key());
+    }
+
+    assert!(valid_key_slot("war-2_b"));
+}
\ No newline at end of file
@@ -0,0 +1,87 @@
+//! Upgrades for older versions of the persistence file.
+//!
+//! Each migration takes the JSON for one version and returns the JSON for the
+//! next, so a file from any past version can be brought up to date by running
+//! every migration after it in turn. When the format changes, bump
+//! [`CURRENT_VERSION`] and add a migration to the end of [`MIGRATIONS`].
+#![warn(missing_docs)]
+
+use serde_json::{Map, Value};
+use crate::persistence::PersistenceError;
+
+/// Version written by this build
+pub const CURRENT_VERSION: u32 = 3;
+
+/// A single migration, from version `n` to `n + 1`
+type Migration = fn(Map<String, Value>) -> Result<Map<String, Value>, PersistenceError>;
+
+/// All migrations, indexed by the version they upgrade from
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [
+    v0_to_v1,
+    v1_to_v2,
+    v2_to_v3,
+];
+
+/// Version 0 is everything before the version field was added: the original
+/// `{api_key, monitors}` format, optionally with `key_backend`. Version 1 adds
+/// the version field itself, and always has `key_backend`.
+fn v0_to_v1(mut data: Map<String, Value>) -> Result<Map<String, Value>, PersistenceError> {
+    data.entry("key_backend").or_insert(Value::String("Keyring".to_string()));
+    data.entry("monitors").or_insert(Value::Array(vec![]));
+
+    Ok(data)
+}
+
+/// Version 2 replaces the flat monitor list with profiles. The old monitors
+/// become a single group in a profile called "Default".
+fn v1_to_v2(mut data: Map<String, Value>) -> Result<Map<String, Value>, PersistenceError> {
+    let monitors = data.remove("monitors").unwrap_or(Value::Array(vec![]));
+
+    data.insert("active_profile".to_string(), Value::from("Default"));
+    data.insert("profiles".to_string(), serde_json::json!([{
+        "name": "Default",
+        "groups": [{"name": "Monitors", "monitors": monitors}],
+        "key_slot": "api_key",
+    }]));
+
+    Ok(data)
+}
+
+/// Version 3 moves webhooks from the top level into each profile. Every
+/// profile gets a copy of the old ones, so they keep going whichever is open.
+fn v2_to_v3(mut data: Map<String, Value>) -> Result<Map<String, Value>, PersistenceError> {
+    let Some(webhooks) = data.remove("webhooks") else { return Ok(data) };
+
+    if let Some(Value::Array(profiles)) = data.get_mut("profiles") {
+        for profile in profiles.iter_mut().filter_map(Value::as_object_mut) {
+            profile.entry("webhooks").or_insert(webhooks.clone());
+        }
+    }
+
+    Ok(data)
+}
+
+/// Bring persistence JSON from any older version up to [`CURRENT_VERSION`]
+pub fn migrate(data: Value) -> Result<Value, PersistenceError> {
+    let Value::Object(mut data) = data else {
+        return Err(PersistenceError::Malformed("top level isn't an object".to_string()));
+    };
+
+    let version = match data.get("version") {
+        None => 0,
+        Some(x) => x.as_u64()
+            .and_then(|x| u32::try_from(x).ok())
+            .ok_or(PersistenceError::Malformed("version isn't a number".to_string()))?,
+    };
+
+    if version > CURRENT_VERSION {
+        return Err(PersistenceError::UnsupportedVersion(version));
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        data = migration(data)?;
+    }
+
+    data.insert("version".to_string(), Value::from(CURRENT_VERSION));
+    Ok(Value::Object(data))
+}
@@ -0,0 +1,88 @@
+use crate::monitors::selection::MonitorList;
+use crate::persistence::PersistedData;
+use crate::persistence::keystore::KeyBackend;
+use crate::persistence::migrations::{migrate, CURRENT_VERSION};
+use crate::persistence::PersistenceError;
+use crate::profile::Profile;
+
+/// Ids of all the simple monitors in a profile
+fn monitor_ids(profile: &Profile) -> Vec<u32> {
+    profile.groups.iter()
+        .flat_map(|x| x.monitors.iter())
+        .filter_map(|x| match x {
+            MonitorList::Simple(x) => Some(x.id),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn load_v0() {
+    let data = PersistedData::parse(include_str!("fixtures/v0.json")).unwrap();
+
+    assert_eq!(data.version, CURRENT_VERSION);
+    assert_eq!(data.api_key, "abcdefghijklmnop");
+    assert_eq!(data.key_backend, KeyBackend::Keyring);
+    assert_eq!(data.profiles.len(), 1);
+    assert_eq!(data.active_profile, "Default");
+    assert_eq!(monitor_ids(&data.profiles[0]), vec![1, 2]);
+    assert_eq!(data.profiles[0].groups[0].monitors.len(), 3);
+}
+
+#[test]
+fn load_v0_keystore() {
+    let data = PersistedData::parse(include_str!("fixtures/v0_keystore.json")).unwrap();
+
+    assert_eq!(data.api_key, "");
+    assert_eq!(data.key_backend, KeyBackend::EncryptedFile);
+    assert_eq!(monitor_ids(&data.profiles[0]), vec![3]);
+}
+
+#[test]
+fn load_v1() {
+    let data = PersistedData::parse(include_str!("fixtures/v1.json")).unwrap();
+
+    assert_eq!(data.key_backend, KeyBackend::Keyring);
+    assert_eq!(monitor_ids(&data.profiles[0]), vec![4, 5]);
+    assert_eq!(data.profiles[0].key_slot, "api_key");
+}
+
+#[test]
+fn load_v2() {
+    let data = PersistedData::parse(include_str!("fixtures/v2.json")).unwrap();
+
+    assert_eq!(data.active_profile, "War");
+    assert_eq!(data.profiles.len(), 2);
+    assert_eq!(monitor_ids(&data.profiles[1]), vec![7, 8]);
+    assert_eq!(data.profiles[1].groups[1].name, "Friends");
+    assert_eq!(data.profiles[1].key_slot, "war_key");
+}
+
+#[test]
+fn webhooks_move_into_profiles() {
+    let data = PersistedData::parse(r#"{"version": 2, "active_profile": "Default",
+        "profiles": [{"name": "Default", "groups": []}, {"name": "War", "groups": []}],
+        "webhooks": {"hooks": [{"url": "http://localhost/hook"}], "chain_below": 90}}"#).unwrap();
+
+    for profile in &data.profiles {
+        assert_eq!(profile.webhooks.hooks[0].url, "http://localhost/hook");
+        assert_eq!(profile.webhooks.chain_below, 90);
+    }
+
+    // Profiles without webhooks leave them out
+    let data = PersistedData::parse(include_str!("fixtures/v2.json")).unwrap();
+    assert!(data.profiles.iter().all(|x| x.webhooks.is_default()));
+}
+
+#[test]
+fn reject_newer_version() {
+    let result = migrate(serde_json::json!({"version": CURRENT_VERSION + 1, "profiles": []}));
+
+    assert!(matches!(result, Err(PersistenceError::UnsupportedVersion(_))));
+}
+
+#[test]
+fn reject_malformed() {
+    assert!(matches!(migrate(serde_json::json!([])), Err(PersistenceError::Malformed(_))));
+    assert!(PersistedData::parse("{\"monitors\": 5}").is_err());
+}
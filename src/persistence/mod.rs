@@ -0,0 +1,166 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::ExampleApp;
+use crate::history::HistorySettings;
+use crate::profile::Profile;
+use crate::persistence::keystore::KeyBackend;
+use crate::persistence::files::{with_suffix, write_atomic, BACKUP_COUNT};
+use crate::persistence::migrations::{migrate, CURRENT_VERSION};
+
+/// File locations and atomic writes
+pub mod files;
+
+/// Secure API key storage
+pub mod keystore;
+
+/// Upgrades for older persistence files
+pub mod migrations;
+
+#[cfg(test)]
+mod files_test;
+
+#[cfg(test)]
+mod keystore_test;
+
+#[cfg(test)]
+mod migrations_test;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PersistedData{
+    /// Schema version, see [`migrations`]
+    pub version: u32,
+
+    /// Plain text API key. Only used by old versions, and kept until the key has
+    /// been moved into the key store.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub api_key: String,
+
+    /// Where the API key is actually stored
+    #[serde(default)]
+    pub key_backend: KeyBackend,
+
+    /// Name of the profile that was open last
+    pub active_profile: String,
+
+    /// All profiles
+    pub profiles: Vec<Profile>,
+
+    /// What history is kept
+    #[serde(default, skip_serializing_if = "HistorySettings::is_default")]
+    pub history: HistorySettings,
+}
+
+#[derive(Debug)]
+pub enum PersistenceError{
+    IoError(std::io::Error),
+    SerdeError(serde_json::Error),
+    KeyringError(keyring::Error),
+    CryptoError,
+    WrongPassphrase,
+    NeedsPassphrase,
+    Malformed(String),
+    UnsupportedVersion(u32),
+    InvalidKeySlot(String),
+}
+
+impl PersistenceError{
+    /// Whether this is just the file not existing yet, as on first launch
+    pub fn is_not_found(&self) -> bool{
+        matches!(self, PersistenceError::IoError(x) if x.kind() == std::io::ErrorKind::NotFound)
+    }
+}
+
+impl From<std::io::Error> for PersistenceError{
+    fn from(err: std::io::Error) -> PersistenceError{
+        PersistenceError::IoError(err)
+    }
+}
+
+impl From<serde_json::Error> for PersistenceError{
+    fn from(err: serde_json::Error) -> PersistenceError{
+        PersistenceError::SerdeError(err)
+    }
+}
+
+impl From<keyring::Error> for PersistenceError{
+    fn from(err: keyring::Error) -> PersistenceError{
+        PersistenceError::KeyringError(err)
+    }
+}
+
+impl Display for PersistenceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self{
+            PersistenceError::IoError(err) => { write!(f, "File I/O error: {}", err) }
+            PersistenceError::SerdeError(err) => { write!(f, "Serde error: {}", err) }
+            PersistenceError::KeyringError(err) => { write!(f, "Keyring error: {}", err) }
+            PersistenceError::CryptoError => { write!(f, "Encrypted key file is corrupt") }
+            PersistenceError::WrongPassphrase => { write!(f, "Wrong passphrase") }
+            PersistenceError::NeedsPassphrase => { write!(f, "A passphrase is needed to access the key") }
+            PersistenceError::Malformed(x) => { write!(f, "Malformed persistence file: {}", x) }
+            PersistenceError::UnsupportedVersion(x) => {
+                write!(f, "Persistence file is version {}, but only up to {} is supported", x, CURRENT_VERSION)
+            }
+            PersistenceError::InvalidKeySlot(x) => {
+                write!(f, "Key slot {:?} can only have letters, digits, _ and -, and be up to 32 long", x)
+            }
+        }
+    }
+}
+
+impl Error for  PersistenceError{}
+
+impl PersistedData{
+    pub fn load(filename: &Path) -> Result<PersistedData,PersistenceError>{
+        let mut file = std::fs::File::open(filename)?;
+
+        let mut buf = String::new();
+        file.read_to_string(&mut buf)?;
+
+        Self::parse(&buf)
+    }
+
+    /// Parse persistence JSON from any version
+    pub fn parse(json: &str) -> Result<PersistedData,PersistenceError>{
+        let data = migrate(serde_json::from_str(json)?)?;
+
+        Ok(serde_json::from_value::<PersistedData>(data)?)
+    }
+
+    /// Copy a file that couldn't be loaded out of the way, so it isn't lost
+    /// when the defaults get saved over it. Returns the backup's path.
+    pub fn backup(filename: &Path) -> Result<PathBuf, PersistenceError>{
+        let suffix = format!("unreadable-{}", chrono::Utc::now().format("%Y%m%d-%H%M%S"));
+        let backup = with_suffix(filename, &suffix);
+        std::fs::copy(filename, &backup)?;
+
+        Ok(backup)
+    }
+
+    /// Save atomically, keeping a few older copies around
+    pub fn save(&self, filename: &Path) -> Result<(), PersistenceError>{
+        write_atomic(filename, serde_json::to_string(self)?.as_bytes(), BACKUP_COUNT)
+    }
+}
+
+impl From<ExampleApp> for PersistedData{
+    fn from(value: ExampleApp) -> Self {
+        Self::from(&value)
+    }
+}
+
+impl From<&ExampleApp> for PersistedData{
+    fn from(value: &ExampleApp) -> Self {
+        Self{
+            version: CURRENT_VERSION,
+            api_key: value.keys.legacy_key().unwrap_or_default().to_string(),
+            key_backend: value.keys.backend,
+            active_profile: value.profile().name.clone(),
+            profiles: value.profiles.clone(),
+            history: value.history_settings,
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,139 @@
+//! Named layouts, so members can switch between e.g. a "war" layout and a
+//! "chain watch" layout
+#![warn(missing_docs)]
+
+use serde::{Deserialize, Serialize};
+use crate::monitors::basic::SimpleHospMonitor;
+use crate::monitors::core::Monitor;
+use crate::monitors::selection::MonitorList;
+use crate::webhooks::WebhookSettings;
+
+/// Key store entry used by profiles that don't pick their own
+pub const DEFAULT_KEY_SLOT: &str = "api_key";
+
+/// Whether a key slot name is safe to put in a file name: 1 to 32 letters,
+/// digits, `_` or `-`
+pub fn valid_key_slot(name: &str) -> bool {
+    (1..=32).contains(&name.len()) && name.chars().all(|x| x.is_ascii_alphanumeric() || x == '_' || x == '-')
+}
+
+/// A named set of monitors, shown under a collapsible header
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MonitorGroup {
+    /// Name shown in the header
+    pub name: String,
+
+    /// Monitors in the group, in display order
+    pub monitors: Vec<MonitorList>,
+}
+
+impl MonitorGroup {
+    /// An empty group
+    pub fn new(name: &str) -> MonitorGroup {
+        MonitorGroup {
+            name: name.to_string(),
+            monitors: vec![],
+        }
+    }
+}
+
+/// A named layout, with its own monitors, API key and webhooks
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Profile {
+    /// Name shown in the profile menu, and used to pick it on the command line
+    pub name: String,
+
+    /// Monitor groups, in display order
+    pub groups: Vec<MonitorGroup>,
+
+    /// Key store entry holding the API key this profile uses
+    #[serde(default = "default_key_slot")]
+    pub key_slot: String,
+
+    /// Where this profile's events are sent
+    #[serde(default, skip_serializing_if = "WebhookSettings::is_default")]
+    pub webhooks: WebhookSettings,
+
+    /// UI scale while this profile is open
+    #[serde(default = "default_uiscale")]
+    pub uiscale: f32,
+}
+
+/// Serde default for [`Profile::key_slot`]
+fn default_key_slot() -> String {
+    DEFAULT_KEY_SLOT.to_string()
+}
+
+/// Serde default for [`Profile::uiscale`]
+fn default_uiscale() -> f32 {
+    1.5
+}
+
+impl Profile {
+    /// A profile with one empty group, using the default key
+    pub fn new(name: &str) -> Profile {
+        Profile {
+            name: name.to_string(),
+            groups: vec![MonitorGroup::new("Monitors")],
+            key_slot: default_key_slot(),
+            webhooks: WebhookSettings::default(),
+            uiscale: default_uiscale(),
+        }
+    }
+
+    /// All monitors in the profile, across all groups
+    pub fn monitors(&self) -> impl Iterator<Item = &MonitorList> {
+        self.groups.iter().flat_map(|x| x.monitors.iter())
+    }
+
+    /// Position of the group with the given name, which is created if there
+    /// isn't one
+    pub fn group_named(&mut self, name: &str) -> usize {
+        self.groups.iter().position(|x| x.name == name).unwrap_or_else(|| {
+            self.groups.push(MonitorGroup::new(name));
+            self.groups.len() - 1
+        })
+    }
+
+    /// Add a monitor to a group for each user, skipping users the group
+    /// already has one for. Returns how many were added.
+    pub fn add_users(&mut self, group: usize, ids: impl IntoIterator<Item = u32>) -> usize {
+        let monitors = &mut self.groups[group].monitors;
+        let mut added = 0;
+
+        for id in ids {
+            let monitor = MonitorList::Simple(SimpleHospMonitor::new(id));
+
+            if !monitors.iter().any(|x| x.same_target(&monitor)) {
+                monitors.push(monitor);
+                added += 1;
+            }
+        }
+
+        added
+    }
+
+    /// Remove monitors showing any of `ids`, from one group or all of them.
+    /// Returns how many were removed.
+    pub fn remove_users(&mut self, group: Option<usize>, ids: &[u32]) -> usize {
+        let mut removed = 0;
+
+        for (index, x) in self.groups.iter_mut().enumerate() {
+            if group.is_some_and(|y| y != index) {
+                continue;
+            }
+
+            let before = x.monitors.len();
+            x.monitors.retain(|x| !x.users().iter().any(|y| ids.contains(y)));
+            removed += before - x.monitors.len();
+        }
+
+        removed
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Profile {
+        Profile::new("Default")
+    }
+}
\ No newline at end of file
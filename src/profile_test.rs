@@ -0,0 +1,44 @@
+use crate::monitors::basic::SimpleHospMonitor;
+use crate::monitors::selection::MonitorList;
+use crate::profile::{MonitorGroup, Profile};
+
+/// Ids of the monitors in a group
+fn ids(profile: &Profile, group: usize) -> Vec<u32> {
+    profile.groups[group].monitors.iter()
+        .map(|x| match x {
+            MonitorList::Simple(x) => x.id,
+            MonitorList::Leaderboard(_) | MonitorList::None => 0,
+        })
+        .collect()
+}
+
+#[test]
+fn add_skips_existing() {
+    let mut profile = Profile::new("Test");
+
+    assert_eq!(profile.add_users(0, [1, 2]), 2);
+    assert_eq!(profile.add_users(0, [2, 3, 3]), 1);
+    assert_eq!(ids(&profile, 0), vec![1, 2, 3]);
+
+    // Another group can have the same users
+    let war = profile.group_named("War");
+    assert_eq!(war, 1);
+    assert_eq!(profile.group_named("War"), 1);
+    assert_eq!(profile.add_users(war, [1]), 1);
+}
+
+#[test]
+fn remove_from_one_group_or_all() {
+    let mut profile = Profile::new("Test");
+    profile.groups.push(MonitorGroup::new("War"));
+    profile.add_users(0, [1, 2, 3]);
+    profile.add_users(1, [1, 2]);
+    profile.groups[1].monitors.push(MonitorList::Simple(SimpleHospMonitor::new(4)));
+
+    assert_eq!(profile.remove_users(Some(1), &[1]), 1);
+    assert_eq!(ids(&profile, 0), vec![1, 2, 3]);
+
+    assert_eq!(profile.remove_users(None, &[2, 4, 5]), 3);
+    assert_eq!(ids(&profile, 0), vec![1, 3]);
+    assert!(profile.groups[1].monitors.is_empty());
+}
\ No newline at end of file
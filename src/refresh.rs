@@ -0,0 +1,218 @@
+//! Fetching everything the open profile shows into the store, and recording
+//! it to history. The window does this on a thread of its own, so waiting on
+//! the API, retries and all, never holds up drawing.
+#![warn(missing_docs)]
+
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use chrono::{DateTime, TimeDelta, Utc};
+use crate::api::api::GetInfoError;
+use crate::api::cache::{FACTION_ATTACKS, FACTION_CHAIN, FACTION_WARS};
+use crate::api::faction::{faction_attacks, faction_members, ATTACKS_PAGE};
+use crate::api::key::{key_capabilities, KeyCapabilities};
+use crate::clock::Clock;
+use crate::history::{History, HistoryError};
+use crate::leaderboard::Leaderboard;
+use crate::store::{Attack, EntityStore, LastAction};
+
+/// How often everything is fetched again
+pub const REFRESH_INTERVAL: TimeDelta = TimeDelta::seconds(30);
+
+/// Most pages of attacks fetched in one refresh, when catching up
+const MAX_ATTACK_PAGES: usize = 5;
+
+/// Everything a refresh needs, copied out of the app so it can run elsewhere
+pub struct RefreshJob {
+    /// Key to fetch with
+    pub apikey: String,
+
+    /// Users the open profile's monitors show
+    pub ids: Vec<u32>,
+
+    /// Where fetched data goes
+    pub store: Arc<EntityStore>,
+
+    /// Time source for the chain timer and history
+    pub clock: Arc<dyn Clock>,
+
+    /// Where history goes, if it's being kept
+    pub history: Option<Arc<History>>,
+
+    /// Faction whose activity is recorded outside of wars too
+    pub watch: Option<u32>,
+}
+
+/// What a refresh came back with
+#[derive(Debug)]
+pub struct RefreshOutcome {
+    /// What the key can do, if it could be looked up
+    pub key_caps: Option<KeyCapabilities>,
+
+    /// Whether fetching worked
+    pub result: Result<(), GetInfoError>,
+
+    /// Whether saving history worked. `None` if it wasn't saved, because
+    /// there's no history or fetching failed first.
+    pub history: Option<Result<(), HistoryError>>,
+}
+
+impl RefreshJob {
+    /// Fetch everything, then save it to history
+    pub async fn run(self) -> RefreshOutcome {
+        let key_caps = match self.apikey.is_empty() {
+            true => None,
+            false => key_capabilities(&self.apikey).await.ok(),
+        };
+
+        let mut history = None;
+        let result = self.fetch(key_caps.as_ref(), &mut history).await;
+
+        RefreshOutcome { key_caps, result, history }
+    }
+
+    /// Do the fetching for [`Self::run`]
+    async fn fetch(&self, key_caps: Option<&KeyCapabilities>, saved: &mut Option<Result<(), HistoryError>>) -> Result<(), GetInfoError> {
+        self.store.refresh_users(&self.apikey, self.ids.clone()).await?;
+
+        // The key owner's chain and war, if the key can see them
+        let faction = key_caps
+            .filter(|x| x.allows(&FACTION_CHAIN) && x.allows(&FACTION_WARS))
+            .and_then(|x| x.faction_id);
+
+        if let Some(faction) = faction {
+            self.store.refresh_faction(&self.apikey, faction, self.clock.as_ref()).await?;
+        }
+
+        if let Some(history) = &self.history {
+            let owner = key_caps.and_then(|x| x.faction_id);
+            *saved = Some(self.save_history(history, key_caps, owner).await);
+        }
+
+        Ok(())
+    }
+
+    /// Save what's just been fetched to the history database, along with any
+    /// new attacks in the faction's log and, every so often, the enemy's last
+    /// actions
+    async fn save_history(&self, history: &History, key_caps: Option<&KeyCapabilities>, faction: Option<u32>) -> Result<(), HistoryError> {
+        let now = self.clock.now();
+        history.prune_if_due(now)?;
+
+        let users = self.ids.iter()
+            .filter_map(|x| self.store.user(*x)?.ok())
+            .collect::<Vec<_>>();
+        history.record_users(&users, now)?;
+
+        // The watched faction's activity is recorded whether we're at war or not
+        let mut enemies = self.watch.into_iter().collect::<Vec<_>>();
+
+        if let Some(faction) = faction {
+            if let Some(chain) = self.store.chain(faction) {
+                history.record_chain(faction, &chain, now)?;
+            }
+
+            if let Some(Some(war)) = self.store.war(faction) {
+                history.record_war(&war, now)?;
+
+                if war.end.is_none() && let Some((_, theirs)) = war.sides(faction) && !enemies.contains(&theirs.id) {
+                    enemies.push(theirs.id);
+                }
+            }
+        }
+
+        for enemy in enemies {
+            if !history.activity_due(enemy, now)? {
+                continue;
+            }
+
+            // Tried again on the next refresh if it fails
+            if let Ok(members) = faction_members(&self.apikey, enemy).await {
+                history.record_activity(enemy, &members.iter().map(LastAction::from_member).collect::<Vec<_>>(), now)?;
+            }
+        }
+
+        let Some(faction) = faction else { return Ok(()) };
+
+        // Say so rather than quietly never recording any attacks
+        if !key_caps.is_some_and(|x| x.allows(&FACTION_ATTACKS)) {
+            return Err(HistoryError::Fetch("attacks", GetInfoError::Other(16)));
+        }
+
+        // Catch up a page at a time. If fetching fails, the rest is picked up
+        // on the next refresh.
+        for _ in 0..MAX_ATTACK_PAGES {
+            let from = history.last_attack(faction)?.unwrap_or(now - TimeDelta::days(1));
+            let page = faction_attacks(&self.apikey, Some(from)).await.map_err(|x| HistoryError::Fetch("attacks", x))?;
+
+            let attacks = page.iter().map(Attack::from_model).collect::<Vec<_>>();
+            let added = history.record_attacks(faction, &attacks)?;
+
+            if attacks.len() < ATTACKS_PAGE || added == 0 {
+                break;
+            }
+        }
+
+        // For leaderboard monitors
+        if let Some(war) = history.wars()?.first() {
+            self.store.set_leaderboard(Leaderboard::load(history, war, faction, now)?);
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs refreshes on a background thread, one at a time, every
+/// [`REFRESH_INTERVAL`]
+#[derive(Default)]
+pub struct Refresher {
+    /// The refresh in progress, and whether the user asked for it
+    running: Option<(JoinHandle<RefreshOutcome>, bool)>,
+
+    /// When the last refresh started
+    last: Option<DateTime<Utc>>,
+}
+
+impl Refresher {
+    /// Whether a refresh is in progress
+    pub fn running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Whether it's time for another refresh at `now`
+    pub fn due(&self, now: DateTime<Utc>) -> bool {
+        !self.running() && self.last.is_none_or(|x| now - x >= REFRESH_INTERVAL)
+    }
+
+    /// Start running `job` at `now`, unless a refresh is already in progress.
+    /// `asked` is whether the user asked for it, and so wants to hear if it
+    /// fails.
+    pub fn start(&mut self, job: RefreshJob, asked: bool, now: DateTime<Utc>) -> bool {
+        if self.running() {
+            return false;
+        }
+
+        // The HTTP client needs a runtime, and this thread doesn't have one
+        let handle = std::thread::spawn(move || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Couldn't start async runtime")
+                .block_on(job.run())
+        });
+
+        self.running = Some((handle, asked));
+        self.last = Some(now);
+        true
+    }
+
+    /// The outcome of the refresh in progress, and whether it was asked for,
+    /// once it's finished
+    pub fn finished(&mut self) -> Option<(RefreshOutcome, bool)> {
+        if !self.running.as_ref()?.0.is_finished() {
+            return None;
+        }
+
+        let (handle, asked) = self.running.take()?;
+        Some((handle.join().expect("Refresh thread panicked"), asked))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,117 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use torn_api::models::{FactionId, UserId};
+use torn_api::request::IntoRequest;
+use torn_api::request::models::{FactionMembersForIdRequest, UserProfileForIdRequest};
+use crate::api::client::{install, ApiClient, ApiConfig};
+use crate::api::recording::{Exchange, Recording, Replay, ReplayClock};
+use crate::clock::{Clock, SystemClock};
+use crate::refresh::{RefreshJob, RefreshOutcome, Refresher};
+use crate::store::EntityStore;
+use crate::test_history::{memory, now};
+
+/// Clock that only moves when it's told to
+#[derive(Debug)]
+struct ManualClock(Mutex<DateTime<Utc>>);
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// Serve user 1's profile and faction 200's members from a replay instead of
+/// the API
+fn install_replay() {
+    let profile = UserProfileForIdRequest::builder(UserId(1)).api_key_public("key").build().into_request().1;
+    let members = FactionMembersForIdRequest::builder(FactionId(200)).api_key_public("key").build().into_request().1;
+
+    let recording = Recording {
+        exchanges: vec![
+            Exchange::new(&profile, now(), 200, None, include_bytes!("api/fixtures/user_profile.json")),
+            Exchange::new(&members, now(), 200, None, include_bytes!("api/fixtures/faction_members.json")),
+        ],
+    };
+
+    install(ApiClient::new(ApiConfig::default()).with_replay(Replay::new(recording, 1.0, Arc::new(SystemClock))));
+}
+
+/// Wait for the refresh in progress to finish
+fn wait(refresher: &mut Refresher) -> (RefreshOutcome, bool) {
+    loop {
+        if let Some(x) = refresher.finished() {
+            return x;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn history_advances_on_its_own() {
+    install_replay();
+
+    let history = Arc::new(memory());
+    let clock = Arc::new(ManualClock(Mutex::new(now())));
+    let mut refresher = Refresher::default();
+
+    let job = || RefreshJob {
+        apikey: "key".to_string(),
+        ids: vec![1],
+        store: Arc::new(EntityStore::default()),
+        clock: clock.clone(),
+        history: Some(history.clone()),
+        watch: Some(200),
+    };
+
+    // Nobody asks for any of these, they just come due
+    for (after, due) in [(TimeDelta::zero(), true), (TimeDelta::seconds(10), false), (TimeDelta::hours(2), true)] {
+        let at = now() + after;
+        *clock.0.lock().unwrap() = at;
+
+        assert_eq!(refresher.due(at), due);
+        if !due {
+            continue;
+        }
+
+        assert!(refresher.start(job(), false, at));
+        assert!(!refresher.due(at));
+
+        let (outcome, asked) = wait(&mut refresher);
+        assert!(!asked);
+        assert_eq!(outcome.result, Ok(()));
+        assert!(matches!(outcome.history, Some(Ok(()))));
+    }
+
+    // A poll of the watched faction for each refresh
+    let hour = now().duration_trunc(TimeDelta::hours(1)).unwrap();
+    let hours = history.activity(200).unwrap();
+    assert_eq!(hours.iter().map(|x| x.hour).collect::<Vec<_>>(), vec![hour, hour + TimeDelta::hours(2)]);
+}
+
+#[test]
+fn replays_refresh_at_their_own_speed() {
+    let local = Arc::new(ManualClock(Mutex::new(now())));
+    let clock = Arc::new(ReplayClock::new(now(), 10.0, local.clone()));
+    let mut refresher = Refresher::default();
+
+    // Nothing to fetch, so it's done straight away
+    let job = RefreshJob {
+        apikey: String::new(),
+        ids: vec![],
+        store: Arc::default(),
+        clock: clock.clone(),
+        history: None,
+        watch: None,
+    };
+
+    assert!(refresher.start(job, false, clock.now()));
+    wait(&mut refresher);
+
+    // 3 seconds at 10x is the whole interval
+    *local.0.lock().unwrap() += TimeDelta::seconds(2);
+    assert!(!refresher.due(clock.now()));
+
+    *local.0.lock().unwrap() += TimeDelta::seconds(1);
+    assert!(refresher.due(clock.now()));
+}
\ No newline at end of file
@@ -0,0 +1,438 @@
+//! Everything fetched from the API, in one place that all monitors read from,
+//! so a player shown by several monitors is only fetched once
+#![warn(missing_docs)]
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+use torn_api::models::{Attack as AttackModel, AttackPlayer, FactionAttackResult, FactionMember, FactionOngoingChain, FactionRankedWar, UserStatus};
+use torn_api::models::user_profile_response::Profile;
+use uniquevec::UniqueVec;
+use crate::api::api::GetInfoError;
+use crate::api::faction::{faction_chain, faction_members, faction_ranked_war};
+use crate::api::user::user_profile;
+use crate::clock::Clock;
+use crate::leaderboard::Leaderboard;
+use crate::util::serialize_display;
+
+/// How many tracked users need to share a faction before it's cheaper to fetch
+/// the whole faction
+const BATCH_THRESHOLD: usize = 2;
+
+/// What's known about a user
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct User {
+    /// User id
+    pub id: u32,
+
+    /// Username
+    pub name: String,
+
+    /// When they leave hospital. In the past if they're not in hospital.
+    pub hospital_until: DateTime<Utc>,
+
+    /// Faction they're in, if any
+    pub faction_id: Option<u32>,
+}
+
+/// When a status ends, if it's a hospital stay
+fn hospital_until(status: &UserStatus) -> DateTime<Utc> {
+    let until = match status.state.as_str() {
+        "Hospital" => status.until.unwrap_or_default(),
+        _ => 0,
+    };
+
+    timestamp(until as i64)
+}
+
+/// Convert a Unix timestamp from the API
+fn timestamp(secs: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(secs, 0).unwrap_or_default()
+}
+
+impl User {
+    /// Build from a user profile
+    pub fn from_profile(profile: &Profile) -> User {
+        User {
+            id: profile.id.0 as u32,
+            name: profile.name.clone(),
+            hospital_until: hospital_until(&profile.status),
+            faction_id: profile.faction_id.map(|x| x.0 as u32),
+        }
+    }
+
+    /// Build from an entry in a faction's member list
+    pub fn from_member(faction_id: u32, member: &FactionMember) -> User {
+        User {
+            id: member.id.0 as u32,
+            name: member.name.clone(),
+            hospital_until: hospital_until(&member.status),
+            faction_id: Some(faction_id),
+        }
+    }
+}
+
+/// A faction's chain
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Chain {
+    /// Hits in the chain so far
+    pub current: u32,
+
+    /// Next bonus milestone
+    pub max: u32,
+
+    /// When the chain breaks without another hit. `None` if there's no chain.
+    pub breaks_at: Option<DateTime<Utc>>,
+
+    /// When the post-chain cooldown ends, if it's cooling down
+    pub cooldown_until: Option<DateTime<Utc>>,
+
+    /// Respect multiplier for the next hit
+    pub modifier: f64,
+}
+
+impl Chain {
+    /// Build from the chain response. The timeout is relative, so `now` is
+    /// when it was fetched.
+    pub fn from_model(chain: &FactionOngoingChain, now: DateTime<Utc>) -> Chain {
+        Chain {
+            current: chain.current.max(0) as u32,
+            max: chain.max.max(0) as u32,
+            breaks_at: (chain.current > 0 && chain.timeout > 0)
+                .then(|| now + TimeDelta::seconds(chain.timeout as i64)),
+            cooldown_until: (chain.cooldown > 0).then(|| timestamp(chain.cooldown as i64)),
+            modifier: chain.modifier,
+        }
+    }
+}
+
+/// One side of a ranked war
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WarFaction {
+    /// Faction id
+    pub id: u32,
+
+    /// Faction name
+    pub name: String,
+
+    /// Score so far
+    pub score: u32,
+
+    /// Current chain
+    pub chain: u32,
+}
+
+/// A ranked war
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RankedWar {
+    /// War id
+    pub id: u32,
+
+    /// When it started, or will start
+    pub start: DateTime<Utc>,
+
+    /// When it ended, if it has
+    pub end: Option<DateTime<Utc>>,
+
+    /// Lead needed to win
+    pub target: u32,
+
+    /// Both sides
+    pub factions: Vec<WarFaction>,
+}
+
+impl RankedWar {
+    /// Build from the wars response
+    pub fn from_model(war: &FactionRankedWar) -> RankedWar {
+        RankedWar {
+            id: war.war_id.max(0) as u32,
+            start: timestamp(war.start as i64),
+            end: war.end.filter(|x| *x > 0).map(|x| timestamp(x as i64)),
+            target: war.target.max(0) as u32,
+            factions: war.factions.iter()
+                .map(|x| WarFaction {
+                    id: x.id.0 as u32,
+                    name: x.name.clone(),
+                    score: x.score.max(0) as u32,
+                    chain: x.chain.max(0) as u32,
+                })
+                .collect(),
+        }
+    }
+
+    /// The given faction's side, and the other side
+    pub fn sides(&self, faction_id: u32) -> Option<(&WarFaction, &WarFaction)> {
+        let ours = self.factions.iter().find(|x| x.id == faction_id)?;
+        let theirs = self.factions.iter().find(|x| x.id != faction_id)?;
+
+        Some((ours, theirs))
+    }
+}
+
+/// Someone in an attack
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Combatant {
+    /// User id
+    pub id: u32,
+
+    /// Username
+    pub name: String,
+
+    /// Faction they were in at the time, if any
+    pub faction_id: Option<u32>,
+}
+
+impl Combatant {
+    /// Build from one side of an attack
+    fn from_model(player: &AttackPlayer) -> Combatant {
+        Combatant {
+            id: player.id.0 as u32,
+            name: player.name.clone(),
+            faction_id: player.faction.as_ref().map(|x| x.id.0 as u32),
+        }
+    }
+}
+
+/// An attack made by or on a faction
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Attack {
+    /// Attack id
+    pub id: u32,
+
+    /// When it started
+    pub started: DateTime<Utc>,
+
+    /// When it ended
+    pub ended: DateTime<Utc>,
+
+    /// Who attacked. `None` if they were stealthed.
+    pub attacker: Option<Combatant>,
+
+    /// Who was attacked
+    pub defender: Combatant,
+
+    /// How it ended, e.g. hospitalized or lost
+    #[serde(serialize_with = "serialize_display")]
+    pub result: FactionAttackResult,
+
+    /// Respect the attacker's faction gained
+    pub respect_gain: f64,
+
+    /// Respect the defender's faction lost
+    pub respect_loss: f64,
+
+    /// Chain count the hit made, or 0 if it didn't count
+    pub chain: u32,
+
+    /// Whether it counted towards a ranked war
+    pub ranked_war: bool,
+
+    /// Whether someone else finished the defender off first
+    pub interrupted: bool,
+
+    /// Fair fight multiplier
+    pub fair_fight: f64,
+}
+
+impl Attack {
+    /// Build from the attacks response
+    pub fn from_model(attack: &AttackModel) -> Attack {
+        Attack {
+            id: attack.id.0 as u32,
+            started: timestamp(attack.started as i64),
+            ended: timestamp(attack.ended as i64),
+            attacker: attack.attacker.as_ref().map(Combatant::from_model),
+            defender: Combatant::from_model(&attack.defender),
+            result: attack.result,
+            respect_gain: attack.respect_gain,
+            respect_loss: attack.respect_loss,
+            chain: attack.chain.max(0) as u32,
+            ranked_war: attack.is_ranked_war,
+            interrupted: attack.is_interrupted,
+            fair_fight: attack.modifiers.fair_fight,
+        }
+    }
+}
+
+/// When a faction member last did anything
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LastAction {
+    /// User id
+    pub user_id: u32,
+
+    /// When they last acted
+    pub at: DateTime<Utc>,
+}
+
+impl LastAction {
+    /// Build from an entry in a faction's member list
+    pub fn from_member(member: &FactionMember) -> LastAction {
+        LastAction {
+            user_id: member.id.0 as u32,
+            at: timestamp(member.last_action.timestamp as i64),
+        }
+    }
+}
+
+/// How to fetch a set of users in as few calls as possible
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RefreshPlan {
+    /// Factions to fetch, with the tracked users they cover
+    pub factions: Vec<(u32, Vec<u32>)>,
+
+    /// Users to fetch one at a time
+    pub users: Vec<u32>,
+}
+
+impl RefreshPlan {
+    /// Plan fetching `ids`, given the factions users were last seen in. Users
+    /// sharing a faction with enough others are covered by one faction fetch.
+    /// Everyone else, including users not fetched before, is fetched alone.
+    pub fn new(ids: impl IntoIterator<Item = u32>, factions: &HashMap<u32, u32>) -> RefreshPlan {
+        let (ids, _) = UniqueVec::from_iter(ids.into_iter().filter(|x| *x != 0));
+        let mut plan = RefreshPlan::default();
+
+        for id in ids {
+            let Some(faction) = factions.get(&id) else {
+                plan.users.push(id);
+                continue;
+            };
+
+            match plan.factions.iter_mut().find(|(x, _)| x == faction) {
+                Some((_, members)) => members.push(id),
+                None => plan.factions.push((*faction, vec![id])),
+            }
+        }
+
+        // Not worth a faction fetch for just one user
+        let (batched, single): (Vec<_>, Vec<_>) = plan.factions.into_iter()
+            .partition(|(_, members)| members.len() >= BATCH_THRESHOLD);
+
+        plan.factions = batched;
+        plan.users.extend(single.into_iter().flat_map(|(_, members)| members));
+
+        plan
+    }
+}
+
+/// Store of fetched entities, keyed by id. Safe to share between threads.
+#[derive(Debug, Default)]
+pub struct EntityStore {
+    /// Users, or the error fetching them gave
+    users: RwLock<HashMap<u32, Result<User, GetInfoError>>>,
+
+    /// Chains, by faction id
+    chains: RwLock<HashMap<u32, Chain>>,
+
+    /// Ranked wars, by the id of a faction in them. `None` if the faction
+    /// isn't in one.
+    wars: RwLock<HashMap<u32, Option<RankedWar>>>,
+
+    /// Member contributions to the key owner's latest ranked war, worked out
+    /// from history
+    leaderboard: RwLock<Option<Leaderboard>>,
+}
+
+impl EntityStore {
+    /// Get a user, if they've been fetched. Users that couldn't be fetched
+    /// give the error the API returned for them.
+    pub fn user(&self, id: u32) -> Option<Result<User, GetInfoError>> {
+        self.users.read().unwrap().get(&id).cloned()
+    }
+
+    /// All users fetched successfully
+    pub fn users(&self) -> Vec<User> {
+        self.users.read().unwrap()
+            .values()
+            .filter_map(|x| x.as_ref().ok())
+            .cloned()
+            .collect()
+    }
+
+    /// Put a user in the store, replacing what was there
+    pub fn insert_user(&self, id: u32, user: Result<User, GetInfoError>) {
+        self.users.write().unwrap().insert(id, user);
+    }
+
+    /// Faction each user was last seen in, for planning refreshes
+    fn user_factions(&self) -> HashMap<u32, u32> {
+        self.users.read().unwrap()
+            .iter()
+            .filter_map(|(id, x)| Some((*id, x.as_ref().ok()?.faction_id?)))
+            .collect()
+    }
+
+    /// Fetch the given users, once each. Users known to share a faction are
+    /// fetched together through the faction's member list, falling back to
+    /// fetching them one at a time if that doesn't work or they've left.
+    ///
+    /// Ids that don't exist are recorded against the user. A wrong key stops
+    /// the refresh, as it'll be the same for every user.
+    pub async fn refresh_users(&self, apikey: &str, ids: impl IntoIterator<Item = u32>) -> Result<(), GetInfoError> {
+        let plan = RefreshPlan::new(ids, &self.user_factions());
+        let mut remaining = plan.users;
+
+        for (faction, tracked) in plan.factions {
+            let members = match faction_members(apikey, faction).await {
+                Ok(x) => x,
+                Err(GetInfoError::WrongKey) => return Err(GetInfoError::WrongKey),
+                Err(_) => {
+                    remaining.extend(tracked);
+                    continue;
+                }
+            };
+
+            // Everyone in the faction is stored, not just who's tracked, as
+            // it's free and helps with name lookups
+            for member in &members {
+                let user = User::from_member(faction, member);
+                self.insert_user(user.id, Ok(user));
+            }
+
+            remaining.extend(tracked.into_iter().filter(|x| !members.iter().any(|y| y.id.0 as u32 == *x)));
+        }
+
+        for id in remaining {
+            match user_profile(apikey, id).await {
+                Ok(x) => self.insert_user(id, Ok(User::from_profile(&x))),
+                Err(GetInfoError::InvalidId) => self.insert_user(id, Err(GetInfoError::InvalidId)),
+                Err(x) => return Err(x),
+            }
+        }
+
+        Ok(())
+    }
+    /// A faction's chain, if it's been fetched
+    pub fn chain(&self, faction_id: u32) -> Option<Chain> {
+        self.chains.read().unwrap().get(&faction_id).cloned()
+    }
+
+    /// The ranked war a faction is in. `None` if it hasn't been fetched, and
+    /// `Some(None)` if it isn't in one.
+    pub fn war(&self, faction_id: u32) -> Option<Option<RankedWar>> {
+        self.wars.read().unwrap().get(&faction_id).cloned()
+    }
+
+    /// Member contributions to the latest ranked war, if history has been
+    /// recorded for one
+    pub fn leaderboard(&self) -> Option<Leaderboard> {
+        self.leaderboard.read().unwrap().clone()
+    }
+
+    /// Replace the latest war's leaderboard
+    pub fn set_leaderboard(&self, leaderboard: Leaderboard) {
+        *self.leaderboard.write().unwrap() = Some(leaderboard);
+    }
+
+    /// Fetch a faction's chain and ranked war
+    pub async fn refresh_faction(&self, apikey: &str, faction_id: u32, clock: &dyn Clock) -> Result<(), GetInfoError> {
+        let chain = faction_chain(apikey, faction_id).await?;
+        self.chains.write().unwrap().insert(faction_id, Chain::from_model(&chain, clock.now()));
+
+        let war = faction_ranked_war(apikey, faction_id).await?;
+        self.wars.write().unwrap().insert(faction_id, war.as_ref().map(RankedWar::from_model));
+
+        Ok(())
+    }
+}
\ No newline at end of file
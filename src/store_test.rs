@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use chrono::{DateTime, TimeDelta};
+use torn_api::models::{FactionMembersResponse, FactionOngoingChainResponse, FactionWarsResponse, UserProfileResponse};
+use crate::api::client::parse_response;
+use crate::store::{Chain, RankedWar, RefreshPlan, User};
+
+#[test]
+fn plan_batches_shared_factions() {
+    // 1-3 share a faction, 4 is alone in another, 5 is unaffiliated and 6
+    // hasn't been seen before
+    let factions = HashMap::from([(1, 100), (2, 100), (3, 100), (4, 200)]);
+    let plan = RefreshPlan::new([1, 2, 3, 4, 5, 6, 2, 0], &factions);
+
+    assert_eq!(plan, RefreshPlan {
+        factions: vec![(100, vec![1, 2, 3])],
+        users: vec![5, 6, 4],
+    });
+}
+
+#[test]
+fn user_from_member() {
+    let response: FactionMembersResponse = parse_response(include_bytes!("api/fixtures/faction_members.json")).unwrap();
+
+    let user = User::from_member(100, &response.members[0]);
+    assert_eq!(user.id, 1);
+    assert_eq!(user.hospital_until, DateTime::from_timestamp(1_700_000_000, 0).unwrap());
+    assert_eq!(user.faction_id, Some(100));
+
+    // Travelling has an end time too, but it's not hospital
+    let user = User::from_member(100, &response.members[1]);
+    assert_eq!(user.name, "Traveller");
+    assert_eq!(user.hospital_until, DateTime::UNIX_EPOCH);
+}
+
+#[test]
+fn user_from_profile() {
+    let response: UserProfileResponse = parse_response(include_bytes!("api/fixtures/user_profile.json")).unwrap();
+
+    assert_eq!(User::from_profile(&response.profile), User {
+        id: 1,
+        name: "Chedburn".to_string(),
+        hospital_until: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+        faction_id: Some(100),
+    });
+}
+
+#[test]
+fn chain_from_model() {
+    let response: FactionOngoingChainResponse = parse_response(include_bytes!("api/fixtures/faction_chain.json")).unwrap();
+    let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+
+    let chain = Chain::from_model(&response.chain, now);
+    assert_eq!(chain.current, 120);
+    assert_eq!(chain.breaks_at, Some(now + TimeDelta::seconds(90)));
+    assert_eq!(chain.cooldown_until, None);
+
+    // No chain, so nothing to break
+    let chain = Chain::from_model(&torn_api::models::FactionOngoingChain { current: 0, timeout: 0, ..response.chain }, now);
+    assert_eq!(chain.breaks_at, None);
+}
+
+#[test]
+fn war_from_model() {
+    let response: FactionWarsResponse = parse_response(include_bytes!("api/fixtures/faction_wars.json")).unwrap();
+    let war = RankedWar::from_model(response.wars.ranked.as_ref().unwrap());
+
+    assert_eq!(war.id, 300);
+    assert_eq!(war.end, None);
+
+    let (ours, theirs) = war.sides(100).unwrap();
+    assert_eq!((ours.score, theirs.name.as_str()), (1200, "Theirs"));
+    assert_eq!(war.sides(200).unwrap().0.name, "Theirs");
+    assert_eq!(war.sides(300), None);
+}
\ No newline at end of file
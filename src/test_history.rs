@@ -0,0 +1,39 @@
+//! History for the tests of everything that reads it: a war between faction
+//! 100 ("Ours") and 200 ("Theirs"), and the attacks in the
+//! `faction_attacks.json` fixture
+
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use torn_api::models::FactionAttacksResponse;
+use crate::api::client::parse_response;
+use crate::history::{History, HistorySettings};
+use crate::store::{Attack, RankedWar, WarFaction};
+
+/// When the war starts, and the time the fixture's attacks are around
+pub fn now() -> DateTime<Utc> {
+    DateTime::from_timestamp(1_700_000_000, 0).unwrap()
+}
+
+/// An empty history that's gone once dropped
+pub fn memory() -> History {
+    History::open(Path::new(":memory:"), &HistorySettings::default()).unwrap()
+}
+
+/// The fixture's attacks, made by and on faction 100
+pub fn attacks() -> Vec<Attack> {
+    let response: FactionAttacksResponse = parse_response(include_bytes!("api/fixtures/faction_attacks.json")).unwrap();
+    response.attacks.iter().map(Attack::from_model).collect()
+}
+
+/// War 7, which started at [`now`], with each side's score
+pub fn war(ours: u32, theirs: u32, end: Option<DateTime<Utc>>) -> RankedWar {
+    let side = |id, name: &str, score| WarFaction { id, name: name.to_string(), score, chain: 0 };
+
+    RankedWar {
+        id: 7,
+        start: now(),
+        end,
+        target: 3000,
+        factions: vec![side(100, "Ours", ours), side(200, "Theirs", theirs)],
+    }
+}
\ No newline at end of file
@@ -0,0 +1,328 @@
+//! Terminal front end, for running torndkt on another machine over SSH. Shows
+//! the open profile's monitors from the same store the window uses, and
+//! refreshes them on its own.
+#![warn(missing_docs)]
+
+use std::collections::HashMap;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use futures::executor;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use crate::api::faction::faction_members;
+use crate::bulk;
+use crate::monitors::core::{Monitor, MonitorState};
+use crate::persistence::PersistedData;
+use crate::profile::Profile;
+use crate::refresh::REFRESH_INTERVAL;
+use crate::store::User;
+use crate::util::to_hms;
+use crate::ExampleApp;
+
+/// How long to wait for a key press before redrawing the countdowns
+const TICK: Duration = Duration::from_millis(250);
+
+/// What typing does at the moment
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Mode {
+    /// Keys move around and run commands
+    #[default]
+    Normal,
+
+    /// Typing in targets to add
+    Adding(String),
+
+    /// Typing in the passphrase protecting the API key
+    Passphrase(String),
+}
+
+/// Something the user asked for with the keyboard
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Leave the terminal UI
+    Quit,
+
+    /// Fetch everything again now
+    Refresh,
+
+    /// Add the targets typed in to a group
+    Add(usize, String),
+
+    /// Remove a monitor, by group and position in the group
+    Remove(usize, usize),
+
+    /// Unlock the key with a passphrase
+    Unlock(String),
+
+    /// Carry on without the key
+    SkipPassphrase,
+}
+
+/// Selection and input, kept apart from the app so key handling can be tested
+#[derive(Debug, Clone, Default)]
+pub struct TuiState {
+    /// Row the cursor is on
+    pub selected: usize,
+
+    /// What typing does
+    pub mode: Mode,
+
+    /// Result of the last thing done, or the last error
+    pub message: Option<String>,
+}
+
+impl TuiState {
+    /// Handle a key press. `rows` is the group and position of each monitor on
+    /// screen, from [`rows`].
+    pub fn handle_key(&mut self, key: KeyEvent, rows: &[(usize, usize)]) -> Option<Action> {
+        match &mut self.mode {
+            Mode::Normal => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => Some(Action::Quit),
+                KeyCode::Char('r') => Some(Action::Refresh),
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.selected = self.selected.saturating_sub(1);
+                    None
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.selected = (self.selected + 1).min(rows.len().saturating_sub(1));
+                    None
+                }
+                KeyCode::Home => {
+                    self.selected = 0;
+                    None
+                }
+                KeyCode::End => {
+                    self.selected = rows.len().saturating_sub(1);
+                    None
+                }
+                KeyCode::Char('a') | KeyCode::Insert => {
+                    self.mode = Mode::Adding(String::new());
+                    None
+                }
+                KeyCode::Char('d') | KeyCode::Delete => {
+                    let (group, index) = *rows.get(self.selected)?;
+                    Some(Action::Remove(group, index))
+                }
+                _ => None,
+            },
+            Mode::Adding(input) | Mode::Passphrase(input) => match key.code {
+                KeyCode::Char(x) => {
+                    input.push(x);
+                    None
+                }
+                KeyCode::Backspace => {
+                    input.pop();
+                    None
+                }
+                KeyCode::Enter => {
+                    let input = std::mem::take(input);
+                    let passphrase = matches!(self.mode, Mode::Passphrase(_));
+                    self.mode = Mode::Normal;
+
+                    if passphrase {
+                        Some(Action::Unlock(input))
+                    }
+                    else {
+                        // New targets go in the group the cursor is in
+                        let group = rows.get(self.selected).map_or(0, |x| x.0);
+                        Some(Action::Add(group, input))
+                    }
+                }
+                KeyCode::Esc => {
+                    let passphrase = matches!(self.mode, Mode::Passphrase(_));
+                    self.mode = Mode::Normal;
+                    passphrase.then_some(Action::SkipPassphrase)
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Group and position of each monitor in a profile, in display order
+pub fn rows(profile: &Profile) -> Vec<(usize, usize)> {
+    profile.groups.iter()
+        .enumerate()
+        .flat_map(|(group, x)| (0..x.monitors.len()).map(move |index| (group, index)))
+        .collect()
+}
+
+/// Run the terminal UI until the user quits
+pub fn run(mut app: ExampleApp) -> std::io::Result<()> {
+    // API calls are made the same way as from the window, which needs a
+    // runtime for the HTTP client
+    let runtime = tokio::runtime::Runtime::new()?;
+    let _guard = runtime.enter();
+
+    let mut terminal = ratatui::init();
+    let result = run_loop(&mut app, &mut terminal);
+    ratatui::restore();
+
+    result
+}
+
+/// Draw, handle keys and refresh, until the user quits
+fn run_loop(app: &mut ExampleApp, terminal: &mut DefaultTerminal) -> std::io::Result<()> {
+    let mut state = TuiState::default();
+    let mut last_refresh: Option<DateTime<Utc>> = None;
+
+    loop {
+        if app.passphrase_open && state.mode == Mode::Normal {
+            state.mode = Mode::Passphrase(String::new());
+        }
+
+        // By the app's clock, which runs faster in a sped up replay
+        let now = app.clock.now();
+        let due = last_refresh.is_none_or(|x| now - x >= REFRESH_INTERVAL);
+        if due && !app.passphrase_open {
+            if let Err(x) = app.refresh() {
+                state.message = Some(x.to_string());
+            }
+            last_refresh = Some(now);
+        }
+
+        app.publish();
+
+        let rows = rows(app.profile());
+        state.selected = state.selected.min(rows.len().saturating_sub(1));
+        terminal.draw(|frame| draw(frame, app, &state, &rows))?;
+
+        if !event::poll(TICK)? {
+            continue;
+        }
+
+        let Event::Key(key) = event::read()? else { continue };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match state.handle_key(key, &rows) {
+            None => {}
+            Some(Action::Quit) => return Ok(()),
+            Some(Action::Refresh) => last_refresh = None,
+            Some(Action::Add(group, input)) => {
+                state.message = Some(add(app, group, &input));
+                save(app, &mut state);
+                last_refresh = None;
+            }
+            Some(Action::Remove(group, index)) => {
+                app.profile_mut().groups[group].monitors.remove(index);
+                save(app, &mut state);
+            }
+            Some(Action::Unlock(passphrase)) => {
+                app.passphrase_buf = passphrase;
+                app.submit_passphrase();
+
+                if app.passphrase_open {
+                    state.message = app.key_store_error.clone();
+                }
+            }
+            Some(Action::SkipPassphrase) => app.skip_passphrase(),
+        }
+    }
+}
+
+/// Add the targets in `input` to a group, the same way the bulk add window
+/// does. Returns what happened, for showing to the user.
+fn add(app: &mut ExampleApp, group: usize, input: &str) -> String {
+    let entries = bulk::parse(input);
+
+    let mut factions = HashMap::new();
+    for id in bulk::factions(&entries) {
+        if let Ok(x) = executor::block_on(faction_members(&app.apikey, id)) {
+            factions.insert(id, x.iter().map(|x| User::from_member(id, x)).collect::<Vec<_>>());
+        }
+    }
+
+    let result = bulk::resolve(&entries, &factions, &app.store.users());
+
+    let profile = app.profile_mut();
+    let group = if profile.groups.is_empty() { profile.group_named("Monitors") } else { group };
+    let added = profile.add_users(group, result.ids.iter().copied());
+
+    let mut message = format!("Added {} target(s)", added);
+    let skipped = result.unresolved.len() + result.invalid.len();
+    if skipped > 0 {
+        message += &format!(", couldn't add {}: {}", skipped,
+            result.unresolved.iter().chain(&result.invalid).cloned().collect::<Vec<_>>().join(", "));
+    }
+
+    message
+}
+
+/// Save the monitors, noting any error
+fn save(app: &ExampleApp, state: &mut TuiState) {
+    if let Err(x) = PersistedData::from(app).save(&app.paths.persistence) {
+        state.message = Some(format!("Couldn't save settings: {}", x));
+    }
+}
+
+/// Draw the whole screen
+fn draw(frame: &mut Frame, app: &ExampleApp, state: &TuiState, rows: &[(usize, usize)]) {
+    let [table_area, input_area, help_area] = Layout::vertical([
+        Constraint::Min(3),
+        Constraint::Length(1),
+        Constraint::Length(1),
+    ]).areas(frame.area());
+
+    let profile = app.profile();
+    let store = app.store.as_ref();
+    let clock = app.clock.as_ref();
+
+    let table_rows = rows.iter().map(|&(group, index)| {
+        let summary = profile.groups[group].monitors[index].summary(store, clock);
+
+        // Group names only go on the first row of each group
+        let group_name = if index == 0 { profile.groups[group].name.as_str() } else { "" };
+
+        let (status, color) = match &summary.state {
+            MonitorState::Unknown => ("not fetched".to_string(), Color::DarkGray),
+            MonitorState::Error(x) => (x.to_string(), Color::Yellow),
+            MonitorState::Waiting => ("in hospital".to_string(), Color::Red),
+            MonitorState::Ready => ("out".to_string(), Color::Green),
+            MonitorState::Info => (String::new(), Color::Reset),
+        };
+
+        Row::new([
+            Cell::from(group_name.to_string()).style(Style::new().add_modifier(Modifier::BOLD)),
+            Cell::from(summary.target),
+            Cell::from(summary.name),
+            Cell::from(to_hms(summary.time_left)),
+            Cell::from(status),
+        ]).style(Style::new().fg(color))
+    });
+
+    let table = Table::new(table_rows, [
+        Constraint::Length(16),
+        Constraint::Length(12),
+        Constraint::Length(18),
+        Constraint::Length(10),
+        Constraint::Min(10),
+    ])
+        .header(Row::new(["Group", "Target", "Name", "Time left", "Status"]).style(Style::new().add_modifier(Modifier::UNDERLINED)))
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+        .block(Block::bordered().title(format!(" torndkt: {} ", profile.name)));
+
+    let mut table_state = TableState::default().with_selected((!rows.is_empty()).then_some(state.selected));
+    frame.render_stateful_widget(table, table_area, &mut table_state);
+
+    let input = match &state.mode {
+        Mode::Normal => Line::from(state.message.clone().unwrap_or_default()),
+        Mode::Adding(x) => Line::from(format!("Add (ids, names, links or faction:<id>): {}_", x)),
+        Mode::Passphrase(x) => Line::from(format!("API key passphrase: {}_", "*".repeat(x.chars().count()))),
+    };
+    frame.render_widget(Paragraph::new(input), input_area);
+
+    let help = match (&state.mode, &state.message) {
+        (Mode::Normal, _) => "q quit  ↑/↓ move  a add  d remove  r refresh".to_string(),
+        (Mode::Adding(_), _) => "Enter add  Esc cancel".to_string(),
+        // A wrong passphrase puts the prompt straight back up, so say why
+        (Mode::Passphrase(_), Some(x)) => format!("{}. Enter unlock  Esc skip", x),
+        (Mode::Passphrase(_), None) => "Enter unlock  Esc skip".to_string(),
+    };
+    frame.render_widget(Paragraph::new(help).style(Style::new().fg(Color::DarkGray)), help_area);
+}
\ No newline at end of file
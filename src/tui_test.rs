@@ -0,0 +1,82 @@
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crate::monitors::basic::SimpleHospMonitor;
+use crate::monitors::selection::MonitorList;
+use crate::profile::{MonitorGroup, Profile};
+use crate::tui::{rows, Action, Mode, TuiState};
+
+/// A key press with no modifiers
+fn key(code: KeyCode) -> KeyEvent {
+    KeyEvent::new(code, KeyModifiers::NONE)
+}
+
+/// Type a string, one key at a time
+fn type_in(state: &mut TuiState, input: &str, rows: &[(usize, usize)]) {
+    for x in input.chars() {
+        assert_eq!(state.handle_key(key(KeyCode::Char(x)), rows), None);
+    }
+}
+
+/// Profile with two monitors in one group and one in another
+fn profile() -> Profile {
+    let mut war = MonitorGroup::new("War");
+    war.monitors = vec![MonitorList::Simple(SimpleHospMonitor::new(1)), MonitorList::Simple(SimpleHospMonitor::new(2))];
+
+    let mut chain = MonitorGroup::new("Chain");
+    chain.monitors = vec![MonitorList::Simple(SimpleHospMonitor::new(3))];
+
+    Profile { groups: vec![war, MonitorGroup::new("Empty"), chain], ..Profile::new("Test") }
+}
+
+#[test]
+fn rows_span_groups() {
+    assert_eq!(rows(&profile()), vec![(0, 0), (0, 1), (2, 0)]);
+}
+
+#[test]
+fn navigation_stays_in_bounds() {
+    let rows = rows(&profile());
+    let mut state = TuiState::default();
+
+    state.handle_key(key(KeyCode::Up), &rows);
+    assert_eq!(state.selected, 0);
+
+    for _ in 0..5 {
+        state.handle_key(key(KeyCode::Char('j')), &rows);
+    }
+    assert_eq!(state.selected, 2);
+
+    assert_eq!(state.handle_key(key(KeyCode::Delete), &rows), Some(Action::Remove(2, 0)));
+    assert_eq!(state.handle_key(key(KeyCode::Char('q')), &rows), Some(Action::Quit));
+}
+
+#[test]
+fn adding_goes_in_selected_group() {
+    let rows = rows(&profile());
+    let mut state = TuiState { selected: 2, ..TuiState::default() };
+
+    state.handle_key(key(KeyCode::Char('a')), &rows);
+    type_in(&mut state, "45x", &rows);
+    state.handle_key(key(KeyCode::Backspace), &rows);
+    assert_eq!(state.mode, Mode::Adding("45".to_string()));
+
+    // Keys that are commands in normal mode are just typed
+    type_in(&mut state, "q", &rows);
+    assert_eq!(state.handle_key(key(KeyCode::Enter), &rows), Some(Action::Add(2, "45q".to_string())));
+    assert_eq!(state.mode, Mode::Normal);
+
+    // Cancelling doesn't add anything
+    state.handle_key(key(KeyCode::Char('a')), &rows);
+    assert_eq!(state.handle_key(key(KeyCode::Esc), &rows), None);
+    assert_eq!(state.mode, Mode::Normal);
+}
+
+#[test]
+fn passphrase_prompt() {
+    let mut state = TuiState { mode: Mode::Passphrase(String::new()), ..TuiState::default() };
+
+    type_in(&mut state, "hunter2", &[]);
+    assert_eq!(state.handle_key(key(KeyCode::Enter), &[]), Some(Action::Unlock("hunter2".to_string())));
+
+    state.mode = Mode::Passphrase(String::new());
+    assert_eq!(state.handle_key(key(KeyCode::Esc), &[]), Some(Action::SkipPassphrase));
+}
\ No newline at end of file
@@ -4,4 +4,20 @@ pub fn to_hms(seconds: i64) -> String{
             (seconds % 3600 / 60),
             (seconds % 60)
     )
+}
+
+/// Serialize anything by its `Display` form, for types from `torn_api` that
+/// can only be deserialized
+pub fn serialize_display<T: std::fmt::Display, S: serde::Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+/// Quote a CSV field if it needs it
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    }
+    else {
+        value.to_string()
+    }
 }
\ No newline at end of file
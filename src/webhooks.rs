@@ -0,0 +1,534 @@
+//! Outbound webhooks. When something worth knowing about happens, e.g. a
+//! target leaving hospital, JSON is POSTed to each webhook that wants it, so a
+//! local relay can pass it on to Discord or similar.
+//!
+//! Events are found by comparing each [`Snapshot`] with the one before it.
+//! Each webhook has its own queue, so a slow one doesn't hold up the others,
+//! and is sent to at most once per [`DeliveryConfig::min_interval`].
+#![warn(missing_docs)]
+
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use chrono::{DateTime, TimeDelta, Utc};
+use clap::ValueEnum;
+use reqwest::header::{CONTENT_TYPE, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use crate::api::api::GetInfoError;
+use crate::live::Snapshot;
+use crate::monitors::core::MonitorState;
+
+/// Seconds left on the chain before it counts as about to break, by default
+const DEFAULT_CHAIN_BELOW: u32 = 60;
+
+/// Something that can set a webhook off
+#[derive(Serialize, Deserialize, ValueEnum, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// A monitored target left hospital
+    OutOfHospital,
+
+    /// The chain is about to break
+    ChainLow,
+
+    /// The other side took the lead in the ranked war
+    LeadChange,
+
+    /// The API key stopped working
+    KeyError,
+
+    /// Sent by hand, to check a webhook works
+    Test,
+}
+
+impl WebhookEvent {
+    /// Name used in payloads and on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            WebhookEvent::OutOfHospital => "out_of_hospital",
+            WebhookEvent::ChainLow => "chain_low",
+            WebhookEvent::LeadChange => "lead_change",
+            WebhookEvent::KeyError => "key_error",
+            WebhookEvent::Test => "test",
+        }
+    }
+}
+
+impl Display for WebhookEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Somewhere to send events
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    /// URL to POST to
+    pub url: String,
+
+    /// Events to send. Empty means all of them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<WebhookEvent>,
+
+    /// JSON to send instead of the default payload, with `{name}`
+    /// placeholders for the event's fields. See [`render`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+}
+
+impl Webhook {
+    /// Whether this webhook wants `event`. Everything wants tests.
+    pub fn wants(&self, event: WebhookEvent) -> bool {
+        event == WebhookEvent::Test || self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+/// Saved webhook settings
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WebhookSettings {
+    /// Where to send events
+    #[serde(default)]
+    pub hooks: Vec<Webhook>,
+
+    /// Seconds left on the chain timer that count as about to break
+    #[serde(default = "default_chain_below")]
+    pub chain_below: u32,
+}
+
+/// Serde default for [`WebhookSettings::chain_below`]
+fn default_chain_below() -> u32 {
+    DEFAULT_CHAIN_BELOW
+}
+
+impl Default for WebhookSettings {
+    fn default() -> WebhookSettings {
+        WebhookSettings {
+            hooks: vec![],
+            chain_below: DEFAULT_CHAIN_BELOW,
+        }
+    }
+}
+
+impl WebhookSettings {
+    /// Whether these are the defaults, so they can be left out of the file
+    pub fn is_default(&self) -> bool {
+        *self == WebhookSettings::default()
+    }
+}
+
+/// Something that happened
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// What kind of thing
+    pub kind: WebhookEvent,
+
+    /// When it was noticed, by Torn's clock
+    pub at: DateTime<Utc>,
+
+    /// Description for people
+    pub message: String,
+
+    /// Details, e.g. the target's id and name
+    pub fields: Map<String, Value>,
+}
+
+impl Event {
+    /// Event with no details yet
+    pub fn new(kind: WebhookEvent, at: DateTime<Utc>, message: String) -> Event {
+        Event {
+            kind,
+            at,
+            message,
+            fields: Map::new(),
+        }
+    }
+
+    /// Add a detail
+    pub fn with(mut self, name: &str, value: impl Into<Value>) -> Event {
+        self.fields.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// Everything about the event, as one JSON object. This is the default
+    /// payload, and what templates are filled in from.
+    pub fn to_json(&self) -> Map<String, Value> {
+        let mut json = Map::new();
+        json.insert("event".to_string(), Value::from(self.kind.name()));
+        json.insert("at".to_string(), Value::from(self.at.to_rfc3339()));
+        json.insert("message".to_string(), Value::from(self.message.clone()));
+        json.extend(self.fields.clone());
+        json
+    }
+
+    /// What to send to `hook`
+    pub fn payload(&self, hook: &Webhook) -> Result<Value, WebhookError> {
+        match &hook.template {
+            Some(template) => render(template, self),
+            None => Ok(Value::Object(self.to_json())),
+        }
+    }
+}
+
+/// Fill in a template. `{name}` is replaced with the event's field of that
+/// name, escaped to go inside a JSON string, e.g. `{"content": "{message}"}`.
+/// Braces that aren't around a field name are left alone. The result has to
+/// be valid JSON.
+pub fn render(template: &str, event: &Event) -> Result<Value, WebhookError> {
+    let fields = event.to_json();
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let field = rest[1..].find('}')
+            .map(|end| &rest[1..end + 1])
+            .and_then(|name| fields.get(name).map(|x| (name, x)));
+
+        match field {
+            Some((name, value)) => {
+                output.push_str(&escape(value));
+                rest = &rest[name.len() + 2..];
+            }
+            None => {
+                output.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+
+    serde_json::from_str(&output).map_err(|x| WebhookError::Template(x.to_string()))
+}
+
+/// A value as it goes inside a JSON string: strings without their quotes, and
+/// everything else as JSON
+fn escape(value: &Value) -> String {
+    let json = match value {
+        Value::String(_) => value.to_string(),
+        x => Value::String(x.to_string()).to_string(),
+    };
+
+    json[1..json.len() - 1].to_string()
+}
+
+/// Reasons an event couldn't be sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookError {
+    /// The template didn't make valid JSON
+    Template(String),
+    /// Couldn't reach the webhook
+    Network(String),
+    /// The webhook answered with an error status
+    Status(u16),
+}
+
+impl Display for WebhookError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookError::Template(x) => write!(f, "Template isn't valid JSON once filled in: {}", x),
+            WebhookError::Network(x) => write!(f, "Couldn't reach webhook: {}", x),
+            WebhookError::Status(x) => write!(f, "Webhook answered with status {}", x),
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {}
+
+/// Finds events by comparing snapshots
+#[derive(Debug, Clone, Default)]
+pub struct Watcher {
+    /// Snapshot seen last time
+    last: Option<Snapshot>,
+
+    /// Whether the chain was already about to break
+    chain_low: bool,
+
+    /// Faction that was ahead in the war
+    leader: Option<u32>,
+
+    /// Whether the key was already failing
+    key_error: bool,
+}
+
+impl Watcher {
+    /// Events between the last snapshot and this one, at `now`. The chain
+    /// counts as about to break with `chain_below` left on its timer.
+    pub fn observe(&mut self, snapshot: &Snapshot, now: DateTime<Utc>, chain_below: TimeDelta) -> Vec<Event> {
+        let mut events = vec![];
+
+        if let Some(last) = &self.last {
+            for monitor in &snapshot.monitors {
+                let was_waiting = last.monitors.iter()
+                    .any(|x| x.group == monitor.group && x.target == monitor.target && x.state == MonitorState::Waiting);
+
+                if was_waiting && monitor.state == MonitorState::Ready {
+                    events.push(Event::new(WebhookEvent::OutOfHospital, now, format!("{} [{}] is out of hospital", monitor.name, monitor.target))
+                        .with("target", monitor.target.clone())
+                        .with("name", monitor.name.clone())
+                        .with("group", monitor.group.clone()));
+                }
+            }
+        }
+
+        // Only the start of each spell with the chain running low counts,
+        // rather than every snapshot during it
+        let chain = snapshot.chain.as_ref()
+            .and_then(|x| x.breaks_at.map(|y| (x, (y - now).max(TimeDelta::zero()))))
+            .filter(|(_, left)| *left <= chain_below);
+
+        if let Some((chain, left)) = chain && !self.chain_low {
+            events.push(Event::new(WebhookEvent::ChainLow, now, format!("Chain at {} breaks in {}s", chain.current, left.num_seconds()))
+                .with("chain", chain.current)
+                .with("seconds_left", left.num_seconds()));
+        }
+        self.chain_low = chain.is_some();
+
+        let sides = snapshot.faction_id
+            .and_then(|id| snapshot.war.as_ref()?.sides(id));
+
+        match sides {
+            Some((ours, theirs)) => {
+                let leader = match ours.score.cmp(&theirs.score) {
+                    std::cmp::Ordering::Greater => Some(ours),
+                    std::cmp::Ordering::Less => Some(theirs),
+                    // A tie isn't a change until someone pulls ahead
+                    std::cmp::Ordering::Equal => None,
+                };
+
+                if let Some(leader) = leader {
+                    if self.leader.is_some_and(|x| x != leader.id) {
+                        events.push(Event::new(WebhookEvent::LeadChange, now, format!("{} took the lead, {} - {}", leader.name, ours.score, theirs.score))
+                            .with("leader", leader.name.clone())
+                            .with("leader_id", leader.id)
+                            .with("ours", ours.score)
+                            .with("theirs", theirs.score));
+                    }
+
+                    self.leader = Some(leader.id);
+                }
+            }
+            None => self.leader = None,
+        }
+
+        self.last = Some(snapshot.clone());
+        events
+    }
+
+    /// Event for the result of a refresh at `now`, if the key has just
+    /// started failing
+    pub fn refreshed(&mut self, result: &Result<(), GetInfoError>, now: DateTime<Utc>) -> Option<Event> {
+        let failing = match result {
+            Ok(_) => false,
+            Err(x) if x.is_key_error() => true,
+            // Anything else says nothing about the key
+            Err(_) => return None,
+        };
+
+        let started = failing && !self.key_error;
+        self.key_error = failing;
+
+        let Err(err) = result else { return None };
+        started.then(|| Event::new(WebhookEvent::KeyError, now, format!("API key error: {}. {}", err, err.help_text()))
+            .with("error", err.to_string()))
+    }
+}
+
+/// How events are sent
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeliveryConfig {
+    /// How long a single POST can take
+    pub timeout: Duration,
+
+    /// How many times a failed POST is retried
+    pub retries: u32,
+
+    /// Delay before the first retry. Doubles with each retry after that.
+    pub backoff: Duration,
+
+    /// Shortest time between POSTs to one webhook
+    pub min_interval: Duration,
+
+    /// Events that can wait for a webhook before more are dropped
+    pub queue: usize,
+}
+
+impl Default for DeliveryConfig {
+    fn default() -> DeliveryConfig {
+        DeliveryConfig {
+            timeout: Duration::from_secs(10),
+            retries: 3,
+            backoff: Duration::from_secs(1),
+            min_interval: Duration::from_secs(2),
+            queue: 32,
+        }
+    }
+}
+
+/// How long a `Retry-After` header asks us to wait, up to a minute. Only the
+/// seconds form is understood, and anything that isn't a number is ignored.
+pub fn retry_after(value: &str) -> Option<Duration> {
+    let secs = value.trim().parse::<f64>().ok().filter(|x| x.is_finite())?;
+    Some(Duration::from_secs_f64(secs.clamp(0.0, 60.0)))
+}
+
+/// POST an event to a webhook, retrying network errors, rate limiting and
+/// server errors
+pub async fn deliver(client: &reqwest::Client, hook: &Webhook, event: &Event, config: &DeliveryConfig) -> Result<(), WebhookError> {
+    let payload = event.payload(hook)?;
+    let mut attempt = 0;
+
+    loop {
+        let response = client.post(&hook.url)
+            .timeout(config.timeout)
+            .header(CONTENT_TYPE, "application/json")
+            .body(payload.to_string())
+            .send()
+            .await;
+
+        let (err, retry_after) = match response {
+            Ok(x) if x.status().is_success() => return Ok(()),
+            Ok(x) => {
+                let status = x.status();
+                if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+                    return Err(WebhookError::Status(status.as_u16()));
+                }
+
+                let retry_after = x.headers().get(RETRY_AFTER)
+                    .and_then(|x| retry_after(x.to_str().ok()?));
+
+                (WebhookError::Status(status.as_u16()), retry_after)
+            }
+            Err(x) => (WebhookError::Network(x.to_string()), None),
+        };
+
+        if attempt >= config.retries {
+            return Err(err);
+        }
+
+        let delay = retry_after.unwrap_or(config.backoff.saturating_mul(2u32.saturating_pow(attempt)));
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// The latest problem sending to each webhook, by index, cleared once an
+/// event gets through
+type Problems = Arc<Mutex<Vec<Option<String>>>>;
+
+/// Sends events to webhooks in the background
+#[derive(Debug)]
+pub struct Dispatcher {
+    queues: Vec<(Webhook, mpsc::Sender<Event>)>,
+    problems: Problems,
+}
+
+impl Dispatcher {
+    /// Start sending to `hooks`, on a thread of their own
+    pub fn spawn(hooks: &[Webhook], config: DeliveryConfig) -> std::io::Result<Dispatcher> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        let client = reqwest::Client::new();
+        let problems = Problems::new(Mutex::new(vec![None; hooks.len()]));
+        let mut queues = vec![];
+        let mut workers = vec![];
+
+        for (index, hook) in hooks.iter().enumerate() {
+            let (sender, receiver) = mpsc::channel(config.queue.max(1));
+            queues.push((hook.clone(), sender));
+            workers.push(worker(client.clone(), hook.clone(), receiver, config.clone(), problems.clone(), index));
+        }
+
+        // Runs until the dispatcher is dropped and the queues close
+        std::thread::spawn(move || runtime.block_on(futures::future::join_all(workers)));
+
+        Ok(Dispatcher { queues, problems })
+    }
+
+    /// Queue an event for every webhook that wants it. Events for a webhook
+    /// that's fallen too far behind are dropped.
+    pub fn send(&self, event: &Event) {
+        for (index, (hook, queue)) in self.queues.iter().enumerate() {
+            if hook.wants(event.kind) && queue.try_send(event.clone()).is_err() {
+                let problem = format!("{} is behind, dropped {} event", hook.url, event.kind);
+                eprintln!("Webhook {}", problem);
+                self.problems.lock().unwrap()[index] = Some(problem);
+            }
+        }
+    }
+
+    /// What's stopping events from getting through, if anything
+    pub fn problem(&self) -> Option<String> {
+        let problems = self.problems.lock().unwrap();
+        let problems = problems.iter().flatten().cloned().collect::<Vec<_>>();
+
+        (!problems.is_empty()).then(|| problems.join("; "))
+    }
+}
+
+/// Send one webhook's events in order, no faster than the rate limit
+async fn worker(client: reqwest::Client, hook: Webhook, mut receiver: mpsc::Receiver<Event>, config: DeliveryConfig, problems: Problems, index: usize) {
+    let mut last: Option<Instant> = None;
+
+    while let Some(event) = receiver.recv().await {
+        if let Some(x) = last {
+            tokio::time::sleep_until(x + config.min_interval).await;
+        }
+
+        let problem = deliver(&client, &hook, &event, &config).await.err()
+            .map(|x| format!("couldn't send {} event to {}: {}", event.kind, hook.url, x));
+
+        if let Some(x) = &problem {
+            eprintln!("Webhook {}", x);
+        }
+        problems.lock().unwrap()[index] = problem;
+
+        last = Some(Instant::now());
+    }
+}
+
+/// Watches what the app shows and sends events to the configured webhooks
+#[derive(Debug)]
+pub struct Notifier {
+    watcher: Watcher,
+    dispatcher: Dispatcher,
+    chain_below: TimeDelta,
+}
+
+impl Notifier {
+    /// Start sending events to the webhooks in `settings`
+    pub fn start(settings: &WebhookSettings, config: DeliveryConfig) -> std::io::Result<Notifier> {
+        Ok(Notifier {
+            watcher: Watcher::default(),
+            dispatcher: Dispatcher::spawn(&settings.hooks, config)?,
+            chain_below: TimeDelta::seconds(settings.chain_below as i64),
+        })
+    }
+
+    /// Look at the latest snapshot, taken at `now`
+    pub fn observe(&mut self, snapshot: &Snapshot, now: DateTime<Utc>) {
+        for event in self.watcher.observe(snapshot, now, self.chain_below) {
+            self.dispatcher.send(&event);
+        }
+    }
+
+    /// Why events aren't getting through, if they aren't
+    pub fn problem(&self) -> Option<String> {
+        self.dispatcher.problem()
+    }
+
+    /// Look at the result of a refresh, finished at `now`
+    pub fn refreshed(&mut self, result: &Result<(), GetInfoError>, now: DateTime<Utc>) {
+        if let Some(event) = self.watcher.refreshed(result, now) {
+            self.dispatcher.send(&event);
+        }
+    }
+}
\ No newline at end of file
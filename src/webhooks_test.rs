@@ -0,0 +1,203 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::{Json, Router};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde_json::{json, Value};
+use crate::api::api::GetInfoError;
+use crate::live::{MonitorSnapshot, Snapshot};
+use crate::monitors::core::MonitorState;
+use crate::store::{Chain, RankedWar, WarFaction};
+use crate::webhooks::{deliver, render, retry_after, DeliveryConfig, Dispatcher, Event, Watcher, Webhook, WebhookError, WebhookEvent};
+
+fn now() -> DateTime<Utc> {
+    DateTime::from_timestamp(1_700_000_000, 0).unwrap()
+}
+
+fn hook(url: &str, template: Option<&str>) -> Webhook {
+    Webhook {
+        url: url.to_string(),
+        events: vec![],
+        template: template.map(|x| x.to_string()),
+    }
+}
+
+/// Snapshot with one monitor, a chain breaking at `breaks_in` seconds, and a
+/// war with the given scores
+fn snapshot(state: MonitorState, breaks_in: i64, ours: u32, theirs: u32) -> Snapshot {
+    let side = |id, name: &str, score| WarFaction { id, name: name.to_string(), score, chain: 0 };
+
+    Snapshot {
+        profile: "War".to_string(),
+        faction_id: Some(100),
+        monitors: vec![MonitorSnapshot {
+            group: "Targets".to_string(),
+            target: "1".to_string(),
+            name: "Test".to_string(),
+            state,
+            ends_at: None,
+        }],
+        chain: Some(Chain {
+            current: 120,
+            max: 250,
+            breaks_at: Some(now() + TimeDelta::seconds(breaks_in)),
+            cooldown_until: None,
+            modifier: 1.0,
+        }),
+        war: Some(RankedWar {
+            id: 1,
+            start: now(),
+            end: None,
+            target: 3000,
+            factions: vec![side(100, "Ours", ours), side(200, "Theirs", theirs)],
+        }),
+    }
+}
+
+#[test]
+fn templates() {
+    let event = Event::new(WebhookEvent::OutOfHospital, now(), "\"Test\" is out".to_string())
+        .with("target", "1")
+        .with("chain", 120);
+
+    assert_eq!(render(r#"{"content": "{message} ({target})", "n": {chain}}"#, &event),
+        Ok(json!({"content": "\"Test\" is out (1)", "n": 120})));
+
+    // Unknown placeholders are left as they are
+    assert_eq!(render(r#"{"content": "{nope}"}"#, &event), Ok(json!({"content": "{nope}"})));
+
+    assert!(matches!(render(r#"{"content": {message}}"#, &event), Err(WebhookError::Template(_))));
+
+    let payload = event.payload(&hook("", None)).unwrap();
+    assert_eq!(payload["event"], "out_of_hospital");
+    assert_eq!(payload["at"], "2023-11-14T22:13:20+00:00");
+    assert_eq!(payload["chain"], 120);
+}
+
+#[test]
+fn watcher_events() {
+    let mut watcher = Watcher::default();
+    let below = TimeDelta::seconds(60);
+    let kinds = |x: Vec<Event>| x.iter().map(|x| x.kind).collect::<Vec<_>>();
+
+    assert!(watcher.observe(&snapshot(MonitorState::Waiting, 200, 10, 5), now(), below).is_empty());
+
+    let events = watcher.observe(&snapshot(MonitorState::Ready, 50, 10, 5), now(), below);
+    assert_eq!(kinds(events.clone()), [WebhookEvent::OutOfHospital, WebhookEvent::ChainLow]);
+    assert_eq!(events[0].fields["name"], "Test");
+    assert_eq!(events[1].fields["seconds_left"], 50);
+
+    // Still low, and a tie, so nothing new
+    assert!(watcher.observe(&snapshot(MonitorState::Ready, 40, 10, 10), now(), below).is_empty());
+
+    let events = watcher.observe(&snapshot(MonitorState::Ready, 200, 10, 15), now(), below);
+    assert_eq!(kinds(events.clone()), [WebhookEvent::LeadChange]);
+    assert_eq!(events[0].fields["leader"], "Theirs");
+
+    assert_eq!(kinds(watcher.observe(&snapshot(MonitorState::Ready, 30, 10, 15), now(), below)), [WebhookEvent::ChainLow]);
+
+    // Key errors are only sent when they start
+    assert!(watcher.refreshed(&Err(GetInfoError::Network), now()).is_none());
+    assert!(watcher.refreshed(&Err(GetInfoError::WrongKey), now()).is_some());
+    assert!(watcher.refreshed(&Err(GetInfoError::Other(18)), now()).is_none());
+    assert!(watcher.refreshed(&Ok(()), now()).is_none());
+    assert!(watcher.refreshed(&Err(GetInfoError::WrongKey), now()).is_some());
+}
+
+/// Local listener answering with `statuses` in turn, recording what it's sent
+async fn listener(statuses: &[u16]) -> (String, Arc<Mutex<Vec<Value>>>) {
+    type Received = (Arc<Mutex<Vec<Value>>>, Vec<u16>);
+
+    let received = Arc::new(Mutex::new(vec![]));
+    let app = Router::new()
+        .route("/hook", post(|State((received, statuses)): State<Received>, Json(body): Json<Value>| async move {
+            let mut received = received.lock().unwrap();
+            received.push(body);
+
+            let status = statuses[(received.len() - 1).min(statuses.len() - 1)];
+            StatusCode::from_u16(status).unwrap()
+        }))
+        .with_state((received.clone(), statuses.to_vec()));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    (url, received)
+}
+
+#[tokio::test]
+async fn delivers_with_retries() {
+    let config = DeliveryConfig {
+        backoff: Duration::from_millis(10),
+        retries: 2,
+        ..DeliveryConfig::default()
+    };
+    let client = reqwest::Client::new();
+    let event = Event::new(WebhookEvent::KeyError, now(), "API key error".to_string());
+
+    let (url, received) = listener(&[503, 429, 204]).await;
+    let hook = hook(&url, Some(r#"{"content": "{message}"}"#));
+    assert_eq!(deliver(&client, &hook, &event, &config).await, Ok(()));
+    assert_eq!(*received.lock().unwrap(), vec![json!({"content": "API key error"}); 3]);
+
+    // Client errors aren't retried
+    let (url, received) = listener(&[404]).await;
+    assert_eq!(deliver(&client, &self::hook(&url, None), &event, &config).await, Err(WebhookError::Status(404)));
+    assert_eq!(received.lock().unwrap().len(), 1);
+
+    // Gives up after the retries
+    let (url, received) = listener(&[500]).await;
+    assert_eq!(deliver(&client, &self::hook(&url, None), &event, &config).await, Err(WebhookError::Status(500)));
+    assert_eq!(received.lock().unwrap().len(), 3);
+}
+
+/// The dispatcher's problem once its delivery thread has caught up, waiting
+/// a while for it to be `failing` or not
+async fn settled(dispatcher: &Dispatcher, failing: bool) -> Option<String> {
+    for _ in 0..200 {
+        if dispatcher.problem().is_some() == failing {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    dispatcher.problem()
+}
+
+#[tokio::test]
+async fn delivery_problems_are_kept() {
+    let config = DeliveryConfig {
+        retries: 0,
+        min_interval: Duration::ZERO,
+        ..DeliveryConfig::default()
+    };
+    let event = Event::new(WebhookEvent::KeyError, now(), "API key error".to_string());
+
+    let (url, _) = listener(&[404, 204]).await;
+    let dispatcher = Dispatcher::spawn(&[hook(&url, None)], config).unwrap();
+    assert_eq!(dispatcher.problem(), None);
+
+    dispatcher.send(&event);
+    let problem = settled(&dispatcher, true).await.unwrap();
+    assert!(problem.contains("key_error") && problem.contains("404"), "{}", problem);
+
+    // Cleared once an event gets through
+    dispatcher.send(&event);
+    assert_eq!(settled(&dispatcher, false).await, None);
+}
+
+#[test]
+fn retry_after_is_bounded() {
+    assert_eq!(retry_after("5"), Some(Duration::from_secs(5)));
+    assert_eq!(retry_after(" 0.5 "), Some(Duration::from_millis(500)));
+    assert_eq!(retry_after("-3"), Some(Duration::ZERO));
+    assert_eq!(retry_after("1e20"), Some(Duration::from_secs(60)));
+
+    // Would panic if turned into a Duration
+    assert_eq!(retry_after("NaN"), None);
+    assert_eq!(retry_after("inf"), None);
+    assert_eq!(retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+}
\ No newline at end of file